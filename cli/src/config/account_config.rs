@@ -1,10 +1,24 @@
 use anyhow::{anyhow, Context, Result};
+use cardamom_lib::{
+    carddav::RetryPolicy,
+    csv_format::CsvMapping,
+    phone::{self, Region},
+    vcard_version::VcardVersion,
+};
 use log::{debug, info, trace};
 use std::{env, fs, path::PathBuf};
 
 use crate::{config::*, output::run_cmd};
 
 const CACHED_CARDS_FILE_NAME: &str = ".cache";
+const LOCAL_CACHE_FILE_NAME: &str = ".local";
+const REMOTE_CACHE_FILE_NAME: &str = ".remote";
+const FILE_CACHE_FILE_NAME: &str = ".file";
+const SYNC_JOURNAL_FILE_NAME: &str = ".journal";
+const SYNC_LOCK_FILE_NAME: &str = ".lock";
+const INDEX_FILE_NAME: &str = ".index";
+const TOMBSTONES_FILE_NAME: &str = ".tombstones";
+const DEFAULT_SYNC_CONCURRENCY: usize = 4;
 
 /// Represents the user account.
 #[derive(Debug, Default, Clone)]
@@ -15,6 +29,9 @@ pub struct AccountConfig {
     pub default: bool,
     /// Represents the directory used to synchronize contacts.
     pub sync_dir: PathBuf,
+    /// Represents the single vCard file used to synchronize contacts
+    /// instead of the directory.
+    pub sync_file: Option<PathBuf>,
     /// Represents the CardDAV server host.
     pub host: String,
     /// Represents the CardDAV server port.
@@ -23,6 +40,21 @@ pub struct AccountConfig {
     pub login: String,
     /// Represents the CardDAV password command.
     pub passwd_cmd: String,
+    /// Represents the maximum number of attempts of a CardDAV
+    /// request.
+    pub max_attempts: u32,
+    /// Represents the maximum number of cards uploaded or deleted at
+    /// the same time during a sync.
+    pub sync_concurrency: usize,
+    /// Represents the format of the tables.
+    pub format: Format,
+    /// Represents the mapping of CSV columns used by imports.
+    pub csv_mapping: CsvMapping,
+    /// Represents the vCard version cards are converted to before
+    /// being uploaded.
+    pub vcard_version: Option<VcardVersion>,
+    /// Represents the region national phone numbers belong to.
+    pub phone_region: Option<Region>,
 }
 
 impl<'a> AccountConfig {
@@ -60,14 +92,54 @@ impl<'a> AccountConfig {
         fs::create_dir_all(&sync_dir)
             .with_context(|| format!("cannot create sync dir at {:?}", sync_dir))?;
 
+        let sync_file = account
+            .sync_file
+            .as_ref()
+            .map(|file| {
+                shellexpand::full(file)
+                    .map(|file| PathBuf::from(file.to_string()))
+                    .with_context(|| format!("cannot expand sync file path {:?}", file))
+            })
+            .transpose()?;
+
+        let vcard_version = account
+            .vcard_version
+            .as_ref()
+            .map(|version| {
+                version
+                    .parse()
+                    .with_context(|| format!("cannot parse vcard version of account {:?}", name))
+            })
+            .transpose()?;
+
+        let phone_region = account
+            .phone_region
+            .as_deref()
+            .map(|region| {
+                phone::region(region)
+                    .with_context(|| format!("cannot parse phone region of account {:?}", name))
+            })
+            .transpose()?;
+
         let account_config = AccountConfig {
             name,
             default: account.default.unwrap_or_default(),
             sync_dir,
+            sync_file,
             host: account.host.to_owned(),
             port: account.port.unwrap_or(8843),
             login: account.login.to_owned(),
             passwd_cmd: account.passwd_cmd.to_owned(),
+            max_attempts: account
+                .max_attempts
+                .unwrap_or(RetryPolicy::default().max_attempts),
+            sync_concurrency: account.sync_concurrency.unwrap_or(DEFAULT_SYNC_CONCURRENCY),
+            format: account.format.to_owned().unwrap_or_default(),
+            csv_mapping: CsvMapping {
+                columns: account.csv_mapping.to_owned().unwrap_or_default(),
+            },
+            vcard_version,
+            phone_region,
         };
         trace!("account config: {:?}", account_config);
 
@@ -79,12 +151,62 @@ impl<'a> AccountConfig {
         self.sync_dir.join(CACHED_CARDS_FILE_NAME)
     }
 
+    pub fn local_cache_file_path(&self) -> PathBuf {
+        self.sync_dir.join(LOCAL_CACHE_FILE_NAME)
+    }
+
+    pub fn file_cache_file_path(&self) -> PathBuf {
+        self.sync_dir.join(FILE_CACHE_FILE_NAME)
+    }
+
+    pub fn remote_cache_file_path(&self) -> PathBuf {
+        self.sync_dir.join(REMOTE_CACHE_FILE_NAME)
+    }
+
+    /// Returns the path of the cache of the local store: the one of
+    /// the vCard file if there is one, the one of the sync directory
+    /// otherwise.
+    pub fn local_store_cache_file_path(&self) -> PathBuf {
+        match self.sync_file {
+            Some(_) => self.file_cache_file_path(),
+            None => self.local_cache_file_path(),
+        }
+    }
+
+    /// Returns the path of the cache of the CardDAV server, when it
+    /// is synchronized with the CardDAV server of the given account.
+    pub fn peer_cache_file_path(&self, peer: &str) -> PathBuf {
+        self.sync_dir
+            .join(format!("{}.{}", REMOTE_CACHE_FILE_NAME, peer))
+    }
+
+    /// Returns the path of the journal of the sync with the CardDAV
+    /// server of the given account.
+    pub fn peer_journal_file_path(&self, peer: &str) -> PathBuf {
+        self.sync_dir
+            .join(format!("{}.{}", SYNC_JOURNAL_FILE_NAME, peer))
+    }
+
+    pub fn sync_journal_file_path(&self) -> PathBuf {
+        self.sync_dir.join(SYNC_JOURNAL_FILE_NAME)
+    }
+
+    pub fn sync_lock_file_path(&self) -> PathBuf {
+        self.sync_dir.join(SYNC_LOCK_FILE_NAME)
+    }
+
+    pub fn index_file_path(&self) -> PathBuf {
+        self.sync_dir.join(INDEX_FILE_NAME)
+    }
+
+    pub fn tombstones_file_path(&self) -> PathBuf {
+        self.sync_dir.join(TOMBSTONES_FILE_NAME)
+    }
+
     pub fn passwd(&self) -> Result<String> {
         let passwd = run_cmd(&self.passwd_cmd)
             .with_context(|| format!("cannot run passwd cmd {:?}", self.passwd_cmd))?;
-        let passwd = passwd
-            .trim_end_matches(|c| c == '\r' || c == '\n')
-            .to_owned();
+        let passwd = passwd.trim_end_matches(['\r', '\n']).to_owned();
         Ok(passwd)
     }
 }
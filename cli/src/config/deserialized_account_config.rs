@@ -1,4 +1,7 @@
 use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::config::Format;
 
 /// Represents the user account from the config file.
 #[derive(Debug, Clone, Deserialize)]
@@ -9,6 +12,10 @@ pub struct DeserializedAccountConfig {
     /// Represents the directory used to synchronize
     /// contacts. Defaults to $XDG_DATA_HOME/<account-name>.
     pub sync_dir: Option<String>,
+    /// Represents a single vCard file holding all the contacts, to
+    /// synchronize instead of the directory. Caches are still kept in
+    /// the sync directory.
+    pub sync_file: Option<String>,
     /// Represents the CardDAV server host.
     pub host: String,
     /// Represents the CardDAV server port. Defaults to 8843.
@@ -17,4 +24,25 @@ pub struct DeserializedAccountConfig {
     pub login: String,
     /// Represents the CardDAV password command.
     pub passwd_cmd: String,
+    /// Represents the maximum number of attempts of a CardDAV
+    /// request. Defaults to 4.
+    pub max_attempts: Option<u32>,
+    /// Represents the maximum number of cards uploaded or deleted at
+    /// the same time during a sync. Defaults to 4.
+    pub sync_concurrency: Option<usize>,
+    /// Represents the format of the tables. Defaults to auto, which
+    /// makes tables fit the terminal.
+    pub format: Option<Format>,
+    /// Represents the mapping of CSV columns to contact fields used
+    /// by imports, like `"Mobile" = "phone:cell"`. Columns of Google
+    /// and Outlook exports are known.
+    pub csv_mapping: Option<HashMap<String, String>>,
+    /// Represents the vCard version (3.0 or 4.0) cards are converted
+    /// to before being uploaded, for servers accepting only one
+    /// version. Defaults to none, which uploads cards as they are.
+    pub vcard_version: Option<String>,
+    /// Represents the region (ISO 3166 country code, like `FR`)
+    /// national phone numbers belong to, used to read them in E.164.
+    /// Defaults to none, which leaves national numbers as they are.
+    pub phone_region: Option<String>,
 }
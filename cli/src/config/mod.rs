@@ -7,5 +7,8 @@ pub use deserialized_config::*;
 pub mod deserialized_account_config;
 pub use deserialized_account_config::*;
 
+pub mod format;
+pub use format::*;
+
 pub mod account_config;
 pub use account_config::*;
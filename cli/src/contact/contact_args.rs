@@ -3,15 +3,119 @@
 //! This module provides subcommands, arguments and a command matcher
 //! related to the contact.
 
-use anyhow::Result;
-use clap::{self, App, ArgMatches, SubCommand};
+use anyhow::{anyhow, Result};
+use cardamom_lib::{
+    export::ExportFormat,
+    import::ImportFormat,
+    phone::{self, Region},
+    vcard_version::VcardVersion,
+};
+use clap::{self, App, Arg, ArgMatches, SubCommand};
 use log::{debug, info};
+use std::{convert::TryFrom, path::PathBuf};
+
+use crate::contact::contact_template::ContactTemplate;
+
+type Wait = bool;
+type AccountName = String;
+type Limit = Option<usize>;
+type Id = String;
+type Raw = bool;
+type Query = String;
+type Upload = bool;
+type Yes = bool;
+type Remote = bool;
+type Mappings = Vec<(String, String)>;
+type File = Option<PathBuf>;
+type Split = bool;
+type Fix = bool;
+type MinScore = u8;
+type List = bool;
+
+/// Represents the contact field used to sort contacts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Name,
+    Email,
+    Phone,
+    Org,
+}
+
+impl TryFrom<&str> for SortBy {
+    type Error = anyhow::Error;
+
+    fn try_from(field: &str) -> Result<Self> {
+        match field {
+            "name" => Ok(Self::Name),
+            "email" => Ok(Self::Email),
+            "phone" => Ok(Self::Phone),
+            "org" => Ok(Self::Org),
+            field => Err(anyhow!("cannot sort contacts by unknown field {:?}", field)),
+        }
+    }
+}
+
+/// Represents what to do with an imported contact looking like an
+/// existing one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OnDuplicate {
+    #[default]
+    Ask,
+    Skip,
+    Add,
+}
+
+impl TryFrom<&str> for OnDuplicate {
+    type Error = anyhow::Error;
+
+    fn try_from(action: &str) -> Result<Self> {
+        match action {
+            "ask" => Ok(Self::Ask),
+            "skip" => Ok(Self::Skip),
+            "add" => Ok(Self::Add),
+            action => Err(anyhow!("cannot handle duplicates with {:?}", action)),
+        }
+    }
+}
 
 /// Represents the contact commands.
 #[derive(Debug, PartialEq, Eq)]
 pub enum Cmd {
     /// Represents the sync contact command.
-    Sync,
+    Sync(Wait),
+    /// Represents the sync contact command between two accounts.
+    SyncAccounts(AccountName, AccountName, Wait),
+    /// Represents the list contacts command.
+    List(SortBy, Limit),
+    /// Represents the show contact command.
+    Show(Id, Raw),
+    /// Represents the search contacts command.
+    Search(Query, SortBy, Limit),
+    /// Represents the add contact command. An empty template means
+    /// that the contact is written in the editor.
+    Add(ContactTemplate, Upload),
+    /// Represents the edit contact command.
+    Edit(Id, Raw, Upload),
+    /// Represents the delete contact command.
+    Delete(Id, Yes, Remote),
+    /// Represents the import contacts command. Without format, the
+    /// format is guessed from the file extension.
+    Import(PathBuf, Option<ImportFormat>, Mappings, OnDuplicate),
+    /// Represents the export contacts command. Without file, the
+    /// contacts are written to the standard output.
+    Export(ExportFormat, Option<Query>, File, Split),
+    /// Represents the convert cards command. Without path, the cards
+    /// are read from the standard input.
+    Convert(VcardVersion, Option<PathBuf>),
+    /// Represents the lint contacts command.
+    Lint(Fix),
+    /// Represents the dedupe contacts command. The listed duplicates
+    /// are not merged.
+    Dedupe(MinScore, List),
+    /// Represents the normalize contacts command. Without region, the
+    /// region of the account is used.
+    Normalize(Option<Region>),
 }
 
 /// Represents the contact command matcher.
@@ -20,7 +124,136 @@ pub fn matches(m: &ArgMatches) -> Result<Option<Cmd>> {
 
     let cmd = if let Some(m) = m.subcommand_matches("sync") {
         debug!("sync command matched");
-        Some(Cmd::Sync)
+        let wait = m.is_present("wait");
+        debug!("wait: {}", wait);
+        match (m.value_of("from"), m.value_of("to")) {
+            (Some(from), Some(to)) => {
+                debug!("from account: {:?}, to account: {:?}", from, to);
+                Some(Cmd::SyncAccounts(from.to_owned(), to.to_owned(), wait))
+            }
+            _ => Some(Cmd::Sync(wait)),
+        }
+    } else if let Some(m) = m.subcommand_matches("list") {
+        debug!("list command matched");
+        let (sort, limit) = list_matches(m)?;
+        Some(Cmd::List(sort, limit))
+    } else if let Some(m) = m.subcommand_matches("search") {
+        debug!("search command matched");
+        let query = m
+            .values_of("query")
+            .unwrap_or_default()
+            .collect::<Vec<_>>()
+            .join(" ");
+        debug!("query: {:?}", query);
+        let (sort, limit) = list_matches(m)?;
+        Some(Cmd::Search(query, sort, limit))
+    } else if let Some(m) = m.subcommand_matches("add") {
+        debug!("add command matched");
+        let values = |name| {
+            m.values_of(name)
+                .unwrap_or_default()
+                .map(String::from)
+                .collect()
+        };
+        let template = ContactTemplate {
+            name: m.value_of("name").unwrap_or_default().to_owned(),
+            emails: values("email"),
+            phones: values("phone"),
+            org: m.value_of("org").unwrap_or_default().to_owned(),
+            ..ContactTemplate::default()
+        };
+        debug!("template: {:?}", template);
+        let upload = m.is_present("upload");
+        debug!("upload: {}", upload);
+        Some(Cmd::Add(template, upload))
+    } else if let Some(m) = m.subcommand_matches("show") {
+        debug!("show command matched");
+        let id = m.value_of("id").unwrap_or_default().to_owned();
+        debug!("id: {:?}", id);
+        let raw = m.is_present("raw");
+        debug!("raw: {}", raw);
+        Some(Cmd::Show(id, raw))
+    } else if let Some(m) = m.subcommand_matches("edit") {
+        debug!("edit command matched");
+        let id = m.value_of("id").unwrap_or_default().to_owned();
+        debug!("id: {:?}", id);
+        let raw = m.is_present("raw");
+        debug!("raw: {}", raw);
+        let upload = m.is_present("upload");
+        debug!("upload: {}", upload);
+        Some(Cmd::Edit(id, raw, upload))
+    } else if let Some(m) = m.subcommand_matches("delete") {
+        debug!("delete command matched");
+        let id = m.value_of("id").unwrap_or_default().to_owned();
+        debug!("id: {:?}", id);
+        let yes = m.is_present("yes");
+        debug!("yes: {}", yes);
+        let remote = m.is_present("remote");
+        debug!("remote: {}", remote);
+        Some(Cmd::Delete(id, yes, remote))
+    } else if let Some(m) = m.subcommand_matches("import") {
+        debug!("import command matched");
+        let path = PathBuf::from(m.value_of("file").unwrap_or_default());
+        debug!("path: {:?}", path);
+        let format = m.value_of("format").map(str::parse).transpose()?;
+        debug!("format: {:?}", format);
+        let mappings = m
+            .values_of("map")
+            .unwrap_or_default()
+            .map(|mapping| {
+                mapping
+                    .split_once('=')
+                    .map(|(column, target)| (column.to_owned(), target.to_owned()))
+                    .ok_or_else(|| anyhow!("cannot parse column mapping {:?}", mapping))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        debug!("mappings: {:?}", mappings);
+        let on_duplicate = m
+            .value_of("on-duplicate")
+            .map(OnDuplicate::try_from)
+            .transpose()?
+            .unwrap_or_default();
+        debug!("on duplicate: {:?}", on_duplicate);
+        Some(Cmd::Import(path, format, mappings, on_duplicate))
+    } else if let Some(m) = m.subcommand_matches("export") {
+        debug!("export command matched");
+        let format = m.value_of("format").unwrap_or("vcf").parse()?;
+        debug!("format: {:?}", format);
+        let query = m.value_of("query").map(String::from);
+        debug!("query: {:?}", query);
+        let file = m.value_of("file").map(PathBuf::from);
+        debug!("file: {:?}", file);
+        let split = m.is_present("split");
+        debug!("split: {}", split);
+        Some(Cmd::Export(format, query, file, split))
+    } else if let Some(m) = m.subcommand_matches("convert") {
+        debug!("convert command matched");
+        let version = m.value_of("to").unwrap_or_default().parse()?;
+        debug!("version: {:?}", version);
+        let path = m.value_of("file").map(PathBuf::from);
+        debug!("path: {:?}", path);
+        Some(Cmd::Convert(version, path))
+    } else if let Some(m) = m.subcommand_matches("lint") {
+        debug!("lint command matched");
+        let fix = m.is_present("fix");
+        debug!("fix: {}", fix);
+        Some(Cmd::Lint(fix))
+    } else if let Some(m) = m.subcommand_matches("dedupe") {
+        debug!("dedupe command matched");
+        let min_score = m
+            .value_of("min-score")
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| anyhow!("cannot parse min score: expected a number from 0 to 100"))?;
+        debug!("min score: {}", min_score);
+        let list = m.is_present("list");
+        debug!("list: {}", list);
+        Some(Cmd::Dedupe(min_score, list))
+    } else if let Some(m) = m.subcommand_matches("normalize") {
+        debug!("normalize command matched");
+        let region = m.value_of("region").map(phone::region).transpose()?;
+        debug!("region: {:?}", region);
+        Some(Cmd::Normalize(region))
     } else {
         None
     };
@@ -29,11 +262,367 @@ pub fn matches(m: &ArgMatches) -> Result<Option<Cmd>> {
     Ok(cmd)
 }
 
+/// Matches the list arguments.
+fn list_matches(m: &ArgMatches) -> Result<(SortBy, Limit)> {
+    let sort = m
+        .value_of("sort")
+        .map(SortBy::try_from)
+        .transpose()?
+        .unwrap_or_default();
+    debug!("sort: {:?}", sort);
+    let limit = m
+        .value_of("limit")
+        .map(|limit| {
+            limit
+                .parse()
+                .map_err(|_| anyhow!("cannot parse limit {:?}", limit))
+        })
+        .transpose()?;
+    debug!("limit: {:?}", limit);
+    Ok((sort, limit))
+}
+
 /// Represents the contact subcommands.
 pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
-    vec![SubCommand::with_name("sync")
-        .aliases(&["synchronize", "synchro", "syn", "s"])
-        .about("Synchronizes contacts")]
+    vec![
+        SubCommand::with_name("sync")
+            .aliases(&["synchronize", "synchro", "syn", "s"])
+            .about("Synchronizes contacts")
+            .args(&wait_args())
+            .args(&accounts_args()),
+        SubCommand::with_name("list")
+            .aliases(&["lst", "l"])
+            .about("Lists contacts")
+            .args(&list_args()),
+        SubCommand::with_name("search")
+            .aliases(&["query", "find", "q"])
+            .about("Searches contacts")
+            .long_about(
+                "Searches contacts matching all the given terms. A term is either \
+                 free text matched against all the contact properties, or scoped \
+                 to a field: name, email, phone, org, address, note, category or \
+                 url (for example `name:alice email:@corp.com org:\"ACME Inc\"`). \
+                 Matching is case and accent insensitive.",
+            )
+            .arg(
+                Arg::with_name("query")
+                    .help("Specifies the search query")
+                    .value_name("QUERY")
+                    .multiple(true)
+                    .required(true),
+            )
+            .args(&list_args()),
+        SubCommand::with_name("add")
+            .aliases(&["new", "create", "a"])
+            .about("Adds a contact")
+            .long_about(
+                "Adds a contact to the local store. Without any contact flag, \
+                 the contact is written in the editor from a template.",
+            )
+            .args(&add_args()),
+        SubCommand::with_name("show")
+            .aliases(&["read", "sh"])
+            .about("Shows a contact")
+            .arg(id_arg())
+            .arg(
+                Arg::with_name("raw")
+                    .long("raw")
+                    .short("r")
+                    .help("Shows the original vCard instead"),
+            ),
+        SubCommand::with_name("edit")
+            .aliases(&["modify", "e"])
+            .about("Edits a contact")
+            .long_about(
+                "Edits a contact of the local store in the editor. The editor is \
+                 opened again as long as the contact is not valid. Properties \
+                 missing from the form are kept as they are.",
+            )
+            .arg(id_arg())
+            .arg(
+                Arg::with_name("raw")
+                    .long("raw")
+                    .short("r")
+                    .help("Edits the original vCard instead"),
+            )
+            .arg(upload_arg()),
+        SubCommand::with_name("delete")
+            .aliases(&["remove", "rm", "d"])
+            .about("Deletes a contact")
+            .long_about(
+                "Deletes a contact from the local store. The deletion is applied \
+                 to the CardDAV server by the next sync, unless --remote is given.",
+            )
+            .arg(id_arg())
+            .arg(
+                Arg::with_name("yes")
+                    .long("yes")
+                    .short("y")
+                    .help("Deletes the contact without confirmation"),
+            )
+            .arg(
+                Arg::with_name("remote")
+                    .long("remote")
+                    .short("r")
+                    .help("Deletes the contact from the CardDAV server right away"),
+            ),
+        SubCommand::with_name("import")
+            .aliases(&["imp", "i"])
+            .about("Imports contacts from a file")
+            .long_about(
+                "Imports contacts from a vCard, CSV or LDIF file into the local \
+                 store. The columns of Google and Outlook CSV files are known, \
+                 other columns can be mapped to a contact field with --map or \
+                 with the csv-mapping table of the account config.",
+            )
+            .arg(
+                Arg::with_name("file")
+                    .help("Specifies the file to import")
+                    .value_name("FILE")
+                    .required(true),
+            )
+            .args(&import_args()),
+        SubCommand::with_name("export")
+            .aliases(&["exp", "x"])
+            .about("Exports contacts to a file")
+            .long_about(
+                "Exports contacts of the local store to a vCard, CSV, LDIF, JSON \
+                 or jCard (RFC 7095) file, or to the standard output. Contacts \
+                 can be filtered with a search query (see the search command).",
+            )
+            .args(&export_args()),
+        SubCommand::with_name("convert")
+            .aliases(&["conv", "c"])
+            .about("Converts vCards to another version")
+            .long_about(
+                "Converts the cards of a vCard file (or of the standard input) \
+                 between the 3.0 and 4.0 versions, then writes them to the \
+                 standard output.",
+            )
+            .arg(
+                Arg::with_name("to")
+                    .long("to")
+                    .short("t")
+                    .help("Defines the version to convert the cards to")
+                    .value_name("VERSION")
+                    .possible_values(&["3.0", "4.0"])
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("file")
+                    .help("Specifies the vCard file to convert")
+                    .value_name("FILE"),
+            ),
+        SubCommand::with_name("lint")
+            .aliases(&["check", "validate"])
+            .about("Checks the local contacts")
+            .long_about(
+                "Checks the cards of the local store for the problems making \
+                 CardDAV servers reject them: missing properties, bad line \
+                 folding, invalid dates, unknown parameters, encoding issues, \
+                 duplicate UIDs and oversized photos. The same checks run \
+                 before each upload.",
+            )
+            .arg(
+                Arg::with_name("fix")
+                    .long("fix")
+                    .help("Repairs the problems which can be fixed safely"),
+            ),
+        SubCommand::with_name("dedupe")
+            .aliases(&["dedup", "merge"])
+            .about("Merges duplicate contacts")
+            .long_about(
+                "Finds the local contacts looking like the same person (same \
+                 name, email address or phone number), scored from 0 to 100, \
+                 then asks for each pair which contact to keep. The other \
+                 contact is merged into the kept one, which keeps its UID and \
+                 gets the group memberships of the merged contact. The merged \
+                 contact is deleted from the server at the next sync.",
+            )
+            .arg(
+                Arg::with_name("min-score")
+                    .long("min-score")
+                    .short("s")
+                    .help("Ignores the duplicates scored below this score")
+                    .value_name("SCORE")
+                    .default_value("0"),
+            )
+            .arg(
+                Arg::with_name("list")
+                    .long("list")
+                    .short("l")
+                    .help("Lists the duplicates without merging them"),
+            ),
+        SubCommand::with_name("normalize")
+            .aliases(&["norm"])
+            .about("Normalizes the phone numbers of the local contacts")
+            .long_about(
+                "Rewrites the phone numbers of the local contacts in the E.164 \
+                 format (+33612345678), so that caller ID lookups match them. \
+                 National numbers are read in the phone region of the account. \
+                 Numbers which cannot be read, or which have an extension, are \
+                 left as they are.",
+            )
+            .arg(
+                Arg::with_name("region")
+                    .long("region")
+                    .short("r")
+                    .help("Overrides the phone region of the account (like FR)")
+                    .value_name("REGION"),
+            ),
+    ]
+}
+
+/// Represents the export arguments.
+fn export_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("format")
+            .long("format")
+            .short("f")
+            .help("Defines the format of the exported contacts")
+            .value_name("FORMAT")
+            .possible_values(&["vcf", "csv", "ldif", "json", "jcard"])
+            .default_value("vcf"),
+        Arg::with_name("query")
+            .long("query")
+            .short("q")
+            .help("Exports only the contacts matching the given search query")
+            .value_name("QUERY"),
+        Arg::with_name("file")
+            .long("file")
+            .short("F")
+            .help("Writes the contacts to the given file instead of the standard output")
+            .value_name("PATH"),
+        Arg::with_name("split")
+            .long("split")
+            .help("Writes one file per contact into the given directory")
+            .requires("file"),
+    ]
+}
+
+/// Represents the import arguments.
+fn import_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("format")
+            .long("format")
+            .short("f")
+            .help("Defines the format of the file, guessed from its extension by default")
+            .value_name("FORMAT")
+            .possible_values(&["vcf", "csv", "ldif"]),
+        Arg::with_name("map")
+            .long("map")
+            .short("m")
+            .help("Maps a CSV column to a contact field (Mobile=phone:cell)")
+            .value_name("COLUMN=FIELD")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("on-duplicate")
+            .long("on-duplicate")
+            .short("d")
+            .help("Defines what to do with contacts looking like existing ones")
+            .value_name("ACTION")
+            .possible_values(&["ask", "skip", "add"])
+            .default_value("ask"),
+    ]
+}
+
+/// Represents the add arguments.
+fn add_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("name")
+            .long("name")
+            .short("n")
+            .help("Defines the name of the contact")
+            .value_name("NAME"),
+        Arg::with_name("email")
+            .long("email")
+            .short("e")
+            .help("Adds an email address, optionally typed (work:alice@corp.com)")
+            .value_name("[TYPE:]EMAIL")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("phone")
+            .long("phone")
+            .short("p")
+            .help("Adds a phone number, optionally typed (cell:+33 6 00 00 00 00)")
+            .value_name("[TYPE:]PHONE")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("org")
+            .long("org")
+            .help("Defines the organization of the contact")
+            .value_name("ORG"),
+        upload_arg(),
+    ]
+}
+
+/// Represents the upload argument.
+fn upload_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("upload")
+        .long("upload")
+        .short("u")
+        .help("Uploads the contact to the CardDAV server right away")
+}
+
+/// Represents the contact id argument. A unique prefix of the id is
+/// enough to find the contact.
+fn id_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("id")
+        .help("Specifies the contact id, or a unique prefix of it")
+        .value_name("ID")
+        .required(true)
+}
+
+/// Represents the list arguments.
+fn list_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("sort")
+            .long("sort")
+            .short("s")
+            .help("Sorts contacts by the given field")
+            .value_name("FIELD")
+            .possible_values(&["name", "email", "phone", "org"])
+            .default_value("name"),
+        Arg::with_name("limit")
+            .long("limit")
+            .short("l")
+            .help("Lists at most the given number of contacts")
+            .value_name("INT"),
+    ]
+}
+
+/// Represents the accounts arguments. They allow to synchronize two
+/// CardDAV accounts directly, for example to migrate contacts from a
+/// provider to another.
+fn accounts_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("from")
+            .long("from")
+            .help("Synchronizes the CardDAV server of the given account...")
+            .value_name("ACCOUNT")
+            .requires("to"),
+        Arg::with_name("to")
+            .long("to")
+            .help("...with the CardDAV server of the given account")
+            .value_name("ACCOUNT")
+            .requires("from"),
+    ]
+}
+
+/// Represents the sync lock arguments. They define what to do when
+/// another sync of the same account is already running.
+fn wait_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("wait")
+            .long("wait")
+            .short("w")
+            .help("Waits for the running sync to terminate")
+            .overrides_with("no-wait"),
+        Arg::with_name("no-wait")
+            .long("no-wait")
+            .help("Fails if a sync is already running (default)")
+            .overrides_with("wait"),
+    ]
 }
 
 #[cfg(test)]
@@ -46,9 +635,221 @@ mod tests {
     fn it_should_match_cmds() {
         let arg = App::new("cardamom")
             .subcommands(subcmds())
-            .get_matches_from(&["cardamom", "sync"]);
+            .get_matches_from(["cardamom", "sync"]);
+
+        assert_eq!(Some(Cmd::Sync(false)), matches(&arg).unwrap());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "sync", "--wait"]);
+
+        assert_eq!(Some(Cmd::Sync(true)), matches(&arg).unwrap());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "sync", "--wait", "--no-wait"]);
+
+        assert_eq!(Some(Cmd::Sync(false)), matches(&arg).unwrap());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "sync", "--from", "old", "--to", "new"]);
+
+        assert_eq!(
+            Some(Cmd::SyncAccounts("old".into(), "new".into(), false)),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from_safe(["cardamom", "sync", "--from", "old"]);
+
+        assert!(arg.is_err());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "list"]);
+
+        assert_eq!(Some(Cmd::List(SortBy::Name, None)), matches(&arg).unwrap());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "list", "--sort", "org", "-l", "10"]);
+
+        assert_eq!(
+            Some(Cmd::List(SortBy::Org, Some(10))),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "list", "--limit", "ten"]);
+
+        assert!(matches(&arg).is_err());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "show", "3f2a", "--raw"]);
+
+        assert_eq!(Some(Cmd::Show("3f2a".into(), true)), matches(&arg).unwrap());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "edit", "3f2a", "-u"]);
+
+        assert_eq!(
+            Some(Cmd::Edit("3f2a".into(), false, true)),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "rm", "3f2a", "--yes"]);
+
+        assert_eq!(
+            Some(Cmd::Delete("3f2a".into(), true, false)),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from([
+                "cardamom",
+                "import",
+                "contacts.txt",
+                "-f",
+                "csv",
+                "--map",
+                "Mobile=phone:cell",
+                "--on-duplicate",
+                "skip",
+            ]);
+
+        assert_eq!(
+            Some(Cmd::Import(
+                "contacts.txt".into(),
+                Some(ImportFormat::Csv),
+                vec![("Mobile".into(), "phone:cell".into())],
+                OnDuplicate::Skip
+            )),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "export"]);
+
+        assert_eq!(
+            Some(Cmd::Export(ExportFormat::Vcf, None, None, false)),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from([
+                "cardamom", "export", "-f", "jcard", "-q", "org:acme", "-F", "out", "--split",
+            ]);
+
+        assert_eq!(
+            Some(Cmd::Export(
+                ExportFormat::Jcard,
+                Some("org:acme".into()),
+                Some("out".into()),
+                true
+            )),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from_safe(["cardamom", "export", "--split"]);
+
+        assert!(arg.is_err());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "convert", "--to", "3.0", "contacts.vcf"]);
+
+        assert_eq!(
+            Some(Cmd::Convert(VcardVersion::V3, Some("contacts.vcf".into()))),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "check", "--fix"]);
+
+        assert_eq!(Some(Cmd::Lint(true)), matches(&arg).unwrap());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "dedupe", "-s", "60", "--list"]);
+
+        assert_eq!(Some(Cmd::Dedupe(60, true)), matches(&arg).unwrap());
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "norm", "-r", "fr"]);
+
+        assert_eq!(
+            Some(Cmd::Normalize(Some(Region::FR))),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from([
+                "cardamom",
+                "add",
+                "--name",
+                "Alice",
+                "-e",
+                "work:alice@corp.com",
+                "-e",
+                "alice@home.net",
+                "--upload",
+            ]);
 
-        assert_eq!(Some(Cmd::Sync), matches(&arg).unwrap());
+        assert_eq!(
+            Some(Cmd::Add(
+                ContactTemplate {
+                    name: "Alice".into(),
+                    emails: vec!["work:alice@corp.com".into(), "alice@home.net".into()],
+                    ..ContactTemplate::default()
+                },
+                true
+            )),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from(["cardamom", "add"]);
+
+        assert_eq!(
+            Some(Cmd::Add(ContactTemplate::default(), false)),
+            matches(&arg).unwrap()
+        );
+
+        let arg = App::new("cardamom")
+            .subcommands(subcmds())
+            .get_matches_from([
+                "cardamom",
+                "search",
+                "name:alice",
+                "org:\"ACME Inc\"",
+                "--limit",
+                "1",
+            ]);
+
+        assert_eq!(
+            Some(Cmd::Search(
+                "name:alice org:\"ACME Inc\"".into(),
+                SortBy::Name,
+                Some(1)
+            )),
+            matches(&arg).unwrap()
+        );
     }
 
     #[test]
@@ -57,7 +858,7 @@ mod tests {
             ($alias:expr) => {
                 App::new("cardamom")
                     .subcommands(subcmds())
-                    .get_matches_from(&["cardamom", $alias])
+                    .get_matches_from(["cardamom", $alias])
                     .subcommand_name()
             };
         }
@@ -67,5 +868,9 @@ mod tests {
         assert_eq!(Some("sync"), get_matches_from!["sync"]);
         assert_eq!(Some("sync"), get_matches_from!["syn"]);
         assert_eq!(Some("sync"), get_matches_from!["s"]);
+
+        assert_eq!(Some("list"), get_matches_from!["list"]);
+        assert_eq!(Some("list"), get_matches_from!["lst"]);
+        assert_eq!(Some("list"), get_matches_from!["l"]);
     }
 }
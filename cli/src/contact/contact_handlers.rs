@@ -2,31 +2,1072 @@
 //!
 //! This module contains all handlers related to the contact.
 
-use anyhow::{Context, Result};
-use log::{info, trace};
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, Utc};
+use log::{debug, info, trace, warn};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
 
-use cardamom_lib::{cache::CachedCards, local::LocalCards, remote::RemoteCards, sync::Patch};
+use cardamom_lib::{
+    backend::{Backend, Replica},
+    cache::CachedCards,
+    card::{href_from_id, Card},
+    carddav::RetryPolicy,
+    contact::Contact,
+    dedupe::{find_duplicates, is_group, merge, move_membership, Duplicate},
+    error::CardamomError,
+    export::{export as export_cards, export_one, ExportFormat},
+    import::{import as import_cards, ImportFormat},
+    index::Index,
+    journal::Journal,
+    lint::{self, lint_cards, Severity},
+    local::LocalCards,
+    lock::SyncLock,
+    phone::{normalize_phones, Region},
+    remote::RemoteCards,
+    report::SyncReport,
+    search::Query,
+    sync::Patch,
+    tombstone::Tombstones,
+    vcard,
+    vcard_version::{convert as convert_card, VcardVersion},
+    vcf_file::VcfFile,
+};
 
-use crate::{config::AccountConfig, output::PrinterService};
+use crate::{
+    config::AccountConfig,
+    contact::{
+        contact_args::{OnDuplicate, SortBy},
+        contact_template::ContactTemplate,
+        dedupe_report::DedupeReport,
+        sync_report::AccountsSyncReport,
+    },
+    output::{self, PrintTableOpts, PrinterService},
+};
 
 /// Synchronizes contacts.
-pub fn sync<'a, P: PrinterService>(config: &AccountConfig, printer: &mut P) -> Result<()> {
+pub fn sync<P: PrinterService>(wait: bool, config: &AccountConfig, printer: &mut P) -> Result<()> {
     info!(">> sync contacts handler");
 
-    let local = LocalCards::new(config.sync_dir.clone())?;
+    let _lock = lock(config, wait)?;
+    let mut remote = Replica::new(remote_cards(config)?, config.remote_cache_file_path())?;
+    let report = match config.sync_file.as_ref() {
+        Some(path) => {
+            let mut local =
+                Replica::new(VcfFile::new(path.clone()), config.file_cache_file_path())?;
+            apply_local(&mut local, &mut remote, config)?
+        }
+        None => {
+            let mut local = Replica::new(
+                LocalCards::new(config.sync_dir.clone()),
+                config.local_cache_file_path(),
+            )?;
+            apply_local(&mut local, &mut remote, config)?
+        }
+    };
+    let failures = report.failures.len();
+    printer.print_struct(report)?;
+
+    info!("<< sync contacts handler");
+    check_failures(failures)
+}
+
+/// Synchronizes contacts of the CardDAV servers of two accounts.
+pub fn sync_accounts<P: PrinterService>(
+    wait: bool,
+    from: &AccountConfig,
+    to: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> sync accounts contacts handler");
+
+    if from.name == to.name {
+        return Err(anyhow!(
+            "cannot synchronize account {:?} with itself",
+            from.name
+        ));
+    }
+
+    let _from_lock = lock(from, wait)?;
+    let _to_lock = if to.sync_lock_file_path() != from.sync_lock_file_path() {
+        Some(lock(to, wait)?)
+    } else {
+        None
+    };
+
+    let mut left = Replica::new(remote_cards(from)?, from.peer_cache_file_path(&to.name))?;
+    let mut right = Replica::new(remote_cards(to)?, to.peer_cache_file_path(&from.name))?;
+
+    let report = apply(&mut left, &mut right, from.peer_journal_file_path(&to.name))?;
+    let failures = report.failures.len();
+    printer.print_struct(AccountsSyncReport {
+        from: from.name.clone(),
+        to: to.name.clone(),
+        report,
+    })?;
+
+    info!("<< sync accounts contacts handler");
+    check_failures(failures)
+}
+
+/// Lists contacts of the local store.
+pub fn list<P: PrinterService>(
+    sort: SortBy,
+    limit: Option<usize>,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> list contacts handler");
+
+    let contacts = index(config)?.contacts().cloned().collect();
+    print_contacts(contacts, sort, limit, config, printer)?;
+
+    info!("<< list contacts handler");
+    Ok(())
+}
+
+/// Searches contacts of the local store.
+pub fn search<P: PrinterService>(
+    query: &str,
+    sort: SortBy,
+    limit: Option<usize>,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> search contacts handler");
+
+    let query = query.parse::<Query>()?.with_region(config.phone_region);
+    trace!("query: {:?}", query);
+    let contacts = index(config)?.search(&query).cloned().collect();
+    print_contacts(contacts, sort, limit, config, printer)?;
+
+    info!("<< search contacts handler");
+    Ok(())
+}
+
+/// Sorts and truncates the given contacts, then prints them as a
+/// table.
+fn print_contacts<P: PrinterService>(
+    mut contacts: Vec<Contact>,
+    sort: SortBy,
+    limit: Option<usize>,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    // contacts without the sort field come last, ties are broken by
+    // name then by id so the order is stable between runs
+    contacts.sort_by_cached_key(|contact| {
+        let key = sort_key(contact, sort);
+        let name = sort_key(contact, SortBy::Name);
+        (key.is_none(), key, name, contact.id.clone())
+    });
+    if let Some(limit) = limit {
+        contacts.truncate(limit);
+    }
+    trace!("contacts: {:?}", contacts);
+
+    printer.print_table(Box::new(contacts), PrintTableOpts::from(&config.format))
+}
+
+/// Shows a contact of the local store.
+pub fn show<P: PrinterService>(
+    id: &str,
+    raw: bool,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> show contact handler");
+
+    let card = find_card(local_cards(config).as_ref(), id)?;
+    trace!("card: {:?}", card);
+
+    if raw {
+        printer.print_struct(card.content)?;
+    } else {
+        printer.print_struct(Contact::from(&card))?;
+    }
+
+    info!("<< show contact handler");
+    Ok(())
+}
+
+/// Adds a contact to the local store, from the given template or from
+/// the editor.
+pub fn add<P: PrinterService>(
+    template: ContactTemplate,
+    upload: bool,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> add contact handler");
+
+    let template = if template.is_empty() {
+        let draft = ContactTemplate::default().to_toml()?;
+        ContactTemplate::from_toml(&output::edit(&draft, "contact.toml")?)?
+    } else {
+        template
+    };
+    let contact = template.into_contact(Contact::new_id())?;
+    trace!("contact: {:?}", contact);
+
+    let mut card = Card {
+        id: contact.id.clone(),
+        date: Local::now(),
+        content: contact.to_vcard(Utc::now()),
+        etag: None,
+        href: None,
+    };
+    let _lock = lock(config, true)?;
+    local_cards(config)
+        .put(&mut card)
+        .context("cannot write contact to the local store")?;
+
+    if upload {
+        upload_card(config, &card)?;
+    }
+
+    printer.print_struct(format!("Contact {} successfully added", card.id))?;
+
+    info!("<< add contact handler");
+    Ok(())
+}
+
+/// Edits a contact of the local store in the editor, either as a form
+/// or as a raw vCard. The editor is opened again as long as the edited
+/// contact is not valid. The revision of the contact is bumped.
+pub fn edit<P: PrinterService>(
+    id: &str,
+    raw: bool,
+    upload: bool,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> edit contact handler");
+
+    let local = local_cards(config);
+    let card = find_card(local.as_ref(), id)?;
+    trace!("card: {:?}", card);
+
+    let content = if raw {
+        edit_until_valid(&card.content, &format!("{}.vcf", card.id), |edited| {
+            update_raw_card(&card, edited)
+        })?
+    } else {
+        let contact = Contact::from(&card);
+        let draft = ContactTemplate::from_contact(&contact).to_toml()?;
+        edit_until_valid(&draft, &format!("{}.toml", card.id), |edited| {
+            let edited_contact = ContactTemplate::from_toml(edited)?.apply(contact.clone())?;
+            if edited_contact == contact {
+                return Ok(None);
+            }
+            Ok(Some(edited_contact.update_vcard(&card.content, Utc::now())))
+        })?
+    };
+
+    let content = match content {
+        Some(content) => content,
+        None => {
+            printer.print_struct(format!("Contact {} unchanged", card.id))?;
+            info!("<< edit contact handler");
+            return Ok(());
+        }
+    };
+
+    // a sync may have run while the contact was edited, its changes
+    // must not be overwritten
+    let _lock = lock(config, true)?;
+    let mut card = match find_card(local.as_ref(), &card.id)? {
+        current_card if current_card.content == card.content => current_card,
+        _ => {
+            return Err(anyhow!(
+                "cannot write contact {}: it changed while it was edited",
+                card.id
+            ))
+        }
+    };
+    card.content = content;
+    card.date = Local::now();
+    local
+        .put(&mut card)
+        .context("cannot write contact to the local store")?;
+
+    if upload {
+        upload_card(config, &card)?;
+    }
+
+    printer.print_struct(format!("Contact {} successfully edited", card.id))?;
+
+    info!("<< edit contact handler");
+    Ok(())
+}
+
+/// Opens the given draft in the editor until the given parser accepts
+/// it. On error, the editor is opened again with the edited content,
+/// unless it did not change since the previous attempt. Returns `None`
+/// if the draft was left untouched, or if the parser found no change.
+fn edit_until_valid<F>(draft: &str, file_name: &str, parse: F) -> Result<Option<String>>
+where
+    F: Fn(&str) -> Result<Option<String>>,
+{
+    let mut content = draft.to_owned();
+    let mut failed_content: Option<String> = None;
+
+    loop {
+        let edited = output::edit(&content, file_name)?;
+        if edited == draft {
+            return Ok(None);
+        }
+
+        match parse(&edited) {
+            Ok(content) => return Ok(content),
+            Err(err) if failed_content.as_ref() == Some(&edited) => return Err(err),
+            Err(err) => {
+                eprintln!("{:?}", err);
+                eprintln!("Press Enter to edit the contact again…");
+                let mut line = String::new();
+                io::stdin()
+                    .read_line(&mut line)
+                    .context("cannot read from stdin")?;
+                failed_content = Some(edited.clone());
+                content = edited;
+            }
+        }
+    }
+}
+
+/// Deletes a contact from the local store, after confirmation. The
+/// deletion is either applied to the CardDAV server right away, or
+/// recorded so the next sync applies it.
+pub fn delete<P: PrinterService>(
+    id: &str,
+    yes: bool,
+    remote: bool,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> delete contact handler");
+
+    let local = local_cards(config);
+    let card = find_card(local.as_ref(), id)?;
+    trace!("card: {:?}", card);
+
+    if !yes {
+        let name = Contact::from(&card)
+            .name()
+            .unwrap_or("(no name)")
+            .to_owned();
+        if !confirm(&format!("Delete contact {} ({})?", name, card.id))? {
+            printer.print_struct(format!("Contact {} not deleted", card.id))?;
+            info!("<< delete contact handler");
+            return Ok(());
+        }
+    }
+
+    let _lock = lock(config, true)?;
+    if remote {
+        if !delete_remote_card(config, &card)? {
+            printer.print_struct(format!(
+                "Contact {} not found on the server, the next sync will delete it",
+                card.id
+            ))?;
+        }
+    } else {
+        let mut tombstones = Tombstones::new(config.tombstones_file_path())?;
+        tombstones.insert(card.clone());
+        tombstones.save()?;
+    }
+    local
+        .delete(&card)
+        .context("cannot delete contact from the local store")?;
+
+    printer.print_struct(format!("Contact {} successfully deleted", card.id))?;
+
+    info!("<< delete contact handler");
+    Ok(())
+}
+
+/// Imports the contacts of the given file into the local store. Each
+/// contact looking like an existing one (including the ones imported
+/// before it) is handled following the given action.
+pub fn import<P: PrinterService>(
+    path: &Path,
+    format: Option<ImportFormat>,
+    mappings: Vec<(String, String)>,
+    on_duplicate: OnDuplicate,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> import contacts handler");
+
+    let format = format
+        .or_else(|| ImportFormat::from_path(path))
+        .ok_or_else(|| anyhow!("cannot guess format of {:?}, please use --format", path))?;
+    debug!("format: {:?}", format);
+    let content =
+        fs::read_to_string(path).with_context(|| format!("cannot read file at {:?}", path))?;
+    let mut mapping = config.csv_mapping.clone();
+    mapping.columns.extend(mappings);
+
+    let cards = import_cards(&content, format, &mapping, Utc::now())
+        .with_context(|| format!("cannot import contacts from {:?}", path))?;
+    debug!("{} cards to import", cards.len());
+
+    let mut on_duplicate = on_duplicate;
+    if on_duplicate == OnDuplicate::Ask && atty::isnt(atty::Stream::Stdin) {
+        warn!("cannot ask what to do with duplicates, skipping them");
+        on_duplicate = OnDuplicate::Skip;
+    }
+
+    let _lock = lock(config, true)?;
+    // phone numbers are compared in E.164
+    let mut contacts: Vec<Contact> = index(config)?
+        .contacts()
+        .map(|contact| contact.with_phone_keys(config.phone_region))
+        .collect();
+    let local = local_cards(config);
+    let (mut imported, mut skipped) = (0, 0);
+    for imported_card in cards {
+        let mut contact = imported_card.contact.with_phone_keys(config.phone_region);
+        let mut content = imported_card.content;
+        // a contact with the same UID comes first, whatever the order
+        // of the contacts
+        let duplicate = contacts
+            .iter()
+            .find(|c| c.id == contact.id)
+            .or_else(|| contacts.iter().find(|c| contact.is_duplicate_of(c)));
+        if let Some(duplicate) = duplicate {
+            let same_id = duplicate.id == contact.id;
+            let add = match on_duplicate {
+                OnDuplicate::Skip => false,
+                OnDuplicate::Add => true,
+                OnDuplicate::Ask => match ask_duplicate(&contact, duplicate)? {
+                    (add, Some(action)) => {
+                        on_duplicate = action;
+                        add
+                    }
+                    (add, None) => add,
+                },
+            };
+            if !add {
+                debug!("skipping duplicate contact {:?}", contact.id);
+                skipped += 1;
+                continue;
+            }
+            // the added contact must not replace the existing one
+            if same_id {
+                contact.id = Contact::new_id();
+                content = vcard::set_uid(&content, &contact.id);
+            }
+        }
+
+        let mut card = Card {
+            id: contact.id.clone(),
+            date: Local::now(),
+            content,
+            etag: None,
+            href: None,
+        };
+        local
+            .put(&mut card)
+            .with_context(|| format!("cannot write contact {} to the local store", card.id))?;
+        contacts.push(contact);
+        imported += 1;
+    }
+
+    printer.print_struct(format!(
+        "{} contact(s) successfully imported, {} duplicate(s) skipped",
+        imported, skipped
+    ))?;
+
+    info!("<< import contacts handler");
+    Ok(())
+}
+
+/// Exports the contacts of the local store matching the given query,
+/// sorted by name. Contacts are written to the standard output, to
+/// the given file, or to one file per contact inside the given
+/// directory.
+pub fn export<P: PrinterService>(
+    format: ExportFormat,
+    query: Option<&str>,
+    output: Option<&Path>,
+    split: bool,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> export contacts handler");
+
+    let query = query
+        .map(str::parse::<Query>)
+        .transpose()?
+        .map(|query| query.with_region(config.phone_region));
+    trace!("query: {:?}", query);
+    let mut cards: Vec<(Contact, Card)> = local_cards(config)
+        .list()
+        .context("cannot list local contacts")?
+        .into_values()
+        .filter(|card| query.iter().all(|query| query.matches(card)))
+        .map(|card| (Contact::from(&card), card))
+        .collect();
+    cards.sort_by_cached_key(|(contact, card)| (sort_key(contact, SortBy::Name), card.id.clone()));
+    let cards: Vec<Card> = cards.into_iter().map(|(_, card)| card).collect();
+    debug!("{} cards to export", cards.len());
+
+    match output {
+        None => io::stdout()
+            .write_all(export_cards(&cards, format)?.as_bytes())
+            .context("cannot write contacts to the standard output")?,
+        Some(dir) if split => {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("cannot create directory at {:?}", dir))?;
+            for card in &cards {
+                // file names are built as the local store does, so
+                // that UIDs cannot escape the directory
+                let href = href_from_id(&card.id);
+                let file_stem = href.trim_end_matches(".vcf");
+                let path = dir.join(format!("{}.{}", file_stem, format.extension()));
+                fs::write(&path, export_one(card, format)?)
+                    .with_context(|| format!("cannot write contact at {:?}", path))?;
+            }
+        }
+        Some(path) => fs::write(path, export_cards(&cards, format)?)
+            .with_context(|| format!("cannot write contacts at {:?}", path))?,
+    }
+
+    if let Some(output) = output {
+        printer.print_struct(format!(
+            "{} contact(s) successfully exported to {:?}",
+            cards.len(),
+            output
+        ))?;
+    }
+
+    info!("<< export contacts handler");
+    Ok(())
+}
+
+/// Checks the cards of the local store. With `fix`, the fixable
+/// problems are repaired and the cards are checked again.
+pub fn lint<P: PrinterService>(fix: bool, config: &AccountConfig, printer: &mut P) -> Result<()> {
+    info!(">> lint contacts handler");
+
+    let local = local_cards(config);
+    let cards = local.list_all().context("cannot list local contacts")?;
+    let mut report = lint_cards(&cards);
+
+    if fix {
+        let _lock = lock(config, true)?;
+        let mut fixed = Vec::new();
+        for card in cards {
+            if !lint::lint(&card.content)
+                .iter()
+                .any(|problem| problem.fixable)
+            {
+                continue;
+            }
+            debug!("fixing card {:?}", card.id);
+            let mut fixed_card = card.clone();
+            fixed_card.content = lint::fix(&card.content, &card.id);
+            local
+                .put(&mut fixed_card)
+                .with_context(|| format!("cannot write contact {} to the local store", card.id))?;
+            fixed.push(card.href.unwrap_or(card.id));
+        }
+        let cards = local.list_all().context("cannot list local contacts")?;
+        report = lint_cards(&cards);
+        report.fixed = fixed;
+    }
+
+    let errors = report.count(Severity::Error);
+    printer.print_struct(report)?;
+
+    info!("<< lint contacts handler");
+    if errors > 0 {
+        return Err(anyhow!("found {} error(s) in local contacts", errors));
+    }
+    Ok(())
+}
+
+/// Finds the local contacts looking like the same person, scored at
+/// least the given score, then asks for each pair which contact to
+/// keep. The other contact is merged into the kept one, its group
+/// memberships move to the kept contact, then it is deleted as the
+/// delete command would do. Duplicates are only listed when asked to,
+/// or when the user cannot be asked.
+pub fn dedupe<P: PrinterService>(
+    min_score: u8,
+    list: bool,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> dedupe contacts handler");
+
+    let local = local_cards(config);
+    let (mut groups, cards): (Vec<Card>, Vec<Card>) = local
+        .list_all()
+        .context("cannot list local contacts")?
+        .into_iter()
+        .partition(|card| is_group(&card.content));
+    let contacts: Vec<Contact> = cards.iter().map(Contact::from).collect();
+    let report = DedupeReport {
+        duplicates: find_duplicates(&contacts, config.phone_region)
+            .into_iter()
+            .filter(|duplicate| duplicate.score >= min_score)
+            .collect(),
+        names: contacts
+            .iter()
+            .filter_map(|contact| Some((contact.id.clone(), contact.name()?.to_owned())))
+            .collect(),
+    };
+    debug!("{} duplicates found", report.duplicates.len());
+
+    if !list && !report.duplicates.is_empty() && atty::isnt(atty::Stream::Stdin) {
+        warn!("cannot ask which contacts to merge, listing them instead");
+    }
+    if list || report.duplicates.is_empty() || atty::isnt(atty::Stream::Stdin) {
+        printer.print_struct(report)?;
+        info!("<< dedupe contacts handler");
+        return Ok(());
+    }
+
+    let _lock = lock(config, true)?;
+    let mut cards: HashMap<String, Card> = cards
+        .into_iter()
+        .map(|card| (card.id.clone(), card))
+        .collect();
+    let mut tombstones = Tombstones::new(config.tombstones_file_path())?;
+    let mut merged = 0;
+    for duplicate in &report.duplicates {
+        // pairs involving an already merged contact are skipped
+        let (first, second) = match (cards.get(&duplicate.first), cards.get(&duplicate.second)) {
+            (Some(first), Some(second)) => (first, second),
+            _ => continue,
+        };
+        let (mut kept, other) = match ask_merge(&report, duplicate)? {
+            MergeAnswer::KeepFirst => (first.clone(), second.clone()),
+            MergeAnswer::KeepSecond => (second.clone(), first.clone()),
+            MergeAnswer::Skip => continue,
+            MergeAnswer::Quit => break,
+        };
+        debug!("merging card {:?} into card {:?}", other.id, kept.id);
+
+        kept.content = merge(&kept.content, &other.content, Utc::now());
+        kept.date = Local::now();
+        local
+            .put(&mut kept)
+            .with_context(|| format!("cannot write contact {} to the local store", kept.id))?;
+
+        for group in groups.iter_mut() {
+            if let Some(content) = move_membership(&group.content, &other.id, &kept.id, Utc::now())
+            {
+                debug!("moving membership of group {:?}", group.id);
+                group.content = content;
+                group.date = Local::now();
+                local.put(group).with_context(|| {
+                    format!("cannot write group {} to the local store", group.id)
+                })?;
+            }
+        }
+
+        tombstones.insert(other.clone());
+        tombstones.save()?;
+        local
+            .delete(&other)
+            .with_context(|| format!("cannot delete contact {} from the local store", other.id))?;
+
+        cards.remove(&other.id);
+        cards.insert(kept.id.clone(), kept);
+        merged += 1;
+    }
+
+    printer.print_struct(format!("{} contact(s) successfully merged", merged))?;
+
+    info!("<< dedupe contacts handler");
+    Ok(())
+}
+
+/// Rewrites the phone numbers of the local contacts in the E.164
+/// format, national numbers being read in the given region (or in the
+/// region of the account). Numbers which cannot be read are left as
+/// they are.
+pub fn normalize<P: PrinterService>(
+    region: Option<Region>,
+    config: &AccountConfig,
+    printer: &mut P,
+) -> Result<()> {
+    info!(">> normalize contacts handler");
+
+    let region = region.or(config.phone_region);
+    debug!("region: {:?}", region);
+    if region.is_none() {
+        warn!("no phone region given, national phone numbers are left as they are");
+    }
+
+    let _lock = lock(config, true)?;
+    let local = local_cards(config);
+    let mut normalized = 0;
+    for mut card in local.list_all().context("cannot list local contacts")? {
+        if let Some(content) = normalize_phones(&card.content, region, Utc::now()) {
+            debug!("normalizing phone numbers of card {:?}", card.id);
+            card.content = content;
+            card.date = Local::now();
+            local
+                .put(&mut card)
+                .with_context(|| format!("cannot write contact {} to the local store", card.id))?;
+            normalized += 1;
+        }
+    }
+
+    printer.print_struct(format!("{} contact(s) successfully normalized", normalized))?;
+
+    info!("<< normalize contacts handler");
+    Ok(())
+}
+
+/// Converts the cards of the given vCard file (or of the standard
+/// input) to the given vCard version, then writes them to the
+/// standard output.
+pub fn convert(version: VcardVersion, path: Option<&Path>) -> Result<()> {
+    info!(">> convert cards handler");
+
+    let content = match path {
+        Some(path) => {
+            fs::read_to_string(path).with_context(|| format!("cannot read file at {:?}", path))?
+        }
+        None => {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .context("cannot read cards from the standard input")?;
+            content
+        }
+    };
+
+    let cards = vcard::split(&content);
+    debug!("{} cards to convert to vCard {}", cards.len(), version);
+    let converted: String = cards
+        .iter()
+        .map(|card| convert_card(card, version))
+        .collect();
+    io::stdout()
+        .write_all(converted.as_bytes())
+        .context("cannot write cards to the standard output")?;
+
+    info!("<< convert cards handler");
+    Ok(())
+}
+
+/// Asks the user whether the given imported contact, looking like the
+/// given existing one, should be added. Returns the answer, and the
+/// action to apply to the next duplicates if the answer applies to
+/// all of them.
+fn ask_duplicate(contact: &Contact, existing: &Contact) -> Result<(bool, Option<OnDuplicate>)> {
+    let name = |contact: &Contact| contact.name().unwrap_or("(no name)").to_owned();
+    loop {
+        eprint!(
+            "Contact {} looks like the existing contact {} ({}). \
+             Add it anyway? [y]es, [n]o, [a]ll, n[o]ne: ",
+            name(contact),
+            name(existing),
+            existing.id
+        );
+        io::stderr().flush().context("cannot write to stderr")?;
+        let mut answer = String::new();
+        io::stdin()
+            .read_line(&mut answer)
+            .context("cannot read from stdin")?;
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok((true, None)),
+            "n" | "no" | "" => return Ok((false, None)),
+            "a" | "all" => return Ok((true, Some(OnDuplicate::Add))),
+            "o" | "none" => return Ok((false, Some(OnDuplicate::Skip))),
+            _ => continue,
+        }
+    }
+}
+
+/// Represents the answer of the user to a merge question.
+enum MergeAnswer {
+    KeepFirst,
+    KeepSecond,
+    Skip,
+    Quit,
+}
+
+/// Asks the user which contact of the given duplicate should be kept,
+/// the other one being merged into it.
+fn ask_merge(report: &DedupeReport, duplicate: &Duplicate) -> Result<MergeAnswer> {
+    report
+        .print_duplicate(duplicate, &mut io::stderr())
+        .context("cannot write to stderr")?;
+    loop {
+        eprint!(
+            "Keep [1] {} or [2] {}, [s]kip or [q]uit? ",
+            report.name(&duplicate.first),
+            report.name(&duplicate.second),
+        );
+        io::stderr().flush().context("cannot write to stderr")?;
+        let mut answer = String::new();
+        io::stdin()
+            .read_line(&mut answer)
+            .context("cannot read from stdin")?;
+        match answer.trim().to_lowercase().as_str() {
+            "1" => return Ok(MergeAnswer::KeepFirst),
+            "2" => return Ok(MergeAnswer::KeepSecond),
+            "s" | "skip" | "" => return Ok(MergeAnswer::Skip),
+            "q" | "quit" => return Ok(MergeAnswer::Quit),
+            _ => continue,
+        }
+    }
+}
+
+/// Asks the user to confirm the given question. Only an explicit yes
+/// is considered as a confirmation.
+fn confirm(question: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().context("cannot write to stderr")?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .context("cannot read from stdin")?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Deletes the given local card from the CardDAV server, then removes
+/// it from the caches of both sides as a sync would do. The card is
+/// only deleted if it did not change on the server since the last
+/// sync. A card missing from the remote cache cannot be located on
+/// the server: its deletion is recorded instead, so the next sync
+/// applies it, and false is returned. The caller must hold the sync
+/// lock.
+fn delete_remote_card(config: &AccountConfig, card: &Card) -> Result<bool> {
+    let remote = remote_cards(config)?;
+    let mut remote_cache = CachedCards::new(config.remote_cache_file_path())?;
+    remote.prepare_cache(&mut remote_cache)?;
+    let mut local_cache = CachedCards::new(config.local_store_cache_file_path())?;
+
+    let remote_card = match remote_cache.cards.get(&card.id) {
+        Some(remote_card) => remote_card,
+        None => {
+            debug!("card {:?} not found in the remote cache", card.id);
+            let mut tombstones = Tombstones::new(config.tombstones_file_path())?;
+            tombstones.insert(card.clone());
+            tombstones.save()?;
+            return Ok(false);
+        }
+    };
+    remote.delete(remote_card).map_err(|e| match e {
+        CardamomError::CardChangedError(_) => anyhow!(
+            "cannot delete contact {}: it changed on the server, sync contacts first",
+            card.id
+        ),
+        e => anyhow::Error::new(e).context(format!("cannot delete contact {}", card.id)),
+    })?;
+
+    remote_cache.cards.remove(&card.id);
+    local_cache.cards.remove(&card.id);
+    remote_cache.save()?;
+    local_cache.save()?;
+    Ok(true)
+}
+
+/// Validates the given raw vCard edited from the given card, then
+/// bumps its revision.
+fn update_raw_card(card: &Card, edited: &str) -> Result<Option<String>> {
+    let contents = vcard::split(edited);
+    let content = match contents.as_slice() {
+        [content] => content,
+        contents => {
+            return Err(anyhow!(
+                "cannot save contact: expected one vCard, found {}",
+                contents.len()
+            ))
+        }
+    };
+    if vcard::uid(content) != vcard::uid(&card.content) {
+        return Err(anyhow!("cannot save contact: its UID cannot change"));
+    }
+    if content == &card.content {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        Contact::parse(&card.id, content).update_vcard(content, Utc::now()),
+    ))
+}
+
+/// Uploads the given local card to the CardDAV server, then records
+/// it in the caches of both sides as a sync would do, so the next
+/// sync does not consider it as a new card. A card known by the
+/// server is only replaced if it did not change there since the last
+/// sync. The caller must hold the sync lock.
+fn upload_card(config: &AccountConfig, card: &Card) -> Result<()> {
+    let remote = remote_cards(config)?;
+    let mut remote_cache = CachedCards::new(config.remote_cache_file_path())?;
+    remote.prepare_cache(&mut remote_cache)?;
+    let mut local_cache = CachedCards::new(config.local_store_cache_file_path())?;
+
+    let (href, etag) = remote_cache
+        .cards
+        .get(&card.id)
+        .map(|cached_card| (cached_card.href.clone(), cached_card.etag.clone()))
+        .unwrap_or_default();
+    let mut remote_card = Card {
+        href,
+        etag,
+        ..card.clone()
+    };
+    remote.put(&mut remote_card).map_err(|e| match e {
+        CardamomError::CardChangedError(_) => anyhow!(
+            "cannot upload contact {}: it changed on the server, sync contacts first",
+            card.id
+        ),
+        e => anyhow::Error::new(e).context(format!("cannot upload contact {}", card.id)),
+    })?;
+
+    remote_cache.cards.insert(card.id.clone(), remote_card);
+    local_cache.cards.insert(card.id.clone(), card.clone());
+    remote_cache.save()?;
+    local_cache.save()?;
+    Ok(())
+}
+
+/// Finds the card matching the given id, or the only card whose id
+/// starts with it.
+fn find_card(backend: &dyn Backend, id: &str) -> Result<Card> {
+    let mut cards = backend.list().context("cannot list local contacts")?;
+    if let Some(card) = cards.remove(id) {
+        return Ok(card);
+    }
+
+    let mut matching_cards = cards.into_values().filter(|card| card.id.starts_with(id));
+    match (matching_cards.next(), matching_cards.next()) {
+        (Some(card), None) => Ok(card),
+        (Some(_), Some(_)) => Err(anyhow!("cannot find contact {:?}: id is ambiguous", id)),
+        (None, _) => Err(anyhow!("cannot find contact {:?}", id)),
+    }
+}
+
+/// Returns the case-insensitive value of the given contact field.
+fn sort_key(contact: &Contact, sort: SortBy) -> Option<String> {
+    match sort {
+        SortBy::Name => contact.name(),
+        SortBy::Email => contact.primary_email().map(|email| email.value.as_str()),
+        SortBy::Phone => contact.primary_phone().map(|phone| phone.value.as_str()),
+        SortBy::Org => contact.organization.as_deref(),
+    }
+    .map(str::to_lowercase)
+}
+
+/// Builds the local backend of the given account: its vCard file if
+/// it has one, its sync directory otherwise.
+fn local_cards(config: &AccountConfig) -> Box<dyn Backend> {
+    match config.sync_file.as_ref() {
+        Some(path) => Box::new(VcfFile::new(path.clone())),
+        None => Box::new(LocalCards::new(config.sync_dir.clone())),
+    }
+}
+
+/// Reads the search index of the local store, then brings it up to
+/// date.
+fn index(config: &AccountConfig) -> Result<Index> {
+    let mut index = Index::new(config.index_file_path())?;
+    if index
+        .refresh(local_cards(config).as_ref())
+        .context("cannot refresh local index")?
+    {
+        index.save()?;
+    }
+    Ok(index)
+}
+
+/// Acquires the sync lock of the given account.
+fn lock(config: &AccountConfig, wait: bool) -> Result<SyncLock> {
+    let lock = SyncLock::acquire(config.sync_lock_file_path(), wait)?;
+    if let Some(pid) = lock.stale_pid {
+        warn!(
+            "previous sync of account {:?} (process {}) did not terminate properly",
+            config.name, pid
+        );
+    }
+    Ok(lock)
+}
+
+/// Builds the CardDAV backend of the given account.
+fn remote_cards(config: &AccountConfig) -> Result<RemoteCards> {
     let remote = RemoteCards::new(
-        config.sync_dir.clone(),
         config.host.clone(),
-        config.port.clone(),
+        config.port,
         config.login.clone(),
         config.passwd()?,
-    )?;
+        RetryPolicy {
+            max_attempts: config.max_attempts,
+            ..RetryPolicy::default()
+        },
+        config.sync_concurrency,
+    )
+    .map(|remote| remote.with_vcard_version(config.vcard_version))
+    .with_context(|| {
+        format!(
+            "cannot connect to carddav server of account {:?}",
+            config.name
+        )
+    })?;
+    Ok(remote)
+}
 
-    let patch = Patch::new(local, remote);
+/// Builds the patch between the two given replicas, or resumes the
+/// interrupted one, then applies it.
+fn apply<L: Backend, R: Backend>(
+    left: &mut Replica<L>,
+    right: &mut Replica<R>,
+    journal_path: PathBuf,
+) -> Result<SyncReport> {
+    let mut journal = Journal::new(journal_path)?;
+    let patch = match journal.patch() {
+        Some(patch) => {
+            warn!("previous sync was interrupted, resuming it");
+            patch
+        }
+        None => Patch::new(left, right),
+    };
     trace!("patch: {:?}", patch);
 
-    printer.print_str("TODO")?;
+    patch
+        .apply(left, right, &mut journal)
+        .context("cannot apply patch")
+}
 
-    info!("<< sync contacts handler");
+/// Synchronizes the local store with the CardDAV server, then applies
+/// the local changes to the search index.
+fn apply_local<L: Backend, R: Backend>(
+    local: &mut Replica<L>,
+    remote: &mut Replica<R>,
+    config: &AccountConfig,
+) -> Result<SyncReport> {
+    // cards deleted on purpose must be deleted remotely, even if the
+    // local cache does not know them
+    let mut tombstones = Tombstones::new(config.tombstones_file_path())?;
+    tombstones.bury(local, remote);
+    let report = apply(local, remote, config.sync_journal_file_path())?;
+    tombstones.clear(&report);
+    tombstones.save()?;
+
+    let mut index = Index::new(config.index_file_path())?;
+    index.apply_report(&report.left, &local.cache.cards);
+    index.save()?;
+
+    Ok(report)
+}
+
+/// Makes the command fail if some cards could not be synchronized.
+fn check_failures(failures: usize) -> Result<()> {
+    if failures > 0 {
+        return Err(anyhow!("cannot synchronize {} card(s)", failures));
+    }
     Ok(())
 }
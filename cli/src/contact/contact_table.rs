@@ -0,0 +1,43 @@
+//! Contact table module.
+//!
+//! This module contains the table rendering of contacts.
+
+use anyhow::Result;
+use cardamom_lib::contact::Contact;
+use termcolor::Color;
+
+use crate::output::{Cell, PrintTable, PrintTableOpts, Row, Table, WriteColor};
+
+impl Table for Contact {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("ID").bold())
+            .cell(Cell::new("NAME").bold())
+            .cell(Cell::new("EMAIL").bold())
+            .cell(Cell::new("PHONE").bold())
+            .cell(Cell::new("ORGANIZATION").bold())
+    }
+
+    fn row(&self) -> Row {
+        let email = self.primary_email().map(|email| email.value.as_str());
+        let phone = self.primary_phone().map(|phone| phone.value.as_str());
+
+        // a truncated id is still a valid prefix to find the contact
+        Row::new()
+            .cell(Cell::new(&self.id).shrinkable().fg(Color::Red))
+            .cell(Cell::new(self.name().unwrap_or_default()).shrinkable())
+            .cell(
+                Cell::new(email.unwrap_or_default())
+                    .shrinkable()
+                    .fg(Color::Blue),
+            )
+            .cell(Cell::new(phone.unwrap_or_default()))
+            .cell(Cell::new(self.organization.as_deref().unwrap_or_default()).shrinkable())
+    }
+}
+
+impl PrintTable for Vec<Contact> {
+    fn print_table(&self, writer: &mut dyn WriteColor, opts: PrintTableOpts) -> Result<()> {
+        Contact::print(writer, self, opts)
+    }
+}
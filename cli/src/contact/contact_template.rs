@@ -0,0 +1,219 @@
+//! Contact template module.
+//!
+//! This module contains the friendly form of a contact, used to
+//! create a contact from the command line flags, or to create and edit
+//! a contact as a TOML file in the editor.
+
+use anyhow::{anyhow, Context, Result};
+use cardamom_lib::contact::{Contact, TypedValue};
+use serde::{Deserialize, Serialize};
+
+/// Represents the header of the template shown in the editor.
+const EDITOR_HEADER: &str = r##"# Lines starting with "#" are ignored and empty values are dropped.
+# Typed values are written "type:value", for example
+# "work:alice@example.org" or "cell,pref:+33 6 00 00 00 00".
+"##;
+
+/// Represents the fields of a contact the user can edit.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContactTemplate {
+    pub name: String,
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+    pub org: String,
+    pub birthday: String,
+    pub categories: Vec<String>,
+    pub urls: Vec<String>,
+    pub notes: Vec<String>,
+}
+
+impl ContactTemplate {
+    /// Builds the template of the given contact.
+    pub fn from_contact(contact: &Contact) -> Self {
+        Self {
+            name: contact.formatted_name.clone().unwrap_or_default(),
+            emails: contact.emails.iter().map(format_typed_value).collect(),
+            phones: contact.phones.iter().map(format_typed_value).collect(),
+            org: contact.organization.clone().unwrap_or_default(),
+            birthday: contact.birthday.clone().unwrap_or_default(),
+            categories: contact.categories.clone(),
+            urls: contact.urls.iter().map(format_typed_value).collect(),
+            notes: contact.notes.clone(),
+        }
+    }
+
+    /// Writes the template as a commented TOML file.
+    pub fn to_toml(&self) -> Result<String> {
+        let toml = toml::to_string(self).context("cannot serialize contact template")?;
+        Ok(format!("{}{}", EDITOR_HEADER, toml))
+    }
+
+    /// Parses the given edited template.
+    pub fn from_toml(content: &str) -> Result<Self> {
+        toml::from_str(content).context("cannot parse contact template")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Builds a new contact with the given id.
+    pub fn into_contact(self, id: String) -> Result<Contact> {
+        self.apply(Contact {
+            id,
+            ..Contact::default()
+        })
+    }
+
+    /// Replaces the fields of the given contact by the ones of the
+    /// template. Fields absent from the template (like addresses) are
+    /// kept. A contact needs at least a name or an email address.
+    pub fn apply(self, contact: Contact) -> Result<Contact> {
+        let typed_values = |values: &[String]| -> Vec<TypedValue> {
+            values
+                .iter()
+                .filter_map(|value| typed_value(value))
+                .collect()
+        };
+        let non_empty = |value: &str| Some(value.trim().to_owned()).filter(|v| !v.is_empty());
+        let emails = typed_values(&self.emails);
+        let name = non_empty(&self.name);
+
+        if name.is_none() && emails.is_empty() {
+            return Err(anyhow!(
+                "cannot save contact: a name or an email is required"
+            ));
+        }
+
+        Ok(Contact {
+            // the formatted name is mandatory, the email address is
+            // the best fallback
+            formatted_name: name.or_else(|| emails.first().map(|email| email.value.clone())),
+            emails,
+            phones: typed_values(&self.phones),
+            organization: non_empty(&self.org),
+            birthday: non_empty(&self.birthday),
+            categories: self
+                .categories
+                .iter()
+                .filter_map(|c| non_empty(c))
+                .collect(),
+            urls: typed_values(&self.urls),
+            notes: self.notes.iter().filter_map(|n| non_empty(n)).collect(),
+            ..contact
+        })
+    }
+}
+
+/// Formats a typed value as `type,type:value`. The preferred value is
+/// marked with the `pref` type.
+fn format_typed_value(value: &TypedValue) -> String {
+    let mut types = value.types.clone();
+    if value.pref == Some(1) {
+        types.push(String::from("pref"));
+    }
+
+    if types.is_empty() {
+        value.value.clone()
+    } else {
+        format!("{}:{}", types.join(","), value.value)
+    }
+}
+
+/// Parses a value written `type,type:value` or `value`. Only words
+/// made of letters are considered as types, so URIs (like `tel:` or
+/// `https:` ones) are kept as they are.
+fn typed_value(value: &str) -> Option<TypedValue> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    let typed_value = match value.split_once(':') {
+        Some((kinds, value))
+            if !kinds.is_empty()
+                && kinds
+                    .chars()
+                    .all(|c| c.is_ascii_alphabetic() || c == ',' || c == '-')
+                && !["tel", "mailto", "sip"].contains(&kinds.to_lowercase().as_str())
+                && !value.starts_with("//") =>
+        {
+            let mut typed_value = TypedValue {
+                value: value.trim().to_owned(),
+                ..TypedValue::default()
+            };
+            for kind in kinds.split(',').map(str::to_lowercase) {
+                match kind.as_str() {
+                    "" => (),
+                    "pref" => typed_value.pref = Some(1),
+                    _ => typed_value.types.push(kind),
+                }
+            }
+            typed_value
+        }
+        _ => TypedValue {
+            value: value.to_owned(),
+            ..TypedValue::default()
+        },
+    };
+
+    Some(typed_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_contact_from_template() {
+        let template = ContactTemplate::from_toml(concat!(
+            "name = \"Alice\"\n",
+            "emails = [\"Work:alice@corp.com\", \"alice@home.net\", \"\"]\n",
+            "urls = [\"https://alice.net\"]\n",
+        ))
+        .unwrap();
+        let contact = template.into_contact("id".into()).unwrap();
+
+        assert_eq!(Some("Alice"), contact.name());
+        assert_eq!(2, contact.emails.len());
+        assert_eq!(vec!["work"], contact.emails[0].types);
+        assert_eq!("alice@corp.com", contact.emails[0].value);
+        assert!(contact.emails[1].types.is_empty());
+        assert_eq!("https://alice.net", contact.urls[0].value);
+        assert_eq!(None, contact.organization);
+
+        assert!(ContactTemplate::default()
+            .into_contact("id".into())
+            .is_err());
+        assert!(ContactTemplate::from_toml("nickname = \"Al\"").is_err());
+    }
+
+    #[test]
+    fn edit_contact_with_template() {
+        let contact = Contact {
+            id: "id".into(),
+            formatted_name: Some("Bob".into()),
+            phones: vec![TypedValue {
+                types: vec!["cell".into(), "voice".into()],
+                pref: Some(1),
+                value: "+33 6 00 00 00 00".into(),
+            }],
+            notes: vec!["Line 1\nLine 2".into()],
+            ..Contact::default()
+        };
+        let toml = ContactTemplate::from_contact(&contact).to_toml().unwrap();
+        assert!(toml.contains("cell,voice,pref:+33 6 00 00 00 00"));
+
+        let template = ContactTemplate::from_toml(&toml).unwrap();
+        assert_eq!(contact, template.clone().apply(contact.clone()).unwrap());
+
+        let edited_contact = ContactTemplate {
+            org: "Acme".into(),
+            ..template
+        }
+        .apply(contact.clone())
+        .unwrap();
+        assert_eq!(Some("Acme".into()), edited_contact.organization);
+    }
+}
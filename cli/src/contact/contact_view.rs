@@ -0,0 +1,138 @@
+//! Contact view module.
+//!
+//! This module contains the plain text rendering of a single contact.
+
+use anyhow::{Context, Result};
+use cardamom_lib::contact::{Contact, TypedValue};
+use std::slice;
+use termcolor::{Color, ColorSpec};
+
+use crate::output::{Print, WriteColor};
+
+/// Represents the width of the field labels column.
+const LABEL_WIDTH: usize = 12;
+
+impl Print for Contact {
+    fn print(&self, writer: &mut dyn WriteColor) -> Result<()> {
+        let write_err = "cannot write contact to writer";
+
+        writer
+            .set_color(ColorSpec::new().set_bold(true))
+            .context(write_err)?;
+        write!(writer, "{}", self.name().unwrap_or("(no name)")).context(write_err)?;
+        writer.reset().context(write_err)?;
+        writeln!(writer).context(write_err)?;
+        if let Some(org) = self.organization.as_ref() {
+            writeln!(writer, "{}", org).context(write_err)?;
+        }
+        writeln!(writer).context(write_err)?;
+
+        for email in &self.emails {
+            print_typed_value(writer, "Email", email)?;
+        }
+        for phone in &self.phones {
+            print_typed_value(writer, "Phone", phone)?;
+        }
+        for addr in &self.addresses {
+            let lines = addr.lines();
+            print_field(writer, "Address", &lines, &addr.types, addr.pref)?;
+        }
+        if let Some(birthday) = self.birthday.as_ref() {
+            print_field(writer, "Birthday", &[format_date(birthday)], &[], None)?;
+        }
+        for url in &self.urls {
+            print_typed_value(writer, "URL", url)?;
+        }
+        if !self.categories.is_empty() {
+            print_field(
+                writer,
+                "Categories",
+                &[self.categories.join(", ")],
+                &[],
+                None,
+            )?;
+        }
+        for note in &self.notes {
+            let lines: Vec<String> = note.lines().map(String::from).collect();
+            print_field(writer, "Note", &lines, &[], None)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn print_typed_value(writer: &mut dyn WriteColor, label: &str, value: &TypedValue) -> Result<()> {
+    print_field(
+        writer,
+        label,
+        slice::from_ref(&value.value),
+        &value.types,
+        value.pref,
+    )
+}
+
+/// Prints a field as a block of lines: the label is printed on the
+/// first line, and the other lines are aligned with the first value
+/// line. The types of the field follow its first line.
+fn print_field(
+    writer: &mut dyn WriteColor,
+    label: &str,
+    lines: &[String],
+    types: &[String],
+    pref: Option<u8>,
+) -> Result<()> {
+    let write_err = "cannot write contact field to writer";
+    let mut types = types.to_vec();
+    if pref == Some(1) {
+        types.push(String::from("pref"));
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        let label = if i == 0 { label } else { "" };
+        writer
+            .set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))
+            .context(write_err)?;
+        write!(writer, "{:<width$}", label, width = LABEL_WIDTH).context(write_err)?;
+        writer.reset().context(write_err)?;
+        write!(writer, "{}", line).context(write_err)?;
+
+        if i == 0 && !types.is_empty() {
+            writer
+                .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
+                .context(write_err)?;
+            write!(writer, " ({})", types.join(", ")).context(write_err)?;
+            writer.reset().context(write_err)?;
+        }
+        writeln!(writer).context(write_err)?;
+    }
+
+    Ok(())
+}
+
+/// Formats the basic date forms of the vCard (`19850412`, `--0412`)
+/// as extended ones. Other values are kept as they are.
+fn format_date(date: &str) -> String {
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    match (date.len(), date.strip_prefix("--")) {
+        (8, None) if is_digits(date) => {
+            format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+        }
+        (6, Some(month_day)) if is_digits(month_day) => {
+            format!("--{}-{}", &month_day[..2], &month_day[2..])
+        }
+        _ => date.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_dates() {
+        assert_eq!("1985-04-12", format_date("19850412"));
+        assert_eq!("--04-12", format_date("--0412"));
+        assert_eq!("1985-04-12", format_date("1985-04-12"));
+        assert_eq!("circa 1800", format_date("circa 1800"));
+    }
+}
@@ -0,0 +1,61 @@
+//! Dedupe report module.
+//!
+//! This module contains the plain text rendering of the duplicate
+//! contacts.
+
+use anyhow::{Context, Result};
+use cardamom_lib::dedupe::Duplicate;
+use serde::Serialize;
+use std::{collections::HashMap, io::Write};
+
+use crate::output::{Print, WriteColor};
+
+/// Represents the duplicate contacts of the local store, with the
+/// names of the contacts indexed by their id.
+#[derive(Debug, Default, Serialize)]
+pub struct DedupeReport {
+    pub duplicates: Vec<Duplicate>,
+    pub names: HashMap<String, String>,
+}
+
+impl DedupeReport {
+    /// Returns the name of the contact matching the given id.
+    pub fn name(&self, id: &str) -> &str {
+        self.names.get(id).map_or("(no name)", String::as_str)
+    }
+
+    /// Writes the given duplicate as a line giving its score and its
+    /// contacts, followed by an indented line giving the reasons.
+    pub fn print_duplicate<W: Write + ?Sized>(
+        &self,
+        duplicate: &Duplicate,
+        writer: &mut W,
+    ) -> Result<()> {
+        writeln!(
+            writer,
+            "{:>3}% {} ({}) / {} ({})",
+            duplicate.score,
+            self.name(&duplicate.first),
+            duplicate.first,
+            self.name(&duplicate.second),
+            duplicate.second,
+        )?;
+        writeln!(writer, "     {}", duplicate.reasons.join(", "))?;
+        Ok(())
+    }
+}
+
+impl Print for DedupeReport {
+    fn print(&self, writer: &mut dyn WriteColor) -> Result<()> {
+        let write_err = "cannot write dedupe report to writer";
+
+        for duplicate in &self.duplicates {
+            self.print_duplicate(duplicate, writer).context(write_err)?;
+        }
+
+        if !self.duplicates.is_empty() {
+            writeln!(writer).context(write_err)?;
+        }
+        writeln!(writer, "{} duplicate(s) found", self.duplicates.len()).context(write_err)
+    }
+}
@@ -0,0 +1,39 @@
+//! Lint report module.
+//!
+//! This module contains the plain text rendering of the lint report.
+
+use anyhow::{Context, Result};
+use cardamom_lib::lint::{LintReport, Severity};
+
+use crate::output::{Print, WriteColor};
+
+impl Print for LintReport {
+    fn print(&self, writer: &mut dyn WriteColor) -> Result<()> {
+        let write_err = "cannot write lint report to writer";
+
+        for (file, problems) in &self.files {
+            writeln!(writer, "{}", file).context(write_err)?;
+            for problem in problems {
+                writeln!(writer, "  {}", problem).context(write_err)?;
+            }
+        }
+
+        if !self.fixed.is_empty() {
+            writeln!(writer, "\nfixed: {}", self.fixed.len()).context(write_err)?;
+            for file in &self.fixed {
+                writeln!(writer, "  {}", file).context(write_err)?;
+            }
+        }
+
+        if !self.files.is_empty() || !self.fixed.is_empty() {
+            writeln!(writer).context(write_err)?;
+        }
+        writeln!(
+            writer,
+            "{} error(s), {} warning(s)",
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )
+        .context(write_err)
+    }
+}
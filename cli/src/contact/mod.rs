@@ -1,2 +1,8 @@
 pub mod contact_args;
 pub mod contact_handlers;
+pub mod contact_table;
+pub mod contact_template;
+pub mod contact_view;
+pub mod dedupe_report;
+pub mod lint_report;
+pub mod sync_report;
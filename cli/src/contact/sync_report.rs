@@ -0,0 +1,90 @@
+//! Sync report module.
+//!
+//! This module contains the plain text rendering of the sync report.
+
+use anyhow::{Context, Result};
+use cardamom_lib::{report::SyncReport, sync::ConflictResolution};
+use serde::Serialize;
+
+use crate::output::{Print, WriteColor};
+
+/// Represents the report of a sync between the CardDAV servers of
+/// two accounts.
+#[derive(Debug, Serialize)]
+pub struct AccountsSyncReport {
+    pub from: String,
+    pub to: String,
+    #[serde(flatten)]
+    pub report: SyncReport,
+}
+
+impl Print for SyncReport {
+    fn print(&self, writer: &mut dyn WriteColor) -> Result<()> {
+        print_report(self, "local", "remote", writer)
+    }
+}
+
+impl Print for AccountsSyncReport {
+    fn print(&self, writer: &mut dyn WriteColor) -> Result<()> {
+        print_report(&self.report, &self.from, &self.to, writer)
+    }
+}
+
+fn print_report(
+    report: &SyncReport,
+    left: &str,
+    right: &str,
+    writer: &mut dyn WriteColor,
+) -> Result<()> {
+    let write_err = "cannot write sync report to writer";
+    let width = left.len().max(right.len()) + 2;
+
+    writeln!(
+        writer,
+        "{:<width$}{:>8}{:>8}{:>8}",
+        "",
+        "added",
+        "updated",
+        "deleted",
+        width = width
+    )
+    .context(write_err)?;
+    for (side, side_report) in [(left, &report.left), (right, &report.right)] {
+        writeln!(
+            writer,
+            "{:<width$}{:>8}{:>8}{:>8}",
+            side,
+            side_report.added,
+            side_report.updated,
+            side_report.deleted,
+            width = width
+        )
+        .context(write_err)?;
+    }
+
+    if !report.conflicts.is_empty() {
+        writeln!(writer, "\nconflicts: {}", report.conflicts.len()).context(write_err)?;
+        for conflict in &report.conflicts {
+            let resolution = match conflict.resolution {
+                ConflictResolution::KeepLeft => format!("kept {} version", left),
+                ConflictResolution::KeepRight => format!("kept {} version", right),
+                ConflictResolution::Delete => String::from("deleted"),
+            };
+            writeln!(writer, "  {}: {}", conflict.id, resolution).context(write_err)?;
+        }
+    }
+
+    if !report.failures.is_empty() {
+        writeln!(writer, "\nfailures: {}", report.failures.len()).context(write_err)?;
+        for failure in &report.failures {
+            writeln!(writer, "  {}: {}", failure.id, failure.error).context(write_err)?;
+        }
+    }
+
+    writeln!(
+        writer,
+        "\nsynchronized in {:.2}s",
+        report.duration.as_secs_f64()
+    )
+    .context(write_err)
+}
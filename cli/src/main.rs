@@ -42,8 +42,63 @@ fn main() -> Result<()> {
 
     // check contact commands
     match contact_args::matches(&m)? {
-        Some(contact_args::Cmd::Sync) => {
-            return contact_handlers::sync(&account_config, &mut printer);
+        Some(contact_args::Cmd::Sync(wait)) => {
+            return contact_handlers::sync(wait, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::SyncAccounts(from, to, wait)) => {
+            let from = AccountConfig::from_config_and_opt_account_name(&config, Some(&from))?;
+            let to = AccountConfig::from_config_and_opt_account_name(&config, Some(&to))?;
+            return contact_handlers::sync_accounts(wait, &from, &to, &mut printer);
+        }
+        Some(contact_args::Cmd::List(sort, limit)) => {
+            return contact_handlers::list(sort, limit, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::Search(query, sort, limit)) => {
+            return contact_handlers::search(&query, sort, limit, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::Add(template, upload)) => {
+            return contact_handlers::add(template, upload, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::Show(id, raw)) => {
+            return contact_handlers::show(&id, raw, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::Edit(id, raw, upload)) => {
+            return contact_handlers::edit(&id, raw, upload, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::Delete(id, yes, remote)) => {
+            return contact_handlers::delete(&id, yes, remote, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::Import(path, format, mappings, on_duplicate)) => {
+            return contact_handlers::import(
+                &path,
+                format,
+                mappings,
+                on_duplicate,
+                &account_config,
+                &mut printer,
+            );
+        }
+        Some(contact_args::Cmd::Export(format, query, file, split)) => {
+            return contact_handlers::export(
+                format,
+                query.as_deref(),
+                file.as_deref(),
+                split,
+                &account_config,
+                &mut printer,
+            );
+        }
+        Some(contact_args::Cmd::Convert(version, path)) => {
+            return contact_handlers::convert(version, path.as_deref());
+        }
+        Some(contact_args::Cmd::Lint(fix)) => {
+            return contact_handlers::lint(fix, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::Dedupe(min_score, list)) => {
+            return contact_handlers::dedupe(min_score, list, &account_config, &mut printer);
+        }
+        Some(contact_args::Cmd::Normalize(region)) => {
+            return contact_handlers::normalize(region, &account_config, &mut printer);
         }
         _ => (),
     }
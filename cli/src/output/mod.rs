@@ -16,3 +16,6 @@ pub use print_table::*;
 
 pub mod printer_service;
 pub use printer_service::*;
+
+pub mod table;
+pub use table::*;
@@ -1,7 +1,11 @@
 use anyhow::{anyhow, Context, Result};
 use log::debug;
 use std::{
+    collections::hash_map::RandomState,
+    env, fs,
+    hash::{BuildHasher, Hasher},
     io::prelude::*,
+    path::Path,
     process::{Command, Stdio},
 };
 
@@ -39,3 +43,48 @@ pub fn pipe_cmd(cmd: &str, data: &[u8]) -> Result<Vec<u8>> {
 
     Ok(res)
 }
+
+/// Opens the given content in the editor of the user (`$VISUAL`, then
+/// `$EDITOR`, then `vi`), and returns the edited content. The file
+/// name helps the editor to highlight the content.
+pub fn edit(content: &str, file_name: &str) -> Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let path = env::temp_dir().join(format!(
+        "cardamom-{}-{:x}-{}",
+        std::process::id(),
+        RandomState::new().build_hasher().finish(),
+        file_name
+    ));
+    write_draft(&path, content).with_context(|| format!("cannot write draft at {:?}", path))?;
+    debug!("editing {:?} with {:?}", path, editor);
+
+    // the editor can hold arguments, so it is run through the shell
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status()
+        .with_context(|| format!("cannot run editor {:?}", editor))?;
+    let edited_content =
+        fs::read_to_string(&path).with_context(|| format!("cannot read draft at {:?}", path))?;
+    let _ = fs::remove_file(&path);
+
+    if !status.success() {
+        return Err(anyhow!("editor {:?} exited with {}", editor, status));
+    }
+    Ok(edited_content)
+}
+
+/// Writes the given draft to a new file only readable by the user.
+/// The file must not exist yet, so that a file (or a symlink) planted
+/// at its path is never written.
+fn write_draft(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+    opts.open(path)?.write_all(content.as_bytes())
+}
@@ -1,6 +1,9 @@
 use anyhow::Result;
 use std::io;
 use termcolor::{self, StandardStream};
+use terminal_size::{terminal_size, Width};
+
+use crate::config::Format;
 
 pub trait WriteColor: io::Write + termcolor::WriteColor {}
 
@@ -13,3 +16,15 @@ pub trait PrintTable {
 pub struct PrintTableOpts {
     pub max_width: Option<usize>,
 }
+
+impl From<&Format> for PrintTableOpts {
+    fn from(format: &Format) -> Self {
+        let max_width = match format {
+            Format::Fixed(width) => Some(*width),
+            // outside of a terminal, the content is not restricted
+            Format::Auto => terminal_size().map(|(Width(width), _)| width as usize),
+            Format::Flowed => None,
+        };
+        Self { max_width }
+    }
+}
@@ -0,0 +1,243 @@
+//! Table module.
+//!
+//! This module contains a minimal table renderer. Columns are sized
+//! after their content, then shrinkable columns are truncated until
+//! the table fits the maximum width.
+
+use anyhow::{Context, Result};
+use termcolor::{Color, ColorSpec};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::output::{PrintTableOpts, WriteColor};
+
+/// Represents the separator between two cells.
+const SEPARATOR: &str = "│";
+/// Represents the character ending a truncated cell.
+const ELLIPSIS: char = '…';
+/// Represents the minimum width of a shrinked column.
+const MIN_SHRINKED_WIDTH: usize = 5;
+
+/// Represents a table cell.
+#[derive(Debug, Default, Clone)]
+pub struct Cell {
+    value: String,
+    shrinkable: bool,
+    style: ColorSpec,
+}
+
+impl Cell {
+    pub fn new<T: AsRef<str>>(value: T) -> Self {
+        Self {
+            // line breaks would break the rows alignment
+            value: value.as_ref().replace(&['\r', '\n', '\t'][..], " "),
+            ..Self::default()
+        }
+    }
+
+    /// Allows the cell to be truncated when the table does not fit.
+    pub fn shrinkable(mut self) -> Self {
+        self.shrinkable = true;
+        self
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.style.set_bold(true);
+        self
+    }
+
+    pub fn fg(mut self, color: Color) -> Self {
+        self.style.set_fg(Some(color));
+        self
+    }
+
+    fn width(&self) -> usize {
+        self.value.width()
+    }
+
+    /// Returns the value of the cell, truncated to the given width
+    /// then padded with spaces.
+    fn fit(&self, width: usize) -> String {
+        let mut value = String::new();
+
+        if self.width() > width {
+            let mut value_width = 0;
+            for c in self.value.chars() {
+                let char_width = c.width().unwrap_or_default();
+                if value_width + char_width >= width {
+                    break;
+                }
+                value_width += char_width;
+                value.push(c);
+            }
+            value.push(ELLIPSIS);
+            value_width += 1;
+            value.push_str(&" ".repeat(width.saturating_sub(value_width)));
+        } else {
+            value.push_str(&self.value);
+            value.push_str(&" ".repeat(width - self.width()));
+        }
+
+        value
+    }
+}
+
+/// Represents a table row.
+#[derive(Debug, Default, Clone)]
+pub struct Row(Vec<Cell>);
+
+impl Row {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cell(mut self, cell: Cell) -> Self {
+        self.0.push(cell);
+        self
+    }
+}
+
+/// Represents an item printable as a table row.
+pub trait Table
+where
+    Self: Sized,
+{
+    fn head() -> Row;
+    fn row(&self) -> Row;
+
+    /// Prints the given items as a table, with a header.
+    fn print(writer: &mut dyn WriteColor, items: &[Self], opts: PrintTableOpts) -> Result<()> {
+        let head = Self::head();
+        let rows: Vec<Row> = items.iter().map(Self::row).collect();
+        let widths = widths(&head, &rows, opts.max_width);
+        let write_err = "cannot write table to writer";
+
+        for row in [head].iter().chain(rows.iter()) {
+            for (i, (cell, width)) in row.0.iter().zip(&widths).enumerate() {
+                if i > 0 {
+                    write!(writer, "{}", SEPARATOR).context(write_err)?;
+                }
+                writer.set_color(&cell.style).context(write_err)?;
+                write!(writer, " {} ", cell.fit(*width)).context(write_err)?;
+                writer.reset().context(write_err)?;
+            }
+            writeln!(writer).context(write_err)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Computes the width of each column, so the table fits the given
+/// maximum width. Only shrinkable columns are shrinked, the widest
+/// first.
+fn widths(head: &Row, rows: &[Row], max_width: Option<usize>) -> Vec<usize> {
+    let mut widths: Vec<usize> = head.0.iter().map(Cell::width).collect();
+    let mut shrinkable = vec![false; widths.len()];
+
+    for row in rows {
+        for (i, cell) in row.0.iter().enumerate().take(widths.len()) {
+            widths[i] = widths[i].max(cell.width());
+            shrinkable[i] |= cell.shrinkable;
+        }
+    }
+
+    if let Some(max_width) = max_width {
+        // each cell is surrounded by spaces, and cells are separated
+        let decorations_width = widths.len() * 3 - 1;
+        let mut table_width = widths.iter().sum::<usize>() + decorations_width;
+
+        while table_width > max_width {
+            let widest = (0..widths.len())
+                .filter(|i| shrinkable[*i] && widths[*i] > MIN_SHRINKED_WIDTH)
+                .max_by_key(|i| (widths[*i], usize::MAX - i));
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+            table_width -= 1;
+        }
+    }
+
+    widths
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct StringWriter(Vec<u8>);
+
+    impl io::Write for StringWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl termcolor::WriteColor for StringWriter {
+        fn supports_color(&self) -> bool {
+            false
+        }
+
+        fn set_color(&mut self, _spec: &ColorSpec) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn reset(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl WriteColor for StringWriter {}
+
+    struct Item(&'static str, &'static str);
+
+    impl Table for Item {
+        fn head() -> Row {
+            Row::new().cell(Cell::new("ID")).cell(Cell::new("NAME"))
+        }
+
+        fn row(&self) -> Row {
+            Row::new()
+                .cell(Cell::new(self.0))
+                .cell(Cell::new(self.1).shrinkable())
+        }
+    }
+
+    fn print(max_width: Option<usize>) -> String {
+        let mut writer = StringWriter::default();
+        let items = [Item("1", "Émilie Dupont"), Item("22", "日本")];
+        Item::print(&mut writer, &items, PrintTableOpts { max_width }).unwrap();
+        String::from_utf8(writer.0).unwrap()
+    }
+
+    #[test]
+    fn print_table() {
+        assert_eq!(
+            concat!(
+                " ID │ NAME          \n",
+                " 1  │ Émilie Dupont \n",
+                " 22 │ 日本          \n",
+            ),
+            print(None)
+        );
+    }
+
+    #[test]
+    fn print_shrinked_table() {
+        assert_eq!(
+            concat!(
+                " ID │ NAME     \n",
+                " 1  │ Émilie … \n",
+                " 22 │ 日本     \n",
+            ),
+            print(Some(15))
+        );
+    }
+}
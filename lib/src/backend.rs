@@ -0,0 +1,230 @@
+//! Backend module
+//!
+//! This module contains the storage backend abstraction. A backend
+//! only knows how to list, get, put and delete cards. The [`Replica`]
+//! adds to a backend everything the sync needs (the cached cards and
+//! the current ones), so any two backends can be synchronized.
+
+use chrono::{DateTime, Local};
+use log::debug;
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    cache::CachedCards,
+    card::{href_from_id, Card, Cards, CardsMap},
+    card_repository::CardRepository,
+    error::*,
+};
+
+/// Represents a storage backend of cards.
+pub trait Backend: Sync {
+    /// Lists all the cards of the backend. Each card carries its
+    /// location (href) and its version: its entity tag when the
+    /// backend has one, its date otherwise.
+    fn list(&self) -> Result<CardsMap>;
+
+    /// Lists all the cards of the backend, including the ones sharing
+    /// their id with another card (which [`Backend::list`] drops).
+    fn list_all(&self) -> Result<Vec<Card>> {
+        Ok(self.list()?.into_values().collect())
+    }
+
+    /// Lists the location of all the cards of the backend along with
+    /// their date, without reading them when the backend allows it.
+    fn list_dates(&self) -> Result<HashMap<String, DateTime<Local>>> {
+        Ok(self
+            .list()?
+            .into_values()
+            .filter_map(|card| Some((card.href?, card.date)))
+            .collect())
+    }
+
+    /// Gets the card at the given location.
+    fn get(&self, href: &str) -> Result<Card>;
+
+    /// Creates or replaces the given card at its location, or at a
+    /// location derived from its id if it has none. A card with an
+    /// entity tag replaces this very version, a card without location
+    /// never overwrites an existing one. The card is then updated with
+    /// its new location and version.
+    fn put(&self, card: &mut Card) -> Result<()>;
+
+    /// Deletes the given card at its location.
+    fn delete(&self, card: &Card) -> Result<()>;
+
+    /// Returns the maximum number of cards which can be written to
+    /// the backend at the same time.
+    fn concurrency(&self) -> usize {
+        1
+    }
+
+    /// Prepares the cache of the backend before a sync. Backends can
+    /// store metadata in it, or reset it if it does not match them
+    /// anymore.
+    fn prepare_cache(&self, _cache: &mut CachedCards) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Represents a backend along with its cached cards and its current
+/// cards, ready to be synchronized with another one.
+#[derive(Debug)]
+pub struct Replica<B> {
+    backend: B,
+    pub cache: CachedCards,
+    next: CardsMap,
+}
+
+impl<B: Backend> Replica<B> {
+    pub fn new(backend: B, cache_path: PathBuf) -> Result<Self> {
+        let mut cache = CachedCards::new(cache_path)?;
+        backend.prepare_cache(&mut cache)?;
+        let mut next = backend.list()?;
+
+        // a card with the same version as the cached one did not
+        // change, it keeps its cached date so it is not considered as
+        // a new version of the card
+        for card in next.values_mut() {
+            if let Some(cached_card) = cache.cards.get(&card.id) {
+                if cached_card.href != card.href {
+                    debug!(
+                        "card {:?} moved from {:?} to {:?}",
+                        card.id, cached_card.href, card.href
+                    );
+                }
+                let unchanged = match (&card.etag, &cached_card.etag) {
+                    (Some(etag), Some(cached_etag)) => etag == cached_etag,
+                    _ => card.content == cached_card.content,
+                };
+                if unchanged {
+                    card.date = cached_card.date;
+                }
+            }
+        }
+
+        Ok(Self {
+            backend,
+            cache,
+            next,
+        })
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns the known location of the given card. Cards are not
+    /// necessarily located after their id, so the current location
+    /// is used first, then the cached one.
+    fn href(&self, id: &str) -> Option<String> {
+        self.next
+            .get(id)
+            .or_else(|| self.cache.cards.get(id))
+            .and_then(|card| card.href.to_owned())
+    }
+
+    /// Replaces the location and the version of the given card, which
+    /// can come from another backend, by the ones of this backend.
+    fn locate(&self, card: &mut Card) {
+        card.href = self.href(&card.id);
+        card.etag = self
+            .next
+            .get(&card.id)
+            .and_then(|card| card.etag.to_owned());
+    }
+}
+
+impl<B> Cards for Replica<B> {
+    fn prev(&self) -> &CardsMap {
+        &self.cache.cards
+    }
+
+    fn next(&self) -> &CardsMap {
+        &self.next
+    }
+}
+
+impl<B: Backend> CardRepository for Replica<B> {
+    fn insert(&self, card: &mut Card) -> Result<()> {
+        self.locate(card);
+        self.backend.put(card)
+    }
+
+    fn select(&self, id: &str) -> Result<Card> {
+        let href = self.href(id).unwrap_or_else(|| href_from_id(id));
+        self.backend.get(&href)
+    }
+
+    fn select_all(&self) -> Result<CardsMap> {
+        self.backend.list()
+    }
+
+    fn update(&self, card: &mut Card) -> Result<()> {
+        self.locate(card);
+        self.backend.put(card)
+    }
+
+    fn delete(&self, card: &Card) -> Result<()> {
+        let mut card = card.to_owned();
+        self.locate(&mut card);
+        self.backend.delete(&card)
+    }
+
+    fn concurrency(&self) -> usize {
+        self.backend.concurrency()
+    }
+}
+
+impl<B> AsMut<CachedCards> for Replica<B> {
+    fn as_mut(&mut self) -> &mut CachedCards {
+        &mut self.cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use crate::{journal::Journal, local::LocalCards, sync::Patch};
+
+    use super::*;
+
+    fn replica(dir: &Path) -> Replica<LocalCards> {
+        Replica::new(LocalCards::new(dir.to_owned()), dir.join(".cache")).unwrap()
+    }
+
+    #[test]
+    fn sync_two_local_backends() {
+        let dir = env::temp_dir().join("cardamom-backend-sync");
+        let _ = fs::remove_dir_all(&dir);
+        let (left_dir, right_dir) = (dir.join("left"), dir.join("right"));
+        fs::create_dir_all(&left_dir).unwrap();
+        fs::create_dir_all(&right_dir).unwrap();
+        fs::write(
+            left_dir.join("alice.vcf"),
+            "BEGIN:VCARD\r\nUID:uid\r\nFN:Alice\r\nEND:VCARD\r\n",
+        )
+        .unwrap();
+
+        let mut left = replica(&left_dir);
+        let mut right = replica(&right_dir);
+        let mut journal = Journal::new(dir.join(".journal")).unwrap();
+        let report = Patch::new(&left, &right)
+            .apply(&mut left, &mut right, &mut journal)
+            .unwrap();
+
+        // the card is written after its UID on the right side, but
+        // keeps its file name on the left side
+        assert_eq!(1, report.right.added);
+        assert!(right_dir.join("uid.vcf").exists());
+        assert_eq!(
+            Some("alice.vcf".into()),
+            left.cache.cards.get("uid").unwrap().href
+        );
+
+        // nothing changed since the last sync
+        let left = replica(&left_dir);
+        let right = replica(&right_dir);
+        assert_eq!(0, Patch::new(&left, &right).iter().count());
+    }
+}
@@ -1,10 +1,44 @@
-use std::{collections::HashMap, fs, io::Read, path::PathBuf};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
 
-use crate::{card::CardsMap, error::*};
+use crate::{
+    card::{href_from_id, Card, CardsMap},
+    error::*,
+};
+
+/// Represents the current version of the cache format. The version 0
+/// represents the legacy format, where the cache was a bare map of
+/// cards.
+pub const CACHE_VERSION: u32 = 2;
+
+/// Represents the cache as it is written to the disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CacheFile {
+    version: u32,
+    addressbook_url: Option<String>,
+    cards: HashMap<String, CachedCard>,
+}
+
+/// Represents a cached card along with the hash of its content.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedCard {
+    #[serde(flatten)]
+    card: Card,
+    hash: String,
+}
 
 #[derive(Debug, Default)]
 pub struct CachedCards {
     path: PathBuf,
+    /// Represents the URL of the addressbook the cards belong to.
+    pub addressbook_url: Option<String>,
     pub cards: CardsMap,
 }
 
@@ -21,24 +55,225 @@ impl CachedCards {
             .read_to_end(&mut cache_buff)
             .map_err(|e| CardamomError::ReadCachedCardsError(path.clone(), e))?;
 
-        let cards = if cache_buff.is_empty() {
-            HashMap::default()
+        let cache = if cache_buff.is_empty() {
+            CacheFile::default()
         } else {
-            serde_json::from_slice(&cache_buff)
+            let cache: serde_json::Value = serde_json::from_slice(&cache_buff)
+                .map_err(|e| CardamomError::ParseCachedCardsError(path.clone(), e))?;
+            let version = cache.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+            debug!("cache version at {:?}: {}", path, version);
+            // a cache written by a newer version may not be understood,
+            // saving it back would lose what it holds
+            if version > CACHE_VERSION {
+                return Err(CardamomError::UnsupportedCacheVersionError(
+                    path,
+                    version,
+                    CACHE_VERSION,
+                ));
+            }
+            migrate(version, cache)
                 .map_err(|e| CardamomError::ParseCachedCardsError(path.clone(), e))?
         };
 
-        Ok(Self { path, cards })
+        Ok(Self {
+            path,
+            addressbook_url: cache.addressbook_url,
+            cards: cache
+                .cards
+                .into_iter()
+                .map(|(id, cached_card)| (id, cached_card.card))
+                .collect(),
+        })
     }
 
+    /// Saves the cached cards atomically: the cards are written to a
+    /// temporary file which is then renamed over the cache, so a
+    /// crash never leaves a truncated or half-written cache behind.
     pub fn save(&self) -> Result<()> {
-        let cache_writer = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&self.path)
-            .map_err(|e| CardamomError::ReadCachedCardsError(self.path.clone(), e))?;
-        serde_json::to_writer(cache_writer, &self.cards)
-            .map_err(|e| CardamomError::ParseCachedCardsError(self.path.clone(), e))
+        let cache = CacheFile {
+            version: CACHE_VERSION,
+            addressbook_url: self.addressbook_url.clone(),
+            cards: self
+                .cards
+                .iter()
+                .map(|(id, card)| {
+                    let cached_card = CachedCard {
+                        hash: card.hash(),
+                        card: card.to_owned(),
+                    };
+                    (id.to_owned(), cached_card)
+                })
+                .collect(),
+        };
+        let cache_buff = serde_json::to_vec(&cache)
+            .map_err(|e| CardamomError::ParseCachedCardsError(self.path.clone(), e))?;
+        write_atomically(&self.path, &cache_buff)
+            .map_err(|e| CardamomError::WriteCachedCardsError(self.path.clone(), e))
+    }
+}
+
+/// Migrates a cache of the given version to the current format.
+fn migrate(version: u32, cache: serde_json::Value) -> serde_json::Result<CacheFile> {
+    let mut cache = match version {
+        // the legacy cache is a bare map of cards, their dates are
+        // RFC 2822 strings which are still understood by the date
+        // parser
+        0 => {
+            info!("migrating cache from version 0 to 1");
+            let cards: CardsMap = serde_json::from_value(cache)?;
+            CacheFile {
+                version: 1,
+                cards: cards
+                    .into_iter()
+                    .map(|(id, card)| {
+                        let cached_card = CachedCard {
+                            hash: card.hash(),
+                            card,
+                        };
+                        (id, cached_card)
+                    })
+                    .collect(),
+                ..CacheFile::default()
+            }
+        }
+        _ => serde_json::from_value(cache)?,
+    };
+
+    // cards used to be identified by their location, they are now
+    // identified by their UID
+    if cache.version < 2 {
+        info!("migrating cache from version 1 to 2");
+        cache.cards = cache
+            .cards
+            .into_iter()
+            .map(|(id, mut cached_card)| {
+                if cached_card.card.href.is_none() {
+                    cached_card.card.href = Some(href_from_id(&id));
+                }
+                let id = cached_card.card.uid().unwrap_or(id);
+                cached_card.card.id = id.clone();
+                (id, cached_card)
+            })
+            .collect();
+        cache.version = 2;
+    }
+
+    Ok(cache)
+}
+
+/// Writes the given content to a temporary file next to the target
+/// path, flushes it to the disk then renames it over the target.
+pub(crate) fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut tmp_file_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_file_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_file_name);
+
+    let mut tmp_file = fs::File::create(&tmp_path)?;
+    tmp_file.write_all(content)?;
+    tmp_file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    // the rename itself is only durable once the parent directory
+    // is synced as well
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn save_truncates_previous_content() {
+        let dir = env::temp_dir().join("cardamom-cache-save-truncates");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".local");
+        fs::write(&path, "x".repeat(1024)).unwrap();
+
+        let cache = CachedCards {
+            path: path.clone(),
+            ..CachedCards::default()
+        };
+        cache.save().unwrap();
+
+        assert!(fs::read_to_string(&path).unwrap().ends_with('}'));
+        assert!(!dir.join(".local.tmp").exists());
+        assert!(CachedCards::new(path).unwrap().cards.is_empty());
+    }
+
+    #[test]
+    fn migrate_legacy_cache() {
+        let dir = env::temp_dir().join("cardamom-cache-migrate-legacy");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".remote");
+        fs::write(
+            &path,
+            r#"{"id":{"id":"id","date":"Sun, 19 Jan 2020 10:00:00 +0000","content":"BEGIN:VCARD\nUID:uid\nEND:VCARD"}}"#,
+        )
+        .unwrap();
+
+        // cards are now indexed by their UID
+        let mut cache = CachedCards::new(path.clone()).unwrap();
+        let card = cache.cards.get("uid").unwrap().to_owned();
+        assert_eq!("uid", card.id);
+        assert_eq!(Some("id.vcf".into()), card.href);
+        assert_eq!(None, card.etag);
+
+        // dates are saved back with their sub-second precision
+        cache.addressbook_url = Some("https://example.org/contacts/".into());
+        cache.cards.get_mut("uid").unwrap().date =
+            DateTime::parse_from_rfc3339("2020-01-19T10:00:00.123+00:00")
+                .unwrap()
+                .into();
+        cache.save().unwrap();
+
+        let cache_file: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(Some(CACHE_VERSION as u64), cache_file["version"].as_u64());
+        assert_eq!(
+            Some(card.hash().as_str()),
+            cache_file["cards"]["uid"]["hash"].as_str()
+        );
+
+        let cache = CachedCards::new(path).unwrap();
+        assert_eq!(
+            Some("https://example.org/contacts/".into()),
+            cache.addressbook_url
+        );
+        assert_eq!(
+            123,
+            cache
+                .cards
+                .get("uid")
+                .unwrap()
+                .date
+                .timestamp_subsec_millis()
+        );
+    }
+
+    #[test]
+    fn reject_newer_cache() {
+        let dir = env::temp_dir().join("cardamom-cache-reject-newer");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".local");
+        let content = format!(r#"{{"version":{},"cards":{{}}}}"#, CACHE_VERSION + 1);
+        fs::write(&path, &content).unwrap();
+
+        match CachedCards::new(path.clone()) {
+            Err(CardamomError::UnsupportedCacheVersionError(_, version, supported)) => {
+                assert_eq!(CACHE_VERSION + 1, version);
+                assert_eq!(CACHE_VERSION, supported);
+            }
+            res => panic!("unexpected result: {:?}", res.map(|cache| cache.cards)),
+        }
+
+        // the cache is left untouched
+        assert_eq!(content, fs::read_to_string(&path).unwrap());
     }
 }
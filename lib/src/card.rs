@@ -1,9 +1,12 @@
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 
-use crate::card_parsers::date_parser;
+use crate::{card_parsers::date_parser, vcard};
 
+/// Represents a map of cards indexed by their identity, which is the
+/// UID of the card (or its location when the card has no UID).
 pub type CardsMap = HashMap<String, Card>;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -12,6 +15,43 @@ pub struct Card {
     #[serde(with = "date_parser")]
     pub date: DateTime<Local>,
     pub content: String,
+    /// Represents the entity tag of the card, when the card comes
+    /// from a repository supporting them.
+    #[serde(default)]
+    pub etag: Option<String>,
+    /// Represents the location of the card inside its repository.
+    #[serde(default)]
+    pub href: Option<String>,
+}
+
+impl Card {
+    /// Returns the UID of the card, if any.
+    pub fn uid(&self) -> Option<String> {
+        vcard::uid(&self.content)
+    }
+
+    /// Computes the SHA-256 hash of the card content, as a lowercase
+    /// hexadecimal string.
+    pub fn hash(&self) -> String {
+        Sha256::digest(self.content.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Builds the default location of a card inside a repository from its
+/// identity, for cards that do not exist yet in this repository.
+pub fn href_from_id(id: &str) -> String {
+    let id = id.trim_start_matches("urn:uuid:");
+    let file_stem: String = id
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '@' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}.vcf", file_stem)
 }
 
 pub trait Cards {
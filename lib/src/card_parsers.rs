@@ -1,20 +1,26 @@
 pub mod date_parser {
-    use chrono::{DateTime, Local};
+    use chrono::{DateTime, Local, SecondsFormat};
     use serde::{self, Deserialize, Deserializer, Serializer};
 
+    /// Serializes dates as RFC 3339 strings, keeping the sub-second
+    /// precision so that two edits made within the same second can
+    /// still be ordered.
     pub fn serialize<S>(date: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_str(&date.to_rfc2822())
+        serializer.serialize_str(&date.to_rfc3339_opts(SecondsFormat::AutoSi, true))
     }
 
+    /// Deserializes dates from RFC 3339 strings, or from RFC 2822
+    /// strings as written by older caches.
     pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Local>, D::Error>
     where
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        DateTime::parse_from_rfc2822(&s)
+        DateTime::parse_from_rfc3339(&s)
+            .or_else(|_| DateTime::parse_from_rfc2822(&s))
             .map(|d| d.into())
             .map_err(serde::de::Error::custom)
     }
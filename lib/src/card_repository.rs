@@ -1,12 +1,18 @@
 use crate::{
-    card::{Card, Cards},
+    card::{Card, CardsMap},
     error::*,
 };
 
 pub trait CardRepository {
     fn insert(&self, card: &mut Card) -> Result<()>;
     fn select(&self, id: &str) -> Result<Card>;
-    fn select_all(&self) -> Result<Cards>;
+    fn select_all(&self) -> Result<CardsMap>;
     fn update(&self, card: &mut Card) -> Result<()>;
     fn delete(&self, card: &Card) -> Result<()>;
+
+    /// Returns the maximum number of cards which can be written to
+    /// the repository at the same time.
+    fn concurrency(&self) -> usize {
+        1
+    }
 }
@@ -2,192 +2,172 @@
 //!
 //! This module contains everything to interact with CardDAV servers.
 
-use log::trace;
-use quick_xml::de as xml;
-use reqwest::{blocking::Client, Method};
+use chrono::{DateTime, Utc};
+use reqwest::{Method, StatusCode};
 use serde::Deserialize;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+use tokio::runtime::{self, Runtime};
 use url::Url;
 
-use crate::error::*;
+use crate::{error::*, nonblocking::AsyncCardDavClient};
+
+/// Represents the retry policy of CardDAV requests. Only idempotent
+/// requests and conditional uploads are retried, after a delay
+/// growing exponentially with the number of attempts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Represents the maximum number of attempts of a request,
+    /// including the first one.
+    pub max_attempts: u32,
+    /// Represents the delay before the first retry.
+    pub base_delay: Duration,
+    /// Represents the maximum delay between two attempts, including
+    /// the one requested by the server via `Retry-After`.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay to wait after the given failed attempt. The
+    /// delay requested by the server takes precedence, otherwise the
+    /// exponential delay is randomized between its half and itself,
+    /// so clients do not retry all at once.
+    pub(crate) fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        let half_delay = delay / 2;
+        let jitter =
+            RandomState::new().build_hasher().finish() % (half_delay.as_millis() as u64 + 1);
+        half_delay + Duration::from_millis(jitter)
+    }
+}
+
+/// Represents the precondition of a card upload, based on its entity
+/// tag. Conditional uploads cannot overwrite concurrent changes,
+/// which makes them safe to retry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Precondition {
+    /// Uploads the card only if its entity tag matches the given one.
+    IfMatch(String),
+    /// Uploads the card only if it does not exist yet.
+    IfNoneMatch,
+}
 
+/// Represents the blocking CardDAV client. It runs the
+/// [`AsyncCardDavClient`] on a runtime of its own.
 #[derive(Debug)]
 pub struct CardDavClient {
-    client: Client,
-    root_url: Url,
-    current_user_principal_url: Url,
-    addressbook_home_set_url: Url,
-    addressbook_url: Url,
-    login: String,
-    passwd: String,
+    runtime: Runtime,
+    client: AsyncCardDavClient,
 }
 
 impl CardDavClient {
-    pub fn new(host: String, port: u16, login: String, passwd: String) -> Result<Self> {
-        let root_url = format!("https://{}:{}", host, port);
-        let root_url =
-            Url::parse(&root_url).map_err(|e| CardamomError::ParseCardDavUrlError(root_url, e))?;
-
-        let mut client = Self {
-            client: Client::new(),
-            current_user_principal_url: root_url.clone(),
-            addressbook_home_set_url: root_url.clone(),
-            addressbook_url: root_url.clone(),
-            root_url,
-            login,
-            passwd,
-        };
-
-        client.update_current_user_principal_url()?;
-        client.update_addressbook_home_set_url()?;
-        client.update_addressbook_url()?;
-
-        Ok(client)
+    pub fn new(
+        host: String,
+        port: u16,
+        login: String,
+        passwd: String,
+        retry: RetryPolicy,
+    ) -> Result<Self> {
+        let runtime = runtime()?;
+        let client = runtime.block_on(AsyncCardDavClient::new(host, port, login, passwd, retry))?;
+        Ok(Self { runtime, client })
     }
 
-    fn update_current_user_principal_url(&mut self) -> Result<()> {
-        let res = self
-            .client
-            .request(propfind()?, self.root_url.to_string())
-            .basic_auth(&self.login, Some(&self.passwd))
-            .header("Content-Type", "application/xml; charset=utf-8")
-            .header("Depth", "0")
-            .body(
-                r#"
-                <propfind xmlns="DAV:">
-                    <prop>
-                        <current-user-principal />
-                    </prop>
-                </propfind>
-                "#,
-            )
-            .send()
-            .map_err(CardamomError::FetchCurrentUserPrincipalUrlError)?;
-        let res = res
-            .text()
-            .map_err(CardamomError::FetchCurrentUserPrincipalUrlError)?;
-        trace!("current user principal url response: {}", res);
-        let res: Multistatus<CurrentUserPrincipalProp> =
-            xml::from_str(&res).map_err(CardamomError::ParseCurrentUserPrincipalUrlError)?;
-        let path = res
-            .responses
-            .first()
-            .and_then(|res| res.propstat.first())
-            .map(|propstat| propstat.prop.current_user_principal.href.to_owned())
-            .unwrap_or_else(|| self.root_url.path().to_owned());
-        self.current_user_principal_url.set_path(&path);
-        self.addressbook_home_set_url.set_path(&path);
-        self.addressbook_url.set_path(&path);
-        Ok(())
+    pub fn fetch_address_data(&self) -> Result<Multistatus<AddressDataProp>> {
+        self.runtime.block_on(self.client.fetch_address_data())
     }
 
-    fn update_addressbook_home_set_url(&mut self) -> Result<()> {
-        let res = self
-            .client
-            .request(propfind()?, self.current_user_principal_url.to_string())
-            .basic_auth(&self.login, Some(&self.passwd))
-            .header("Content-Type", "application/xml; charset=utf-8")
-            .header("Depth", "0")
-            .body(
-                r#"
-                <propfind xmlns="DAV:" xmlns:c="urn:ietf:params:xml:ns:carddav">
-                    <prop>
-                        <c:addressbook-home-set />
-                    </prop>
-                </propfind>
-                "#,
-            )
-            .send()
-            .map_err(CardamomError::FetchAddressbookHomeSetUrlError)?;
-        let res = res
-            .text()
-            .map_err(CardamomError::FetchAddressbookHomeSetUrlError)?;
-        trace!("addressbook home set url response: {}", res);
-        let res: Multistatus<AddressbookHomeSetProp> =
-            xml::from_str(&res).map_err(CardamomError::ParseAddressbookHomeSetUrlError)?;
-        let path = res
-            .responses
-            .first()
-            .and_then(|res| res.propstat.first())
-            .map(|propstat| propstat.prop.addressbook_home_set.href.to_owned())
-            .unwrap_or_else(|| self.current_user_principal_url.path().to_owned());
-        self.addressbook_home_set_url.set_path(&path);
-        self.addressbook_url.set_path(&path);
-        Ok(())
+    /// Returns the URL of the addressbook.
+    pub fn addressbook_url(&self) -> &Url {
+        self.client.addressbook_url()
     }
 
-    fn update_addressbook_url(&mut self) -> Result<()> {
-        let res = self
-            .client
-            .request(propfind()?, self.addressbook_home_set_url.to_string())
-            .basic_auth(&self.login, Some(&self.passwd))
-            .header("Content-Type", "application/xml; charset=utf-8")
-            .header("Depth", "1")
-            .body(
-                r#"
-                <propfind xmlns="DAV:">
-                    <prop>
-                        <resourcetype />
-                    </prop>
-                </propfind>
-                "#,
-            )
-            .send()
-            .map_err(CardamomError::FetchAddressbookUrlError)?;
-        let res = res
-            .text()
-            .map_err(CardamomError::FetchAddressbookUrlError)?;
-        trace!("addressbook url response: {}", res);
-        let res: Multistatus<AddressbookProp> =
-            xml::from_str(&res).map_err(CardamomError::ParseAddressbookUrlError)?;
-        let path = res
-            .responses
-            .iter()
-            .find_map(|res| {
-                res.propstat
-                    .iter()
-                    .find(|propstat| {
-                        let valid_status = propstat
-                            .status
-                            .as_ref()
-                            .map(|s| s.ends_with("200 OK"))
-                            .unwrap_or(false);
-                        let has_addressbook =
-                            propstat.prop.resourcetype.addressbook.as_ref().is_some();
-                        valid_status && has_addressbook
-                    })
-                    .map(|_| res.href.to_owned())
-            })
-            .unwrap_or_else(|| self.addressbook_home_set_url.path().to_owned());
-        self.addressbook_url.set_path(&path);
-        Ok(())
+    /// Fetches the vCard content of the card at the given href.
+    pub fn fetch_card(&self, href: &str) -> Result<CardResponse> {
+        self.runtime.block_on(self.client.fetch_card(href))
     }
 
-    pub fn fetch_address_data(&self) -> Result<Multistatus<AddressDataProp>> {
-        let res = self
-            .client
-            .request(report()?, self.addressbook_url.to_string())
-            .basic_auth(&self.login, Some(&self.passwd))
-            .header("Content-Type", "application/xml; charset=utf-8")
-            .header("Depth", "1")
-            .body(
-                r#"
-                <c:addressbook-query xmlns="DAV:" xmlns:c="urn:ietf:params:xml:ns:carddav">
-                    <prop>
-                        <getetag />
-                        <getlastmodified />
-                        <c:address-data />
-                    </prop>
-                </c:addressbook-query>
-                "#,
-            )
-            .send()
-            .map_err(CardamomError::FetchAddressDataError)?;
-        let res = res.text().map_err(CardamomError::FetchAddressDataError)?;
-        trace!("address data response: {}", res);
-        xml::from_str(&res).map_err(CardamomError::ParseAddressDataError)
+    /// Creates or replaces the card at the given href.
+    pub fn put_card(
+        &self,
+        href: &str,
+        content: &str,
+        precondition: Option<&Precondition>,
+    ) -> Result<CardResponse> {
+        self.runtime
+            .block_on(self.client.put_card(href, content, precondition))
+    }
+
+    /// Deletes the card at the given href, only if its entity tag
+    /// matches the given one (if any).
+    pub fn delete_card(&self, href: &str, etag: Option<&str>) -> Result<()> {
+        self.runtime.block_on(self.client.delete_card(href, etag))
+    }
+}
+
+/// Builds the runtime of the blocking wrappers. A single-threaded
+/// runtime is enough, since the wrappers wait for each request. It
+/// can still be driven by several threads at the same time.
+pub(crate) fn runtime() -> Result<Runtime> {
+    runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(CardamomError::BuildRuntimeError)
+}
+
+/// Returns true if the given status means that the server cannot
+/// handle the request for now, but could later.
+pub(crate) fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+pub(crate) fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => DateTime::parse_from_rfc2822(value).ok().map(|date| {
+            (date.with_timezone(&Utc) - now)
+                .to_std()
+                .unwrap_or_default()
+        }),
     }
 }
 
+/// Represents the response of a single card request.
+#[derive(Debug)]
+pub struct CardResponse {
+    pub href: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content: String,
+}
+
 /// Represents the CardDAV response wrapper. The CardDAV response
 /// wraps multiple `response` in a single `multistatus`.
 ///
@@ -252,12 +232,12 @@ pub struct Propstat<T> {
 
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
-struct CurrentUserPrincipalProp {
+pub(crate) struct CurrentUserPrincipalProp {
     pub current_user_principal: CurrentUserPrincipal,
 }
 
 #[derive(Debug, Default, Deserialize)]
-struct CurrentUserPrincipal {
+pub(crate) struct CurrentUserPrincipal {
     pub href: String,
 }
 
@@ -265,12 +245,12 @@ struct CurrentUserPrincipal {
 
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
-struct AddressbookHomeSetProp {
+pub(crate) struct AddressbookHomeSetProp {
     pub addressbook_home_set: AddressbookHomeSet,
 }
 
 #[derive(Debug, Default, Deserialize)]
-struct AddressbookHomeSet {
+pub(crate) struct AddressbookHomeSet {
     pub href: String,
 }
 
@@ -278,17 +258,17 @@ struct AddressbookHomeSet {
 
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
-struct AddressbookProp {
+pub(crate) struct AddressbookProp {
     pub resourcetype: AddressbookResourceType,
 }
 
 #[derive(Debug, Default, Deserialize)]
-struct AddressbookResourceType {
+pub(crate) struct AddressbookResourceType {
     pub addressbook: Option<Addressbook>,
 }
 
 #[derive(Debug, Default, Deserialize)]
-struct Addressbook {}
+pub(crate) struct Addressbook {}
 
 // Address data structs
 
@@ -309,7 +289,7 @@ pub struct CtagProp {
 
 // Methods
 
-fn propfind() -> Result<Method> {
+pub(crate) fn propfind() -> Result<Method> {
     Method::from_bytes(b"PROPFIND").map_err(|_| CardamomError::UnknownError)
 }
 
@@ -424,4 +404,45 @@ mod tests {
                 .map(|s| s.as_ref())
         );
     }
+
+    #[test]
+    fn retry_delay() {
+        let retry = RetryPolicy::default();
+
+        for attempt in 1..=3 {
+            let max_delay = Duration::from_millis(500 * 2u64.pow(attempt - 1));
+            let delay = retry.delay(attempt, None);
+            assert!(delay >= max_delay / 2 && delay <= max_delay);
+        }
+        assert!(retry.delay(20, None) <= retry.max_delay);
+        assert_eq!(
+            Duration::from_secs(2),
+            retry.delay(1, Some(Duration::from_secs(2)))
+        );
+        assert_eq!(
+            retry.max_delay,
+            retry.delay(1, Some(Duration::from_secs(3600)))
+        );
+    }
+
+    #[test]
+    fn retry_after_header() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(
+            Some(Duration::from_secs(120)),
+            parse_retry_after("120", now)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(0)),
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now)
+        );
+        assert_eq!(None, parse_retry_after("soon", now));
+    }
 }
@@ -0,0 +1,874 @@
+//! Contact module
+//!
+//! This module contains the human side of a card: the fields a user
+//! cares about, parsed and unescaped from the raw vCard content.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    card::Card,
+    phone::{phone_key, Region},
+    search::{normalize, phone_digits},
+    vcard::{self, Property},
+};
+
+/// Represents the properties a contact is made of. Other properties
+/// of a card are unknown to the contact.
+const CONTACT_PROPS: [&str; 10] = [
+    "FN",
+    "N",
+    "EMAIL",
+    "TEL",
+    "ADR",
+    "ORG",
+    "BDAY",
+    "NOTE",
+    "CATEGORIES",
+    "URL",
+];
+
+/// Represents the properties of which only the first value belongs
+/// to the contact.
+const SINGLE_PROPS: [&str; 4] = ["FN", "N", "ORG", "BDAY"];
+
+/// Represents what becomes of a line of a card when the contact is
+/// written over it.
+enum LineUpdate {
+    Keep,
+    Replace(Property),
+    Remove,
+}
+
+/// Represents a typed value of a contact, like an email address or a
+/// phone number.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TypedValue {
+    /// Represents the types of the value (home, work, cell…), in
+    /// lowercase.
+    pub types: Vec<String>,
+    /// Represents the preference of the value, from 1 (most
+    /// preferred) to 100.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pref: Option<u8>,
+    pub value: String,
+}
+
+impl TypedValue {
+    fn from_property(prop: &vcard::Property) -> Self {
+        let (types, pref) = types_and_pref(prop);
+        Self {
+            types,
+            pref,
+            value: vcard::unescape(&prop.value),
+        }
+    }
+}
+
+/// Builds the parameters of a property from the given types and
+/// preference.
+fn params(types: &[String], pref: Option<u8>) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    if !types.is_empty() {
+        params.push((String::from("TYPE"), types.join(",")));
+    }
+    if let Some(pref) = pref {
+        params.push((String::from("PREF"), pref.to_string()));
+    }
+    params
+}
+
+/// Represents the structured name of a contact.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Name {
+    pub family: String,
+    pub given: String,
+    pub additional: String,
+    pub prefixes: String,
+    pub suffixes: String,
+}
+
+impl Name {
+    fn from_property(prop: &vcard::Property) -> Self {
+        let mut components = vcard::components(&prop.value).into_iter();
+        let mut next = || components.next().unwrap_or_default();
+        Self {
+            family: next(),
+            given: next(),
+            additional: next(),
+            prefixes: next(),
+            suffixes: next(),
+        }
+    }
+
+    /// Builds a structured name from a formatted one: the last word is
+    /// the family name, the first word the given name, and the words
+    /// in between the additional names.
+    pub fn from_formatted(name: &str) -> Self {
+        let mut words: Vec<&str> = name.split_whitespace().collect();
+        let family = if words.len() > 1 {
+            words.pop().unwrap_or_default()
+        } else {
+            ""
+        };
+        let given = if words.is_empty() {
+            ""
+        } else {
+            words.remove(0)
+        };
+        Self {
+            family: family.to_owned(),
+            given: given.to_owned(),
+            additional: words.join(" "),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the name as it is usually written, or nothing if all
+    /// its components are empty.
+    pub fn display(&self) -> Option<String> {
+        let name = [
+            &self.prefixes,
+            &self.given,
+            &self.additional,
+            &self.family,
+            &self.suffixes,
+        ]
+        .iter()
+        .map(|component| component.trim())
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+        Some(name).filter(|name| !name.is_empty())
+    }
+}
+
+/// Represents a postal address of a contact.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Address {
+    pub types: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pref: Option<u8>,
+    pub po_box: String,
+    pub extended: String,
+    pub street: String,
+    pub locality: String,
+    pub region: String,
+    pub postal_code: String,
+    pub country: String,
+}
+
+impl Address {
+    fn from_property(prop: &vcard::Property) -> Self {
+        let (types, pref) = types_and_pref(prop);
+        let mut components = vcard::components(&prop.value).into_iter();
+        let mut next = || components.next().unwrap_or_default();
+        Self {
+            types,
+            pref,
+            po_box: next(),
+            extended: next(),
+            street: next(),
+            locality: next(),
+            region: next(),
+            postal_code: next(),
+            country: next(),
+        }
+    }
+
+    /// Returns the non-empty lines of the address, as written on an
+    /// envelope.
+    pub fn lines(&self) -> Vec<String> {
+        let city = [&self.postal_code, &self.locality]
+            .iter()
+            .map(|component| component.trim())
+            .filter(|component| !component.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        [
+            self.street.trim(),
+            self.extended.trim(),
+            self.po_box.trim(),
+            &city,
+            self.region.trim(),
+            self.country.trim(),
+        ]
+        .iter()
+        .flat_map(|line| line.lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+    }
+}
+
+/// Represents a contact, as parsed from a card.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Contact {
+    pub id: String,
+    pub formatted_name: Option<String>,
+    pub structured_name: Option<Name>,
+    pub emails: Vec<TypedValue>,
+    pub phones: Vec<TypedValue>,
+    pub addresses: Vec<Address>,
+    pub organization: Option<String>,
+    /// Represents the birthday, as written in the card (a date like
+    /// `19850412` or `--0412`, or a free text).
+    pub birthday: Option<String>,
+    pub notes: Vec<String>,
+    pub categories: Vec<String>,
+    pub urls: Vec<TypedValue>,
+}
+
+impl Contact {
+    /// Returns the name of the contact: its formatted name, or its
+    /// structured name when it has none.
+    pub fn name(&self) -> Option<&str> {
+        self.formatted_name.as_deref()
+    }
+
+    /// Returns the preferred email address of the contact.
+    pub fn primary_email(&self) -> Option<&TypedValue> {
+        primary(&self.emails)
+    }
+
+    /// Returns the preferred phone number of the contact.
+    pub fn primary_phone(&self) -> Option<&TypedValue> {
+        primary(&self.phones)
+    }
+
+    /// Generates a new contact id, to be used as UID.
+    pub fn new_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+
+    /// Returns true if the given contact looks like the same person:
+    /// both contacts share a name, an email address or a phone number,
+    /// regardless of case, accents and separators.
+    pub fn is_duplicate_of(&self, other: &Contact) -> bool {
+        let names = |contact: &Contact| -> Vec<String> {
+            contact.name().map(normalize).into_iter().collect()
+        };
+        let emails = |contact: &Contact| -> Vec<String> {
+            contact
+                .emails
+                .iter()
+                .map(|email| normalize(&email.value))
+                .collect()
+        };
+        let phones = |contact: &Contact| -> Vec<String> {
+            contact
+                .phones
+                .iter()
+                .map(|phone| phone_digits(&phone.value))
+                .collect()
+        };
+        let intersect = |values: Vec<String>, other_values: Vec<String>| {
+            values
+                .iter()
+                .any(|value| !value.trim().is_empty() && other_values.contains(value))
+        };
+
+        intersect(names(self), names(other))
+            || intersect(emails(self), emails(other))
+            || intersect(phones(self), phones(other))
+    }
+
+    /// Returns the contact with its phone numbers replaced by their
+    /// comparison key (see [`phone_key`]), so that numbers written in
+    /// different formats compare equal.
+    pub fn with_phone_keys(&self, region: Option<Region>) -> Contact {
+        let mut contact = self.clone();
+        for phone in contact.phones.iter_mut() {
+            phone.value = phone_key(&phone.value, region);
+        }
+        contact
+    }
+
+    /// Writes the contact as a vCard 4.0, revised at the given date.
+    pub fn to_vcard(&self, rev: DateTime<Utc>) -> String {
+        let mut content = String::from("BEGIN:VCARD\r\n");
+        content.push_str(&prop("VERSION", vec![], String::from("4.0")).to_line());
+        content.push_str(&prop("UID", vec![], vcard::escape(&self.id)).to_line());
+        content.extend(self.props().iter().map(Property::to_line));
+        content.push_str(&rev_prop(rev).to_line());
+        content.push_str("END:VCARD\r\n");
+        content
+    }
+
+    /// Writes the contact over the given card content, revised at the
+    /// given date. Only the values of the contact which changed are
+    /// written again, at the place and in the group of the previous
+    /// ones: the other ones, as well as the properties unknown to the
+    /// contact, are kept as they are. When only the formatted name
+    /// changed, the structured name follows it.
+    pub fn update_vcard(&self, content: &str, rev: DateTime<Utc>) -> String {
+        let prev = Contact::parse(&self.id, content);
+        let mut next = self.clone();
+        if next.formatted_name != prev.formatted_name
+            && next.structured_name.is_some()
+            && next.structured_name == prev.structured_name
+        {
+            next.structured_name = next.formatted_name.as_deref().map(Name::from_formatted);
+        }
+        let (prev_props, next_props) = (prev.props(), next.props());
+        let named = |props: &[Property], name: &str| -> Vec<Property> {
+            props.iter().filter(|p| p.name == name).cloned().collect()
+        };
+        let changed_props: Vec<&str> = CONTACT_PROPS
+            .iter()
+            .filter(|name| named(&prev_props, name) != named(&next_props, name))
+            .copied()
+            .collect();
+
+        let version_3 = vcard::parse(content)
+            .iter()
+            .any(|prop| prop.name == "VERSION" && prop.value.trim() == "3.0");
+        let mut lines = vcard::unfold(content);
+        if lines.first().map(|l| l.eq_ignore_ascii_case("BEGIN:VCARD")) == Some(true) {
+            lines.remove(0);
+        }
+        if lines.last().map(|l| l.eq_ignore_ascii_case("END:VCARD")) == Some(true) {
+            lines.pop();
+        }
+
+        // values of the changed properties found unchanged in the card
+        // are written back as they are, with their group and params
+        let mut pending_props: Vec<Property> = next_props
+            .into_iter()
+            .filter(|prop| changed_props.contains(&prop.name.as_str()))
+            .collect();
+        let line_props: Vec<Option<Property>> =
+            lines.iter().map(|line| vcard::parse_line(line)).collect();
+        let mut updates = Vec::with_capacity(lines.len());
+        for (i, (line, prop)) in lines.iter().zip(&line_props).enumerate() {
+            let prop = match prop {
+                Some(prop) if prop.name == "REV" => {
+                    updates.push(LineUpdate::Remove);
+                    continue;
+                }
+                Some(prop) if changed_props.contains(&prop.name.as_str()) => prop,
+                _ => {
+                    updates.push(LineUpdate::Keep);
+                    continue;
+                }
+            };
+            // only the first value of single properties belongs to the
+            // contact
+            let first = !line_props[..i]
+                .iter()
+                .flatten()
+                .any(|prev_prop| prev_prop.name == prop.name);
+            if !first && SINGLE_PROPS.contains(&prop.name.as_str()) {
+                updates.push(LineUpdate::Keep);
+                continue;
+            }
+            let written_prop = Contact::parse(&self.id, line)
+                .props()
+                .into_iter()
+                .find(|written_prop| written_prop.name == prop.name);
+            match written_prop.and_then(|written_prop| {
+                pending_props
+                    .iter()
+                    .position(|pending_prop| *pending_prop == written_prop)
+            }) {
+                Some(pos) => {
+                    pending_props.remove(pos);
+                    updates.push(LineUpdate::Keep);
+                }
+                None => updates.push(LineUpdate::Remove),
+            }
+        }
+
+        // changed values take the place of the previous ones, in the
+        // same group, new ones follow the last value of their kind
+        for (update, prop) in updates.iter_mut().zip(&line_props) {
+            let prop = match (&update, prop) {
+                (LineUpdate::Remove, Some(prop)) if prop.name != "REV" => prop,
+                _ => continue,
+            };
+            if let Some(pos) = pending_props.iter().position(|p| p.name == prop.name) {
+                let mut next_prop = pending_props.remove(pos);
+                next_prop.group = prop.group.clone();
+                *update = LineUpdate::Replace(next_prop);
+            }
+        }
+        let appended_props: Vec<(Option<usize>, Property)> = pending_props
+            .into_iter()
+            .map(|prop| {
+                let last_line = line_props
+                    .iter()
+                    .rposition(|line_prop| line_prop.as_ref().map(|p| &p.name) == Some(&prop.name));
+                (last_line, prop)
+            })
+            .collect();
+
+        let write_prop = |prop: Property, content: &mut String| {
+            let prop = if version_3 { pref_as_type(prop) } else { prop };
+            content.push_str(&prop.to_line());
+        };
+        let mut updated_content = String::from("BEGIN:VCARD\r\n");
+        for (i, (line, update)) in lines.iter().zip(updates).enumerate() {
+            match update {
+                LineUpdate::Keep => updated_content.push_str(&vcard::fold(line)),
+                LineUpdate::Replace(prop) => write_prop(prop, &mut updated_content),
+                LineUpdate::Remove => (),
+            }
+            appended_props
+                .iter()
+                .filter(|(last_line, _)| *last_line == Some(i))
+                .for_each(|(_, prop)| write_prop(prop.clone(), &mut updated_content));
+        }
+        appended_props
+            .into_iter()
+            .filter(|(last_line, _)| last_line.is_none())
+            .for_each(|(_, prop)| write_prop(prop, &mut updated_content));
+        updated_content.push_str(&rev_prop(rev).to_line());
+        updated_content.push_str("END:VCARD\r\n");
+        updated_content
+    }
+
+    /// Builds the properties of the contact, in the order of
+    /// [`CONTACT_PROPS`].
+    fn props(&self) -> Vec<Property> {
+        let escape_all = |values: &[&String], sep: &str| {
+            values
+                .iter()
+                .map(|value| vcard::escape(value))
+                .collect::<Vec<_>>()
+                .join(sep)
+        };
+        let mut props = vec![prop(
+            "FN",
+            vec![],
+            vcard::escape(self.formatted_name.as_deref().unwrap_or_default()),
+        )];
+
+        if let Some(name) = self.structured_name.as_ref() {
+            let components = [
+                &name.family,
+                &name.given,
+                &name.additional,
+                &name.prefixes,
+                &name.suffixes,
+            ];
+            props.push(prop("N", vec![], escape_all(&components, ";")));
+        }
+        for email in &self.emails {
+            let params = params(&email.types, email.pref);
+            props.push(prop("EMAIL", params, vcard::escape(&email.value)));
+        }
+        for phone in &self.phones {
+            let params = params(&phone.types, phone.pref);
+            props.push(prop("TEL", params, vcard::escape(&phone.value)));
+        }
+        for addr in &self.addresses {
+            let components = [
+                &addr.po_box,
+                &addr.extended,
+                &addr.street,
+                &addr.locality,
+                &addr.region,
+                &addr.postal_code,
+                &addr.country,
+            ];
+            let params = params(&addr.types, addr.pref);
+            props.push(prop("ADR", params, escape_all(&components, ";")));
+        }
+        if let Some(org) = self.organization.as_ref() {
+            props.push(prop("ORG", vec![], vcard::escape(org)));
+        }
+        if let Some(birthday) = self.birthday.as_ref() {
+            props.push(prop("BDAY", vec![], birthday.to_owned()));
+        }
+        for note in &self.notes {
+            props.push(prop("NOTE", vec![], vcard::escape(note)));
+        }
+        if !self.categories.is_empty() {
+            let categories: Vec<&String> = self.categories.iter().collect();
+            props.push(prop("CATEGORIES", vec![], escape_all(&categories, ",")));
+        }
+        for url in &self.urls {
+            // URLs are URIs, which are not escaped
+            let params = params(&url.types, url.pref);
+            props.push(prop("URL", params, url.value.to_owned()));
+        }
+
+        props
+    }
+
+    /// Parses the contact with the given id from the given card
+    /// content.
+    pub fn parse(id: &str, content: &str) -> Self {
+        let mut contact = Contact {
+            id: id.to_owned(),
+            ..Self::default()
+        };
+
+        for prop in vcard::parse(content) {
+            match prop.name.as_str() {
+                "FN" if contact.formatted_name.is_none() => {
+                    contact.formatted_name =
+                        Some(vcard::unescape(&prop.value)).filter(|name| !name.trim().is_empty());
+                }
+                "N" if contact.structured_name.is_none() => {
+                    contact.structured_name = Some(Name::from_property(&prop));
+                }
+                "EMAIL" => contact.emails.push(TypedValue::from_property(&prop)),
+                "TEL" => contact.phones.push(TypedValue::from_property(&prop)),
+                "ADR" => contact.addresses.push(Address::from_property(&prop)),
+                "BDAY" if contact.birthday.is_none() => {
+                    contact.birthday = Some(vcard::unescape(&prop.value));
+                }
+                "NOTE" => contact.notes.push(vcard::unescape(&prop.value)),
+                "CATEGORIES" => contact.categories.extend(
+                    vcard::list(&prop.value)
+                        .into_iter()
+                        .map(|category| category.trim().to_owned())
+                        .filter(|category| !category.is_empty()),
+                ),
+                "URL" => contact.urls.push(TypedValue::from_property(&prop)),
+                "ORG" if contact.organization.is_none() => {
+                    let org = vcard::components(&prop.value)
+                        .into_iter()
+                        .filter(|unit| !unit.trim().is_empty())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    contact.organization = Some(org).filter(|org| !org.is_empty());
+                }
+                _ => (),
+            }
+        }
+
+        if contact.formatted_name.is_none() {
+            contact.formatted_name = contact.structured_name.as_ref().and_then(Name::display);
+        }
+
+        contact
+    }
+}
+
+impl From<&Card> for Contact {
+    fn from(card: &Card) -> Self {
+        Self::parse(&card.id, &card.content)
+    }
+}
+
+fn prop(name: &str, params: Vec<(String, String)>, value: String) -> Property {
+    Property {
+        group: None,
+        name: name.to_owned(),
+        params,
+        value,
+    }
+}
+
+pub(crate) fn rev_prop(rev: DateTime<Utc>) -> Property {
+    prop("REV", vec![], rev.format("%Y%m%dT%H%M%SZ").to_string())
+}
+
+/// Writes the preference of the given property as a type, since
+/// vCard 3.0 has no `PREF` parameter.
+fn pref_as_type(mut prop: Property) -> Property {
+    if prop.param("PREF").is_empty() {
+        return prop;
+    }
+    prop.params.retain(|(name, _)| name != "PREF");
+    match prop.params.iter_mut().find(|(name, _)| name == "TYPE") {
+        Some((_, types)) => types.push_str(",pref"),
+        None => prop
+            .params
+            .push((String::from("TYPE"), String::from("pref"))),
+    }
+    prop
+}
+
+/// Returns the types and the preference of the given property.
+fn types_and_pref(prop: &vcard::Property) -> (Vec<String>, Option<u8>) {
+    let mut types = Vec::new();
+    let mut pref = prop
+        .param("PREF")
+        .into_iter()
+        .find_map(|pref| pref.parse().ok());
+
+    for kind in prop.param("TYPE") {
+        let kind = kind.to_lowercase();
+        // vCard 3.0 marks the preferred value with a type
+        if kind == "pref" {
+            pref = pref.or(Some(1));
+        } else if !kind.is_empty() {
+            types.push(kind);
+        }
+    }
+
+    (types, pref)
+}
+
+/// Returns the value with the lowest preference, or the first one when
+/// none has a preference.
+fn primary(values: &[TypedValue]) -> Option<&TypedValue> {
+    values
+        .iter()
+        .enumerate()
+        .min_by_key(|(i, value)| (value.pref.unwrap_or(u8::MAX), *i))
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+
+    fn contact(content: &str) -> Contact {
+        Contact::from(&Card {
+            id: "id".into(),
+            date: Local::now(),
+            content: content.into(),
+            etag: None,
+            href: None,
+        })
+    }
+
+    #[test]
+    fn parse_contact() {
+        let contact = contact(concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "FN:Doe\\, John\r\n",
+            "EMAIL;TYPE=home:john@home.net\r\n",
+            "EMAIL;TYPE=work;PREF=1:john@work.net\r\n",
+            "item1.TEL;TYPE=cell,voice:+33 6 00 00 00 00\r\n",
+            "ORG:Acme;Research\\; Development\r\n",
+            "END:VCARD\r\n",
+        ));
+
+        assert_eq!(Some("Doe, John"), contact.name());
+        assert_eq!("john@work.net", contact.primary_email().unwrap().value);
+        let phone = contact.primary_phone().unwrap();
+        assert_eq!(vec!["cell", "voice"], phone.types);
+        assert_eq!(
+            Some("Acme, Research; Development"),
+            contact.organization.as_deref()
+        );
+    }
+
+    #[test]
+    fn parse_contact_without_formatted_name() {
+        let contact = contact(concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:3.0\r\n",
+            "N:Doe;John;;Dr.;\r\n",
+            "EMAIL;TYPE=INTERNET:john@home.net\r\n",
+            "EMAIL;TYPE=INTERNET,PREF:john@work.net\r\n",
+            "END:VCARD\r\n",
+        ));
+
+        assert_eq!(Some("Dr. John Doe"), contact.name());
+        assert_eq!("john@work.net", contact.primary_email().unwrap().value);
+        assert_eq!(None, contact.primary_phone());
+        assert_eq!(None, contact.organization);
+    }
+
+    #[test]
+    fn parse_contact_details() {
+        let contact = contact(concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "FN:Jane Doe\r\n",
+            "ADR;TYPE=home:;Apt 3;12 Main St;Springfield;IL;62701;USA\r\n",
+            "BDAY:19850412\r\n",
+            "NOTE:Met at the conference\\nLikes tea\r\n",
+            "CATEGORIES:friends,tea\\, coffee\r\n",
+            "URL;TYPE=work:https://example.org\r\n",
+            "END:VCARD\r\n",
+        ));
+
+        assert_eq!(None, contact.structured_name);
+        assert_eq!(
+            vec!["12 Main St", "Apt 3", "62701 Springfield", "IL", "USA"],
+            contact.addresses[0].lines()
+        );
+        assert_eq!(vec!["home"], contact.addresses[0].types);
+        assert_eq!(Some("19850412"), contact.birthday.as_deref());
+        assert_eq!(vec!["Met at the conference\nLikes tea"], contact.notes);
+        assert_eq!(vec!["friends", "tea, coffee"], contact.categories);
+        assert_eq!("https://example.org", contact.urls[0].value);
+    }
+
+    #[test]
+    fn write_contact() {
+        let contact = Contact {
+            id: "6b1a0c9e".into(),
+            formatted_name: Some("Doe, John".into()),
+            emails: vec![TypedValue {
+                types: vec!["work".into()],
+                pref: Some(1),
+                value: "john@work.net".into(),
+            }],
+            phones: vec![TypedValue {
+                types: vec!["cell".into()],
+                pref: None,
+                value: "+33 6 00 00 00 00".into(),
+            }],
+            organization: Some("Acme; Inc".into()),
+            ..Contact::default()
+        };
+        let rev = DateTime::parse_from_rfc3339("2022-03-01T12:00:00Z").unwrap();
+        let content = contact.to_vcard(rev.into());
+
+        assert_eq!(
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:4.0\r\n",
+                "UID:6b1a0c9e\r\n",
+                "FN:Doe\\, John\r\n",
+                "EMAIL;TYPE=work;PREF=1:john@work.net\r\n",
+                "TEL;TYPE=cell:+33 6 00 00 00 00\r\n",
+                "ORG:Acme\\; Inc\r\n",
+                "REV:20220301T120000Z\r\n",
+                "END:VCARD\r\n",
+            ),
+            content
+        );
+        // the written contact is parsed back (the test card id is
+        // not its UID)
+        assert_eq!(
+            Contact {
+                id: "id".into(),
+                ..contact
+            },
+            super::tests::contact(&content),
+        );
+    }
+
+    #[test]
+    fn find_duplicate_contacts() {
+        let alice = contact("BEGIN:VCARD\r\nFN:Zoë\r\nTEL:06 00 00 00 00\r\nEND:VCARD\r\n");
+        let zoe = contact("BEGIN:VCARD\r\nFN:zoe\r\nEND:VCARD\r\n");
+        let phone = contact("BEGIN:VCARD\r\nFN:Z\r\nTEL:06.00.00.00.00\r\nEND:VCARD\r\n");
+        let bob = contact("BEGIN:VCARD\r\nFN:Bob\r\nEMAIL:BOB@corp.com\r\nEND:VCARD\r\n");
+        let email = contact("BEGIN:VCARD\r\nFN:B.\r\nEMAIL:bob@corp.com\r\nEND:VCARD\r\n");
+
+        assert!(alice.is_duplicate_of(&zoe));
+        assert!(alice.is_duplicate_of(&phone));
+        assert!(bob.is_duplicate_of(&email));
+        assert!(!alice.is_duplicate_of(&bob));
+    }
+
+    #[test]
+    fn update_contact() {
+        let content = concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:3.0\r\n",
+            "UID:id\r\n",
+            "FN:John Doe\r\n",
+            "item1.EMAIL;type=INTERNET:john@home.net\r\n",
+            "item1.X-ABLabel:Home\r\n",
+            "TEL;CELL:+33 6 00 00 00 00\r\n",
+            "X-SOCIALPROFILE;type=twitter:john\r\n",
+            "REV:2020-01-01T00:00:00Z\r\n",
+            "END:VCARD\r\n",
+        );
+        let mut contact = super::tests::contact(content);
+        contact.phones[0].value = "+33 6 11 11 11 11".into();
+        let rev = DateTime::parse_from_rfc3339("2022-03-01T12:00:00Z").unwrap();
+
+        // unchanged and unknown properties are kept as they are
+        assert_eq!(
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "UID:id\r\n",
+                "FN:John Doe\r\n",
+                "item1.EMAIL;type=INTERNET:john@home.net\r\n",
+                "item1.X-ABLabel:Home\r\n",
+                "TEL;TYPE=cell:+33 6 11 11 11 11\r\n",
+                "X-SOCIALPROFILE;type=twitter:john\r\n",
+                "REV:20220301T120000Z\r\n",
+                "END:VCARD\r\n",
+            ),
+            contact.update_vcard(content, rev.into())
+        );
+
+        // the structured name follows the formatted name
+        let content = concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "UID:id\r\n",
+            "FN:Dave Jr\r\n",
+            "N:Jr;Dave;;;\r\n",
+            "EMAIL:dave@corp.com\r\n",
+            "END:VCARD\r\n",
+        );
+        let mut contact = super::tests::contact(content);
+        contact.formatted_name = Some("David Junior".into());
+
+        assert_eq!(
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:4.0\r\n",
+                "UID:id\r\n",
+                "FN:David Junior\r\n",
+                "N:Junior;David;;;\r\n",
+                "EMAIL:dave@corp.com\r\n",
+                "REV:20220301T120000Z\r\n",
+                "END:VCARD\r\n",
+            ),
+            contact.update_vcard(content, rev.into())
+        );
+    }
+
+    #[test]
+    fn update_grouped_contact() {
+        let content = concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:3.0\r\n",
+            "UID:id\r\n",
+            "FN:John Doe\r\n",
+            "TEL;CELL:+33 6 00 00 00 00\r\n",
+            "item1.TEL;type=WORK:+33 1 00 00 00 00\r\n",
+            "item1.X-ABLabel:Office\r\n",
+            "END:VCARD\r\n",
+        );
+        let mut contact = super::tests::contact(content);
+        contact.phones[1].value = "+33 1 11 11 11 11".into();
+        contact.phones[1].pref = Some(1);
+        contact.phones.push(TypedValue {
+            types: vec!["home".into()],
+            pref: Some(2),
+            value: "+33 9 00 00 00 00".into(),
+        });
+        let rev = DateTime::parse_from_rfc3339("2022-03-01T12:00:00Z").unwrap();
+
+        // unchanged values are kept as they are, changed ones keep
+        // their group, and the preference is a type in vCard 3.0
+        assert_eq!(
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "UID:id\r\n",
+                "FN:John Doe\r\n",
+                "TEL;CELL:+33 6 00 00 00 00\r\n",
+                "item1.TEL;TYPE=work,pref:+33 1 11 11 11 11\r\n",
+                "TEL;TYPE=home,pref:+33 9 00 00 00 00\r\n",
+                "item1.X-ABLabel:Office\r\n",
+                "REV:20220301T120000Z\r\n",
+                "END:VCARD\r\n",
+            ),
+            contact.update_vcard(content, rev.into())
+        );
+
+        // vCard 4.0 keeps the preference as a parameter
+        let content = content.replace("VERSION:3.0", "VERSION:4.0");
+        assert!(contact
+            .update_vcard(&content, rev.into())
+            .contains("item1.TEL;TYPE=work;PREF=1:+33 1 11 11 11 11\r\n"));
+    }
+}
@@ -0,0 +1,349 @@
+//! CSV format module
+//!
+//! This module contains the reading of contacts from CSV files, like
+//! the ones exported by Google Contacts or Outlook, and their
+//! writing. Each column is mapped to a contact field: the columns of
+//! these tools are known, other columns need a custom mapping.
+//! Contacts are written with the columns of Google Contacts.
+
+use log::debug;
+use std::{collections::HashMap, io};
+
+use crate::{
+    contact::Contact,
+    error::*,
+    import::{ContactBuilder, Field, Target},
+};
+
+/// Represents the mapping of CSV columns to contact fields, by
+/// column header. Targets are written `field` or `field:type,type`
+/// (see [`Target`]). Columns missing from the mapping fall back to
+/// the known columns of Google and Outlook, other columns are
+/// ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CsvMapping {
+    pub columns: HashMap<String, String>,
+}
+
+impl CsvMapping {
+    /// Returns the target of the column with the given header.
+    pub fn target(&self, header: &str) -> Result<Target> {
+        let custom_target = self
+            .columns
+            .iter()
+            .find(|(column, _)| column.trim().eq_ignore_ascii_case(header.trim()));
+        match custom_target {
+            Some((_, target)) => target.parse(),
+            None => Ok(default_target(header.trim())),
+        }
+    }
+}
+
+/// Reads the contacts of the given CSV content. The first line holds
+/// the column headers, and the delimiter (comma, semicolon or
+/// tabulation) is guessed from it.
+pub fn read_contacts(content: &str, mapping: &CsvMapping) -> Result<Vec<Contact>> {
+    let content = content.trim_start_matches('\u{feff}');
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter(content))
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let targets = reader
+        .headers()
+        .map_err(CardamomError::ParseCsvError)?
+        .iter()
+        .map(|header| mapping.target(header))
+        .collect::<Result<Vec<_>>>()?;
+    debug!("csv targets: {:?}", targets);
+
+    let mut contacts = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(CardamomError::ParseCsvError)?;
+        let mut builder = ContactBuilder::default();
+        for (target, value) in targets.iter().zip(record.iter()) {
+            builder.push(target.clone(), value);
+        }
+        contacts.extend(builder.build());
+    }
+
+    Ok(contacts)
+}
+
+/// Writes the given contacts as CSV, with the columns of Google
+/// Contacts. Numbered columns are repeated as many times as needed.
+pub fn write_contacts(contacts: &[Contact]) -> Result<String> {
+    let max = |count: fn(&Contact) -> usize| contacts.iter().map(count).max().unwrap_or(0);
+    let (emails, phones, urls, addresses) = (
+        max(|c| c.emails.len()),
+        max(|c| c.phones.len()),
+        max(|c| c.urls.len()),
+        max(|c| c.addresses.len()),
+    );
+
+    let mut headers: Vec<String> = [
+        "Name",
+        "Given Name",
+        "Additional Name",
+        "Family Name",
+        "Name Prefix",
+        "Name Suffix",
+        "Birthday",
+        "Notes",
+        "Group Membership",
+        "Organization 1 - Name",
+    ]
+    .iter()
+    .map(|header| header.to_string())
+    .collect();
+    for (kind, count) in [("E-mail", emails), ("Phone", phones), ("Website", urls)] {
+        for n in 1..=count {
+            headers.push(format!("{} {} - Type", kind, n));
+            headers.push(format!("{} {} - Value", kind, n));
+        }
+    }
+    for n in 1..=addresses {
+        for component in ADDRESS_COMPONENTS {
+            headers.push(format!("Address {} - {}", n, component));
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(&headers)
+        .map_err(CardamomError::WriteCsvError)?;
+
+    for contact in contacts {
+        let name = contact.structured_name.clone().unwrap_or_default();
+        let mut record = vec![
+            contact.formatted_name.clone().unwrap_or_default(),
+            name.given,
+            name.additional,
+            name.family,
+            name.prefixes,
+            name.suffixes,
+            contact.birthday.clone().unwrap_or_default(),
+            contact.notes.join("\n"),
+            contact.categories.join(" ::: "),
+            contact.organization.clone().unwrap_or_default(),
+        ];
+        for (values, count) in [
+            (&contact.emails, emails),
+            (&contact.phones, phones),
+            (&contact.urls, urls),
+        ] {
+            for n in 0..count {
+                let value = values.get(n).cloned().unwrap_or_default();
+                record.push(value.types.join(" ::: "));
+                record.push(value.value);
+            }
+        }
+        for n in 0..addresses {
+            let addr = contact.addresses.get(n).cloned().unwrap_or_default();
+            record.extend([
+                addr.types.join(" ::: "),
+                addr.po_box,
+                addr.extended,
+                addr.street,
+                addr.locality,
+                addr.region,
+                addr.postal_code,
+                addr.country,
+            ]);
+        }
+        writer
+            .write_record(&record)
+            .map_err(CardamomError::WriteCsvError)?;
+    }
+
+    let buff = writer.into_inner().map_err(|e| {
+        let err = io::Error::new(e.error().kind(), e.error().to_string());
+        CardamomError::WriteCsvError(err.into())
+    })?;
+    Ok(String::from_utf8_lossy(&buff).to_string())
+}
+
+/// Represents the address columns of Google Contacts, in order.
+const ADDRESS_COMPONENTS: [&str; 8] = [
+    "Type",
+    "PO Box",
+    "Extended Address",
+    "Street",
+    "City",
+    "Region",
+    "Postal Code",
+    "Country",
+];
+
+/// Guesses the delimiter of the given CSV content from its header
+/// line.
+fn delimiter(content: &str) -> u8 {
+    let header = content.lines().next().unwrap_or_default();
+    [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|delimiter| header.bytes().filter(|b| b == delimiter).count())
+        .unwrap_or(b',')
+}
+
+/// Returns the target of the known column with the given header, from
+/// Google Contacts or Outlook exports.
+fn default_target(header: &str) -> Target {
+    // Google columns of multiple values are numbered and grouped, like
+    // `E-mail 1 - Value` and `E-mail 1 - Type`
+    if let Some((group, component)) = header.split_once(" - ") {
+        let kind = group.trim_end_matches(|c: char| c.is_ascii_digit() || c == ' ');
+        let field = match (kind, component) {
+            (_, "Type") => Field::Type,
+            ("E-mail", "Value") => Field::Email,
+            ("Phone", "Value") => Field::Phone,
+            ("Website", "Value") => Field::Url,
+            ("Organization", "Name") => Field::Org,
+            ("Address", "PO Box") => Field::PoBox,
+            ("Address", "Extended Address") => Field::Extended,
+            ("Address", "Street") => Field::Street,
+            ("Address", "City") => Field::Locality,
+            ("Address", "Region") => Field::Region,
+            ("Address", "Postal Code") => Field::PostalCode,
+            ("Address", "Country") => Field::Country,
+            _ => Field::Ignore,
+        };
+        return Target::new(field, &[]).group(group);
+    }
+
+    let (field, types): (Field, &[&str]) = match header {
+        // Google
+        "Name" => (Field::Name, &[]),
+        "Given Name" => (Field::GivenName, &[]),
+        "Additional Name" => (Field::AdditionalName, &[]),
+        "Family Name" => (Field::FamilyName, &[]),
+        "Name Prefix" => (Field::NamePrefix, &[]),
+        "Name Suffix" => (Field::NameSuffix, &[]),
+        "Group Membership" => (Field::Categories, &[]),
+        // Outlook
+        "First Name" => (Field::GivenName, &[]),
+        "Middle Name" => (Field::AdditionalName, &[]),
+        "Last Name" => (Field::FamilyName, &[]),
+        "Title" => (Field::NamePrefix, &[]),
+        "Suffix" => (Field::NameSuffix, &[]),
+        "E-mail Address" | "E-mail 2 Address" | "E-mail 3 Address" => (Field::Email, &[]),
+        "Mobile Phone" => (Field::Phone, &["cell"]),
+        "Home Phone" | "Home Phone 2" => (Field::Phone, &["home"]),
+        "Business Phone" | "Business Phone 2" => (Field::Phone, &["work"]),
+        "Primary Phone" | "Other Phone" => (Field::Phone, &[]),
+        "Home Fax" => (Field::Phone, &["home", "fax"]),
+        "Business Fax" => (Field::Phone, &["work", "fax"]),
+        "Company" => (Field::Org, &[]),
+        "Web Page" => (Field::Url, &[]),
+        "Categories" => (Field::Categories, &[]),
+        "Home Street" => (Field::Street, &["home"]),
+        "Home City" => (Field::Locality, &["home"]),
+        "Home State" => (Field::Region, &["home"]),
+        "Home Postal Code" => (Field::PostalCode, &["home"]),
+        "Home Country/Region" => (Field::Country, &["home"]),
+        "Business Street" => (Field::Street, &["work"]),
+        "Business City" => (Field::Locality, &["work"]),
+        "Business State" => (Field::Region, &["work"]),
+        "Business Postal Code" => (Field::PostalCode, &["work"]),
+        "Business Country/Region" => (Field::Country, &["work"]),
+        // both
+        "Birthday" => (Field::Birthday, &[]),
+        "Notes" => (Field::Note, &[]),
+        _ => (Field::Ignore, &[]),
+    };
+    Target::new(field, types)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::contact::TypedValue;
+
+    use super::*;
+
+    #[test]
+    fn read_google_contacts() {
+        let contacts = read_contacts(
+            concat!(
+                "\u{feff}Name,Given Name,Family Name,Group Membership,",
+                "E-mail 1 - Type,E-mail 1 - Value,Phone 1 - Type,Phone 1 - Value,",
+                "Address 1 - Type,Address 1 - Street,Address 1 - City\n",
+                "Alice Smith,Alice,Smith,* myContacts ::: Friends,",
+                "* Work,alice@corp.com ::: alice@corp.net,Mobile,+33 6 00 00 00 00,",
+                "Home,\"1 Main St\nApt 2\",Springfield\n",
+                ",,,,,,,,,,\n",
+            ),
+            &CsvMapping::default(),
+        )
+        .unwrap();
+
+        assert_eq!(1, contacts.len());
+        let alice = &contacts[0];
+        assert_eq!(Some("Alice Smith"), alice.name());
+        assert_eq!(vec!["Friends"], alice.categories);
+        assert_eq!(2, alice.emails.len());
+        assert_eq!(vec!["work"], alice.emails[1].types);
+        assert_eq!(vec!["cell"], alice.phones[0].types);
+        assert_eq!("1 Main St\nApt 2", alice.addresses[0].street);
+        assert_eq!(vec!["home"], alice.addresses[0].types);
+    }
+
+    #[test]
+    fn write_and_read_contacts() {
+        let alice = Contact {
+            formatted_name: Some("Alice, Smith".into()),
+            emails: vec![
+                TypedValue {
+                    types: vec!["work".into()],
+                    pref: None,
+                    value: "alice@corp.com".into(),
+                },
+                TypedValue {
+                    types: vec![],
+                    pref: None,
+                    value: "alice@home.net".into(),
+                },
+            ],
+            notes: vec!["Line 1\nLine 2".into()],
+            ..Contact::default()
+        };
+        let bob = Contact {
+            formatted_name: Some("Bob".into()),
+            ..Contact::default()
+        };
+
+        let csv = write_contacts(&[alice.clone(), bob]).unwrap();
+        assert!(csv.starts_with("Name,Given Name,"));
+        let contacts = read_contacts(&csv, &CsvMapping::default()).unwrap();
+
+        assert_eq!(2, contacts.len());
+        assert_eq!(alice.formatted_name, contacts[0].formatted_name);
+        assert_eq!(alice.emails, contacts[0].emails);
+        assert_eq!(alice.notes, contacts[0].notes);
+        assert_eq!(Some("Bob"), contacts[1].name());
+    }
+
+    #[test]
+    fn read_custom_contacts() {
+        let mapping = CsvMapping {
+            columns: HashMap::from([
+                ("Nom".into(), "name".into()),
+                ("Portable".into(), "phone:cell".into()),
+                ("Courriel".into(), "email".into()),
+            ]),
+        };
+        let contacts = read_contacts(
+            "Nom;Portable;Courriel;Company\nBob;06 00 00 00 00;bob@corp.com;ACME\n",
+            &mapping,
+        )
+        .unwrap();
+
+        assert_eq!(Some("Bob"), contacts[0].name());
+        assert_eq!(vec!["cell"], contacts[0].phones[0].types);
+        assert_eq!("bob@corp.com", contacts[0].emails[0].value);
+        assert_eq!(Some("ACME".into()), contacts[0].organization);
+
+        let mapping = CsvMapping {
+            columns: HashMap::from([("Nom".into(), "nickname".into())]),
+        };
+        assert!(read_contacts("Nom\nBob\n", &mapping).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+//! Dedupe module
+//!
+//! This module contains the detection of duplicate contacts and their
+//! merge. Contacts sharing a name, an email address or a phone number
+//! are scored by how much they look alike, and two cards are merged
+//! by adding the properties of one to the other.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    contact::{rev_prop, Contact},
+    phone::Region,
+    search::{normalize, phone_digits},
+    vcard::{self, Property},
+};
+
+/// Represents the properties a card can hold only once. When both
+/// cards have one, the one of the kept card wins.
+const SINGLE_PROPS: [&str; 14] = [
+    "VERSION",
+    "UID",
+    "PRODID",
+    "FN",
+    "N",
+    "BDAY",
+    "ANNIVERSARY",
+    "GENDER",
+    "KIND",
+    "PHOTO",
+    "LOGO",
+    "SOUND",
+    "TZ",
+    "GEO",
+];
+
+/// Represents two contacts looking like the same person.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Duplicate {
+    pub first: String,
+    pub second: String,
+    /// Represents how much the contacts look alike, from 0 to 100.
+    pub score: u8,
+    /// Represents what the contacts have in common.
+    pub reasons: Vec<String>,
+}
+
+/// Finds the contacts looking like the same person (see
+/// [`Contact::is_duplicate_of`]), the most similar ones first. Phone
+/// numbers are compared in E.164, national numbers being read in the
+/// given region.
+pub fn find_duplicates(contacts: &[Contact], region: Option<Region>) -> Vec<Duplicate> {
+    let contacts: Vec<Contact> = contacts
+        .iter()
+        .map(|contact| contact.with_phone_keys(region))
+        .collect();
+    let mut duplicates = Vec::new();
+
+    for (i, contact) in contacts.iter().enumerate() {
+        for other in &contacts[i + 1..] {
+            if contact.is_duplicate_of(other) {
+                let (score, reasons) = score(contact, other);
+                duplicates.push(Duplicate {
+                    first: contact.id.clone(),
+                    second: other.id.clone(),
+                    score,
+                    reasons,
+                });
+            }
+        }
+    }
+
+    duplicates.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| (&a.first, &a.second).cmp(&(&b.first, &b.second)))
+    });
+    duplicates
+}
+
+/// Scores how much the given contacts look alike: names count for
+/// half of the score (similar names count partially), a shared email
+/// address for 30 and a shared phone number for 20.
+fn score(contact: &Contact, other: &Contact) -> (u8, Vec<String>) {
+    let mut score = 0.0_f64;
+    let mut reasons = Vec::new();
+
+    if let (Some(name), Some(other_name)) = (contact.name(), other.name()) {
+        let similarity = similarity(&name_key(name), &name_key(other_name));
+        if similarity >= 1.0 {
+            reasons.push(String::from("same name"));
+        } else if similarity >= 0.5 {
+            reasons.push(format!("similar names ({:.0}%)", similarity * 100.0));
+        }
+        score += 50.0 * similarity;
+    }
+
+    let emails: HashSet<String> = contact
+        .emails
+        .iter()
+        .map(|email| normalize(email.value.trim()))
+        .collect();
+    if let Some(email) = other
+        .emails
+        .iter()
+        .find(|email| emails.contains(&normalize(email.value.trim())))
+    {
+        reasons.push(format!("same email {}", email.value.trim()));
+        score += 30.0;
+    }
+
+    let phones: HashSet<String> = contact
+        .phones
+        .iter()
+        .map(|phone| phone_digits(&phone.value))
+        .filter(|phone| !phone.is_empty())
+        .collect();
+    if let Some(phone) = other
+        .phones
+        .iter()
+        .find(|phone| phones.contains(&phone_digits(&phone.value)))
+    {
+        reasons.push(format!("same phone {}", phone.value.trim()));
+        score += 20.0;
+    }
+
+    (score.round().min(100.0) as u8, reasons)
+}
+
+/// Normalizes a name so that names differing only by case, accents,
+/// spacing or word order are equal.
+fn name_key(name: &str) -> String {
+    let normalized = normalize(name);
+    let mut words: Vec<&str> = normalized.split_whitespace().collect();
+    words.sort_unstable();
+    words.join(" ")
+}
+
+/// Returns the similarity of two strings, from 0 to 1: the Dice
+/// coefficient of their bigrams.
+fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let (a, mut b) = (bigrams(a), bigrams(b));
+    let total = a.len() + b.len();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let mut common = 0;
+    for bigram in a {
+        if let Some(pos) = b.iter().position(|other| *other == bigram) {
+            b.swap_remove(pos);
+            common += 1;
+        }
+    }
+    2.0 * common as f64 / total as f64
+}
+
+/// Returns true if the given card is a group card (vCard 4.0 `KIND`
+/// or its Apple variant), which holds members rather than a person.
+pub fn is_group(content: &str) -> bool {
+    vcard::parse(content).iter().any(|prop| {
+        matches!(prop.name.as_str(), "KIND" | "X-ADDRESSBOOKSERVER-KIND")
+            && prop.value.trim().eq_ignore_ascii_case("group")
+    })
+}
+
+/// Merges the other card into the kept one, revised at the given
+/// date. The merged card keeps all the properties of the kept card
+/// (including its UID), plus the properties of the other card it does
+/// not have yet. Categories are merged into a single list.
+pub fn merge(kept: &str, other: &str, rev: DateTime<Utc>) -> String {
+    let mut props: Vec<Property> = vcard::parse(kept)
+        .into_iter()
+        .filter(|prop| !matches!(prop.name.as_str(), "BEGIN" | "END" | "REV"))
+        .collect();
+    let mut groups: HashSet<String> = props
+        .iter()
+        .filter_map(|prop| prop.group.as_ref())
+        .map(|group| group.to_lowercase())
+        .collect();
+
+    // grouped labels (like Apple `itemN.X-ABLabel`) follow the
+    // property of their group, other properties are added unless the
+    // kept card already has them
+    let other_props = vcard::parse(other);
+    let is_label = |prop: &Property| prop.group.is_some() && prop.name.starts_with("X-AB");
+    let mut added_groups = HashSet::new();
+    let mut added_props = Vec::new();
+    for prop in other_props.iter().filter(|prop| !is_label(prop)) {
+        match prop.name.as_str() {
+            "BEGIN" | "END" | "REV" => continue,
+            "CATEGORIES" => {
+                merge_categories(&mut props, prop);
+                continue;
+            }
+            name if SINGLE_PROPS.contains(&name) && props.iter().any(|p| p.name == name) => {
+                continue
+            }
+            _ if props.iter().any(|p| is_same_prop(p, prop)) => continue,
+            _ => (),
+        }
+        if let Some(group) = prop.group.as_ref() {
+            added_groups.insert(group.to_lowercase());
+        }
+        added_props.push(prop.clone());
+    }
+    added_props.extend(
+        other_props
+            .iter()
+            .filter(|prop| {
+                is_label(prop)
+                    && added_groups
+                        .contains(&prop.group.as_deref().unwrap_or_default().to_lowercase())
+            })
+            .cloned(),
+    );
+
+    // groups of the other card are renamed when the kept card already
+    // uses them
+    let mut renamed_groups: HashMap<String, String> = HashMap::new();
+    for mut prop in added_props {
+        if let Some(group) = prop.group.take() {
+            let renamed_group = renamed_groups
+                .entry(group.to_lowercase())
+                .or_insert_with(|| {
+                    let mut name = group.clone();
+                    let mut n = 1;
+                    while groups.contains(&name.to_lowercase()) {
+                        name = format!("item{}", n);
+                        n += 1;
+                    }
+                    groups.insert(name.to_lowercase());
+                    name
+                });
+            prop.group = Some(renamed_group.clone());
+        }
+        props.push(prop);
+    }
+
+    let mut content = String::from("BEGIN:VCARD\r\n");
+    content.extend(props.iter().map(Property::to_line));
+    content.push_str(&rev_prop(rev).to_line());
+    content.push_str("END:VCARD\r\n");
+    content
+}
+
+/// Adds the categories of the given property to the categories of
+/// the given properties.
+fn merge_categories(props: &mut Vec<Property>, other: &Property) {
+    let prop = match props.iter_mut().find(|prop| prop.name == "CATEGORIES") {
+        Some(prop) => prop,
+        None => {
+            props.push(other.clone());
+            return;
+        }
+    };
+
+    let mut categories = vcard::list(&prop.value);
+    for category in vcard::list(&other.value) {
+        let known = categories
+            .iter()
+            .any(|c| normalize(c.trim()) == normalize(category.trim()));
+        if !known && !category.trim().is_empty() {
+            categories.push(category);
+        }
+    }
+    prop.value = categories
+        .iter()
+        .map(|category| vcard::escape(category))
+        .collect::<Vec<_>>()
+        .join(",");
+}
+
+/// Returns true if both properties hold the same value, regardless of
+/// their parameters.
+fn is_same_prop(prop: &Property, other: &Property) -> bool {
+    if prop.name != other.name {
+        return false;
+    }
+    match prop.name.as_str() {
+        "TEL" => {
+            let digits = |value: &str| phone_digits(value.trim().trim_start_matches("tel:"));
+            digits(&prop.value) == digits(&other.value)
+        }
+        _ => {
+            let value = |value: &str| normalize(vcard::unescape(value).trim());
+            value(&prop.value) == value(&other.value)
+        }
+    }
+}
+
+/// Moves the membership of the given group card from a member to
+/// another one, revised at the given date. Returns none if the card
+/// does not have the member.
+pub fn move_membership(content: &str, from: &str, to: &str, rev: DateTime<Utc>) -> Option<String> {
+    let is_member = |prop: &Property, uid: &str| {
+        matches!(prop.name.as_str(), "MEMBER" | "X-ADDRESSBOOKSERVER-MEMBER")
+            && prop.value.trim().trim_start_matches("urn:uuid:") == uid
+    };
+    let props = vcard::parse(content);
+    if !props.iter().any(|prop| is_member(prop, from)) {
+        return None;
+    }
+    let has_to = props.iter().any(|prop| is_member(prop, to));
+
+    let mut updated_content = String::new();
+    for mut prop in props {
+        if prop.name == "REV" {
+            continue;
+        }
+        if prop.name == "END" {
+            updated_content.push_str(&rev_prop(rev).to_line());
+        }
+        if is_member(&prop, from) {
+            if has_to {
+                continue;
+            }
+            prop.value = prop.value.replace(from, to);
+        }
+        updated_content.push_str(&prop.to_line());
+    }
+    Some(updated_content)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::contact::TypedValue;
+
+    use super::*;
+
+    fn contact(id: &str, name: &str, email: &str, phone: &str) -> Contact {
+        let value = |value: &str| -> Vec<TypedValue> {
+            Some(value)
+                .filter(|value| !value.is_empty())
+                .map(|value| TypedValue {
+                    value: value.to_owned(),
+                    ..TypedValue::default()
+                })
+                .into_iter()
+                .collect()
+        };
+        Contact {
+            id: id.to_owned(),
+            formatted_name: Some(name.to_owned()),
+            emails: value(email),
+            phones: value(phone),
+            ..Contact::default()
+        }
+    }
+
+    #[test]
+    fn find_scored_duplicates() {
+        let duplicates = find_duplicates(
+            &[
+                contact("a", "Alice Smith", "alice@corp.com", "06 12 34 56 78"),
+                contact("b", "Smith Alice", "", "+33 6.12.34.56.78"),
+                contact("c", "Alice S.", "Alice@Corp.com", ""),
+                contact("d", "Bob", "bob@corp.com", ""),
+            ],
+            Some(crate::phone::region("FR").unwrap()),
+        );
+
+        assert_eq!(
+            vec![
+                Duplicate {
+                    first: "a".into(),
+                    second: "b".into(),
+                    score: 70,
+                    reasons: vec!["same name".into(), "same phone +33612345678".into()],
+                },
+                Duplicate {
+                    first: "a".into(),
+                    second: "c".into(),
+                    score: 65,
+                    reasons: vec![
+                        "similar names (71%)".into(),
+                        "same email Alice@Corp.com".into()
+                    ],
+                },
+            ],
+            duplicates
+        );
+    }
+
+    #[test]
+    fn merge_cards() {
+        let kept = concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "UID:alice\r\n",
+            "FN:Alice Smith\r\n",
+            "item1.EMAIL:alice@corp.com\r\n",
+            "item1.X-ABLabel:Work\r\n",
+            "CATEGORIES:friends\r\n",
+            "REV:20200101T000000Z\r\n",
+            "END:VCARD\r\n",
+        );
+        let other = concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:3.0\r\n",
+            "UID:alice-2\r\n",
+            "FN:Alice\r\n",
+            "item1.EMAIL;TYPE=INTERNET:ALICE@corp.com\r\n",
+            "item1.X-ABLabel:Work\r\n",
+            "item2.TEL:+33 6 00 00 00 00\r\n",
+            "item2.X-ABLabel:Mobile\r\n",
+            "CATEGORIES:Friends,family\r\n",
+            "BDAY:1985-04-12\r\n",
+            "END:VCARD\r\n",
+        );
+        let rev = "2022-03-01T10:00:00Z".parse().unwrap();
+
+        assert_eq!(
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:4.0\r\n",
+                "UID:alice\r\n",
+                "FN:Alice Smith\r\n",
+                "item1.EMAIL:alice@corp.com\r\n",
+                "item1.X-ABLABEL:Work\r\n",
+                "CATEGORIES:friends,family\r\n",
+                "item2.TEL:+33 6 00 00 00 00\r\n",
+                "BDAY:1985-04-12\r\n",
+                "item2.X-ABLABEL:Mobile\r\n",
+                "REV:20220301T100000Z\r\n",
+                "END:VCARD\r\n",
+            ),
+            merge(kept, other, rev)
+        );
+    }
+
+    #[test]
+    fn move_group_membership() {
+        let group = concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "KIND:group\r\n",
+            "FN:Friends\r\n",
+            "MEMBER:urn:uuid:alice-2\r\n",
+            "END:VCARD\r\n",
+        );
+        let rev = "2022-03-01T10:00:00Z".parse().unwrap();
+
+        assert!(is_group(group));
+        assert_eq!(None, move_membership(group, "bob", "alice", rev));
+        assert_eq!(
+            Some(
+                group
+                    .replace("alice-2", "alice")
+                    .replace("END:VCARD", "REV:20220301T100000Z\r\nEND:VCARD")
+            ),
+            move_membership(group, "alice-2", "alice", rev)
+        );
+    }
+}
@@ -7,16 +7,47 @@ pub enum CardamomError {
     UnknownError,
     #[error("cannot read card {0}: {1}")]
     ReadCardError(String, String),
+    #[error("cannot write card {0}: {1}")]
+    WriteCardError(String, String),
     #[error("cannot delete card {0}: {1}")]
     DeleteCardError(String, String),
+    #[error("cannot modify card {0}: card changed since it was fetched")]
+    CardChangedError(String),
+    #[error("cannot upload invalid card {0}: {1}")]
+    InvalidCardError(String, String),
 
     #[error("cannot parse carddav url {0}: {1}")]
     ParseCardDavUrlError(String, url::ParseError),
+    #[error("cannot build carddav runtime: {0}")]
+    BuildRuntimeError(io::Error),
 
     #[error("cannot read cached cards at {0:?}: {1}")]
     ReadCachedCardsError(PathBuf, io::Error),
     #[error("cannot parse cached cards at {0:?}: {1}")]
     ParseCachedCardsError(PathBuf, serde_json::Error),
+    #[error("cannot read cached cards at {0:?}: version {1} is newer than supported version {2}")]
+    UnsupportedCacheVersionError(PathBuf, u32, u32),
+    #[error("cannot write cached cards at {0:?}: {1}")]
+    WriteCachedCardsError(PathBuf, io::Error),
+
+    #[error("cannot read sync journal at {0:?}: {1}")]
+    ReadJournalError(PathBuf, io::Error),
+    #[error("cannot parse sync journal at {0:?}: {1}")]
+    ParseJournalError(PathBuf, serde_json::Error),
+    #[error("cannot write sync journal at {0:?}: {1}")]
+    WriteJournalError(PathBuf, io::Error),
+    #[error("cannot serialize sync journal entry: {0}")]
+    SerializeJournalEntryError(serde_json::Error),
+
+    #[error("cannot open sync lock at {0:?}: {1}")]
+    OpenSyncLockError(PathBuf, io::Error),
+    #[error("cannot lock sync at {0:?}: {1}")]
+    LockSyncError(PathBuf, io::Error),
+    #[error(
+        "cannot lock sync at {0:?}: another sync is already running{}",
+        .1.map(|pid| format!(" (process {})", pid)).unwrap_or_default()
+    )]
+    SyncLockedError(PathBuf, Option<u32>),
 
     #[error("cannot read local cards directory at {0:?}: {1}")]
     ReadLocalCardsDirError(PathBuf, io::Error),
@@ -24,6 +55,47 @@ pub enum CardamomError {
     GetVcfMetadataError(PathBuf, io::Error),
     #[error("cannot get local card modified time at {0:?}: {1}")]
     GetVcfModifiedError(PathBuf, io::Error),
+    #[error("cannot read local card at {0:?}: {1}")]
+    ReadVcfError(PathBuf, io::Error),
+    #[error("cannot write local card at {0:?}: {1}")]
+    WriteVcfError(PathBuf, io::Error),
+    #[error("cannot delete local card at {0:?}: {1}")]
+    DeleteVcfError(PathBuf, io::Error),
+
+    #[error("cannot read index at {0:?}: {1}")]
+    ReadIndexError(PathBuf, io::Error),
+    #[error("cannot serialize index at {0:?}: {1}")]
+    SerializeIndexError(PathBuf, serde_json::Error),
+    #[error("cannot write index at {0:?}: {1}")]
+    WriteIndexError(PathBuf, io::Error),
+    #[error("cannot parse search query {0:?}: {1}")]
+    ParseQueryError(String, String),
+
+    #[error("cannot import contacts: unknown format {0:?}")]
+    UnknownImportFormatError(String),
+    #[error("cannot parse import field {0:?}")]
+    ParseImportFieldError(String),
+    #[error("cannot parse csv: {0}")]
+    ParseCsvError(csv::Error),
+    #[error("cannot parse ldif at line {0}: {1}")]
+    ParseLdifError(usize, String),
+    #[error("cannot export contacts: unknown format {0:?}")]
+    UnknownExportFormatError(String),
+    #[error("cannot write csv: {0}")]
+    WriteCsvError(csv::Error),
+    #[error("cannot serialize contacts to json: {0}")]
+    SerializeJsonError(serde_json::Error),
+    #[error("cannot convert card: unknown vcard version {0:?}")]
+    UnknownVcardVersionError(String),
+    #[error("cannot parse phone region: unknown country code {0:?}")]
+    UnknownRegionError(String),
+
+    #[error("cannot read tombstones at {0:?}: {1}")]
+    ReadTombstonesError(PathBuf, io::Error),
+    #[error("cannot parse tombstones at {0:?}: {1}")]
+    ParseTombstonesError(PathBuf, serde_json::Error),
+    #[error("cannot write tombstones at {0:?}: {1}")]
+    WriteTombstonesError(PathBuf, io::Error),
 
     #[error("cannot parse missing last modified date from address data")]
     ParseAddressDataLastModifiedError,
@@ -45,6 +117,14 @@ pub enum CardamomError {
     FetchAddressDataError(reqwest::Error),
     #[error("cannot parse remote cards: {0}")]
     ParseAddressDataError(quick_xml::de::DeError),
+    #[error("cannot build remote card url for {0:?}: {1}")]
+    ParseCardUrlError(String, url::ParseError),
+    #[error("cannot fetch remote card {0:?}: {1}")]
+    FetchCardError(String, reqwest::Error),
+    #[error("cannot put remote card {0:?}: {1}")]
+    PutCardError(String, reqwest::Error),
+    #[error("cannot delete remote card {0:?}: {1}")]
+    DeleteRemoteCardError(String, reqwest::Error),
 }
 
 pub type Result<T> = result::Result<T, CardamomError>;
@@ -0,0 +1,141 @@
+//! Export module
+//!
+//! This module contains the export of cards to files readable by
+//! other tools. Cards are exported either all together in one file,
+//! or one by one in one file per card.
+
+use serde_json::{json, Value};
+use std::{result, str::FromStr};
+
+use crate::{card::Card, contact::Contact, csv_format, error::*, jcard, ldif_format};
+
+/// Represents the format of an exported file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Vcf,
+    Csv,
+    Ldif,
+    Json,
+    Jcard,
+}
+
+impl ExportFormat {
+    /// Returns the file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Vcf => "vcf",
+            Self::Csv => "csv",
+            Self::Ldif => "ldif",
+            Self::Json => "json",
+            Self::Jcard => "jcard.json",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = CardamomError;
+
+    fn from_str(format: &str) -> result::Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "vcf" | "vcard" => Ok(Self::Vcf),
+            "csv" => Ok(Self::Csv),
+            "ldif" | "ldi" => Ok(Self::Ldif),
+            "json" => Ok(Self::Json),
+            "jcard" => Ok(Self::Jcard),
+            _ => Err(CardamomError::UnknownExportFormatError(format.to_owned())),
+        }
+    }
+}
+
+/// Exports the given cards all together. JSON and jCard exports are
+/// arrays, with one item per card.
+pub fn export(cards: &[Card], format: ExportFormat) -> Result<String> {
+    let contacts = || -> Vec<Contact> { cards.iter().map(Contact::from).collect() };
+
+    match format {
+        ExportFormat::Vcf => Ok(cards.iter().map(vcf).collect()),
+        ExportFormat::Csv => csv_format::write_contacts(&contacts()),
+        ExportFormat::Ldif => Ok(ldif_format::write_contacts(&contacts())),
+        ExportFormat::Json => to_json(&json!(contacts())),
+        ExportFormat::Jcard => {
+            let jcards: Vec<Value> = cards
+                .iter()
+                .map(|card| jcard::to_jcard(&card.content))
+                .collect();
+            to_json(&json!(jcards))
+        }
+    }
+}
+
+/// Exports the given card alone. JSON and jCard exports are objects
+/// (not arrays).
+pub fn export_one(card: &Card, format: ExportFormat) -> Result<String> {
+    match format {
+        ExportFormat::Json => to_json(&json!(Contact::from(card))),
+        ExportFormat::Jcard => to_json(&jcard::to_jcard(&card.content)),
+        format => export(std::slice::from_ref(card), format),
+    }
+}
+
+/// Returns the content of the given card, ending with a line break so
+/// that cards can be concatenated.
+fn vcf(card: &Card) -> String {
+    let mut content = card.content.clone();
+    if !content.ends_with('\n') {
+        content.push_str("\r\n");
+    }
+    content
+}
+
+fn to_json(value: &Value) -> Result<String> {
+    let mut json =
+        serde_json::to_string_pretty(value).map_err(CardamomError::SerializeJsonError)?;
+    json.push('\n');
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+
+    fn card(id: &str, name: &str) -> Card {
+        Card {
+            id: id.to_owned(),
+            date: Local::now(),
+            content: format!(
+                "BEGIN:VCARD\r\nVERSION:4.0\r\nUID:{}\r\nFN:{}\r\nEND:VCARD",
+                id, name
+            ),
+            etag: None,
+            href: None,
+        }
+    }
+
+    #[test]
+    fn export_cards() {
+        let cards = vec![card("alice", "Alice"), card("bob", "Bob")];
+
+        let vcf = export(&cards, ExportFormat::Vcf).unwrap();
+        assert_eq!(2, vcf.matches("END:VCARD\r\n").count());
+
+        let json: Value =
+            serde_json::from_str(&export(&cards, ExportFormat::Json).unwrap()).unwrap();
+        assert_eq!("Bob", json[1]["formatted-name"]);
+
+        let jcards: Value =
+            serde_json::from_str(&export(&cards, ExportFormat::Jcard).unwrap()).unwrap();
+        assert_eq!(json!(["uid", {}, "text", "alice"]), jcards[0][1][1]);
+
+        let jcard: Value =
+            serde_json::from_str(&export_one(&cards[1], ExportFormat::Jcard).unwrap()).unwrap();
+        assert_eq!("vcard", jcard[0]);
+
+        assert_eq!(
+            "jcard.json",
+            "jcard".parse::<ExportFormat>().unwrap().extension()
+        );
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+}
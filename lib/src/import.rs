@@ -0,0 +1,453 @@
+//! Import module
+//!
+//! This module contains the import of contacts exported by other
+//! tools. A vCard file is imported card by card, while the records of
+//! the other formats (CSV, LDIF) are mapped field by field to a
+//! contact. Each record becomes a vCard with a UID.
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use std::{path::Path, result, str::FromStr};
+
+use crate::{
+    contact::{Address, Contact, Name, TypedValue},
+    csv_format::{self, CsvMapping},
+    error::*,
+    ldif_format, vcard,
+};
+
+/// Represents the format of a file to import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Vcf,
+    Csv,
+    Ldif,
+}
+
+impl ImportFormat {
+    /// Guesses the format of the file at the given path from its
+    /// extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = CardamomError;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format.to_lowercase().as_str() {
+            "vcf" | "vcard" => Ok(Self::Vcf),
+            "csv" => Ok(Self::Csv),
+            "ldif" | "ldi" => Ok(Self::Ldif),
+            _ => Err(CardamomError::UnknownImportFormatError(format.to_owned())),
+        }
+    }
+}
+
+/// Represents an imported card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedCard {
+    pub contact: Contact,
+    pub content: String,
+}
+
+/// Imports the cards of the given file content, revised at the given
+/// date. The CSV columns are mapped following the given mapping.
+pub fn import(
+    content: &str,
+    format: ImportFormat,
+    mapping: &CsvMapping,
+    rev: DateTime<Utc>,
+) -> Result<Vec<ImportedCard>> {
+    let contacts = match format {
+        ImportFormat::Vcf => return Ok(import_vcf(content, rev)),
+        ImportFormat::Csv => csv_format::read_contacts(content, mapping)?,
+        ImportFormat::Ldif => ldif_format::read_contacts(content)?,
+    };
+
+    Ok(contacts
+        .into_iter()
+        .map(|contact| ImportedCard {
+            content: contact.to_vcard(rev),
+            contact,
+        })
+        .collect())
+}
+
+/// Imports the cards of the given vCard file. The cards are kept as
+/// they are (including the properties unknown to the contact), except
+/// that a UID is added to the cards without one.
+fn import_vcf(content: &str, rev: DateTime<Utc>) -> Vec<ImportedCard> {
+    vcard::split(content)
+        .into_iter()
+        .map(|content| {
+            let (id, content) = match vcard::uid(&content) {
+                Some(id) => (id, content),
+                None => {
+                    let id = Contact::new_id();
+                    let content = vcard::set_uid(&content, &id);
+                    (id, content)
+                }
+            };
+            let contact = Contact::parse(&id, &content);
+            ImportedCard {
+                content: contact.update_vcard(&content, rev),
+                contact,
+            }
+        })
+        .collect()
+}
+
+/// Represents a contact field a record value can be imported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    GivenName,
+    AdditionalName,
+    FamilyName,
+    NamePrefix,
+    NameSuffix,
+    Email,
+    Phone,
+    Url,
+    Org,
+    Birthday,
+    BirthYear,
+    BirthMonth,
+    BirthDay,
+    Note,
+    Categories,
+    PoBox,
+    Extended,
+    Street,
+    Locality,
+    Region,
+    PostalCode,
+    Country,
+    /// Represents the types of the other values of the same group
+    /// (like the `E-mail 1 - Type` column of Google CSV files).
+    Type,
+    Ignore,
+}
+
+impl FromStr for Field {
+    type Err = ();
+
+    fn from_str(field: &str) -> result::Result<Self, Self::Err> {
+        match field.to_lowercase().as_str() {
+            "name" => Ok(Self::Name),
+            "given-name" => Ok(Self::GivenName),
+            "additional-name" => Ok(Self::AdditionalName),
+            "family-name" => Ok(Self::FamilyName),
+            "name-prefix" => Ok(Self::NamePrefix),
+            "name-suffix" => Ok(Self::NameSuffix),
+            "email" => Ok(Self::Email),
+            "phone" | "tel" => Ok(Self::Phone),
+            "url" => Ok(Self::Url),
+            "org" => Ok(Self::Org),
+            "birthday" => Ok(Self::Birthday),
+            "birth-year" => Ok(Self::BirthYear),
+            "birth-month" => Ok(Self::BirthMonth),
+            "birth-day" => Ok(Self::BirthDay),
+            "note" => Ok(Self::Note),
+            "categories" | "category" => Ok(Self::Categories),
+            "po-box" => Ok(Self::PoBox),
+            "extended" => Ok(Self::Extended),
+            "street" | "address" => Ok(Self::Street),
+            "locality" | "city" => Ok(Self::Locality),
+            "region" => Ok(Self::Region),
+            "postal-code" => Ok(Self::PostalCode),
+            "country" => Ok(Self::Country),
+            "type" => Ok(Self::Type),
+            "ignore" => Ok(Self::Ignore),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Represents the target of a record value: a field, the types of the
+/// value and its group. Values of the same group share their types,
+/// and address components of the same group make one address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub field: Field,
+    pub types: Vec<String>,
+    pub group: String,
+}
+
+impl Target {
+    pub fn new(field: Field, types: &[&str]) -> Self {
+        Self {
+            field,
+            types: types.iter().map(|kind| kind.to_string()).collect(),
+            group: types.join(","),
+        }
+    }
+
+    /// Sets the group of the target.
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = group.to_owned();
+        self
+    }
+}
+
+impl FromStr for Target {
+    type Err = CardamomError;
+
+    /// Parses a target written `field` or `field:type,type`, like
+    /// `phone:cell` or `street:work`.
+    fn from_str(target: &str) -> Result<Self> {
+        let (field, types) = target.split_once(':').unwrap_or((target, ""));
+        let field = field
+            .trim()
+            .parse()
+            .map_err(|_| CardamomError::ParseImportFieldError(target.to_owned()))?;
+        let types: Vec<&str> = types
+            .split(',')
+            .map(str::trim)
+            .filter(|kind| !kind.is_empty())
+            .collect();
+        Ok(Self::new(field, &types))
+    }
+}
+
+/// Represents a contact being built from the values of a record.
+#[derive(Debug, Default)]
+pub(crate) struct ContactBuilder {
+    contact: Contact,
+    name: Name,
+    birth: (String, String, String),
+    addresses: Vec<(String, Address)>,
+    targets: Vec<(Target, String)>,
+}
+
+impl ContactBuilder {
+    /// Adds the given value to the given target. Values are applied
+    /// when building the contact, once the types of all the groups
+    /// are known.
+    pub fn push(&mut self, target: Target, value: &str) {
+        let value = value.trim();
+        if target.field != Field::Ignore && !value.is_empty() {
+            self.targets.push((target, value.to_owned()));
+        }
+    }
+
+    /// Builds the contact with a new id. A record without any name,
+    /// email address or organization is not considered as a contact.
+    pub fn build(mut self) -> Option<Contact> {
+        let group_types: Vec<(String, Vec<String>)> = self
+            .targets
+            .iter()
+            .filter(|(target, _)| target.field == Field::Type)
+            .map(|(target, value)| (target.group.clone(), types(value)))
+            .collect();
+
+        for (target, value) in std::mem::take(&mut self.targets) {
+            let mut types = target.types.clone();
+            for (group, group_types) in &group_types {
+                if group == &target.group {
+                    types.extend(group_types.iter().cloned());
+                }
+            }
+            self.apply(&target, types, value);
+        }
+
+        let mut contact = self.contact;
+        contact.id = Contact::new_id();
+        contact.birthday = contact.birthday.or_else(|| birthday(&self.birth));
+        contact.addresses = self
+            .addresses
+            .into_iter()
+            .map(|(_, address)| address)
+            .collect();
+        if self.name != Name::default() {
+            contact.structured_name = Some(self.name);
+        }
+        contact.formatted_name = contact
+            .formatted_name
+            .or_else(|| contact.structured_name.as_ref().and_then(Name::display))
+            .or_else(|| contact.emails.first().map(|email| email.value.clone()))
+            .or_else(|| contact.organization.clone());
+
+        if contact.formatted_name.is_none() {
+            warn!("skipping record without name, email nor organization");
+            return None;
+        }
+
+        Some(contact)
+    }
+
+    fn apply(&mut self, target: &Target, types: Vec<String>, value: String) {
+        let typed_value = |value: &str| TypedValue {
+            types: types.clone(),
+            pref: None,
+            value: value.trim().to_owned(),
+        };
+        // some tools (like Google) put several values in one field
+        let values: Vec<&str> = value
+            .split(" ::: ")
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .collect();
+
+        match target.field {
+            Field::Name => self.contact.formatted_name = Some(value),
+            Field::GivenName => self.name.given = value,
+            Field::AdditionalName => self.name.additional = value,
+            Field::FamilyName => self.name.family = value,
+            Field::NamePrefix => self.name.prefixes = value,
+            Field::NameSuffix => self.name.suffixes = value,
+            Field::Email => self
+                .contact
+                .emails
+                .extend(values.iter().map(|v| typed_value(v))),
+            Field::Phone => self
+                .contact
+                .phones
+                .extend(values.iter().map(|v| typed_value(v))),
+            Field::Url => self
+                .contact
+                .urls
+                .extend(values.iter().map(|v| typed_value(v))),
+            Field::Org => self.contact.organization = Some(value),
+            Field::Birthday => self.contact.birthday = Some(value),
+            Field::BirthYear => self.birth.0 = value,
+            Field::BirthMonth => self.birth.1 = value,
+            Field::BirthDay => self.birth.2 = value,
+            Field::Note => self.contact.notes.push(value),
+            Field::Categories => self.contact.categories.extend(
+                value
+                    .split(&[';', ','][..])
+                    .flat_map(|category| category.split(" ::: "))
+                    .map(|category| category.trim().trim_start_matches("* ").to_owned())
+                    // the system group of Google contacts
+                    .filter(|category| !category.is_empty() && category != "myContacts"),
+            ),
+            Field::PoBox
+            | Field::Extended
+            | Field::Street
+            | Field::Locality
+            | Field::Region
+            | Field::PostalCode
+            | Field::Country => {
+                let address = match self
+                    .addresses
+                    .iter()
+                    .position(|(group, _)| group == &target.group)
+                {
+                    Some(pos) => &mut self.addresses[pos].1,
+                    None => {
+                        self.addresses
+                            .push((target.group.clone(), Address::default()));
+                        &mut self.addresses.last_mut().unwrap().1
+                    }
+                };
+                for kind in types {
+                    if !address.types.contains(&kind) {
+                        address.types.push(kind);
+                    }
+                }
+                let component = match target.field {
+                    Field::PoBox => &mut address.po_box,
+                    Field::Extended => &mut address.extended,
+                    Field::Street => &mut address.street,
+                    Field::Locality => &mut address.locality,
+                    Field::Region => &mut address.region,
+                    Field::PostalCode => &mut address.postal_code,
+                    _ => &mut address.country,
+                };
+                *component = value;
+            }
+            Field::Type | Field::Ignore => (),
+        }
+    }
+}
+
+/// Parses the types of a value, as written by other tools (like
+/// `* Work` or `Mobile`).
+fn types(value: &str) -> Vec<String> {
+    value
+        .split(&[',', ';'][..])
+        .flat_map(|kind| kind.split(" ::: "))
+        .map(|kind| kind.trim().trim_start_matches("* ").to_lowercase())
+        .filter_map(|kind| match kind.as_str() {
+            "" | "other" | "custom" => None,
+            "mobile" => Some(String::from("cell")),
+            _ => Some(kind),
+        })
+        .collect()
+}
+
+/// Builds a birthday from its year, month and day components. The
+/// year is optional.
+fn birthday((year, month, day): &(String, String, String)) -> Option<String> {
+    let month: u32 = month.parse().ok()?;
+    let day: u32 = day.parse().ok()?;
+    match year.parse::<u32>() {
+        Ok(year) => Some(format!("{:04}{:02}{:02}", year, month, day)),
+        Err(_) => Some(format!("--{:02}{:02}", month, day)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_vcf_cards() {
+        let rev = Utc::now();
+        let cards = import(
+            concat!(
+                "BEGIN:VCARD\r\nVERSION:4.0\r\nUID:alice\r\nFN:Alice\r\nX-FOO:bar\r\nEND:VCARD\r\n",
+                "BEGIN:VCARD\nVERSION:4.0\nFN:Bob\nEND:VCARD\n",
+            ),
+            ImportFormat::Vcf,
+            &CsvMapping::default(),
+            rev,
+        )
+        .unwrap();
+
+        assert_eq!(2, cards.len());
+        assert_eq!("alice", cards[0].contact.id);
+        assert!(cards[0].content.contains("X-FOO:bar\r\n"));
+        assert_eq!(
+            Some(cards[1].contact.id.clone()),
+            vcard::uid(&cards[1].content)
+        );
+        assert_eq!(Some("Bob"), cards[1].contact.name());
+        assert!(cards[1].content.starts_with("BEGIN:VCARD\r\nUID:"));
+        assert!(!cards[1].content.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn build_contact_from_targets() {
+        let mut builder = ContactBuilder::default();
+        builder.push(Target::new(Field::GivenName, &[]), "Alice");
+        builder.push(Target::new(Field::FamilyName, &[]), "Smith");
+        builder.push(
+            Target::new(Field::Email, &[]).group("E-mail 1"),
+            "a@corp.com",
+        );
+        builder.push(Target::new(Field::Type, &[]).group("E-mail 1"), "* Work");
+        builder.push(Target::new(Field::Street, &["home"]), "1 Main St");
+        builder.push(Target::new(Field::Locality, &["home"]), "Springfield");
+        builder.push(Target::new(Field::BirthMonth, &[]), "4");
+        builder.push(Target::new(Field::BirthDay, &[]), "12");
+        let contact = builder.build().unwrap();
+
+        assert_eq!(Some("Alice Smith"), contact.name());
+        assert_eq!(vec!["work"], contact.emails[0].types);
+        assert_eq!(1, contact.addresses.len());
+        assert_eq!(vec!["home"], contact.addresses[0].types);
+        assert_eq!("Springfield", contact.addresses[0].locality);
+        assert_eq!(Some("--0412".into()), contact.birthday);
+
+        assert!(ContactBuilder::default().build().is_none());
+        assert_eq!(
+            Target::new(Field::Phone, &["cell"]),
+            "phone:cell".parse().unwrap()
+        );
+        assert!("nickname".parse::<Target>().is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! Index module
+//!
+//! This module contains the persistent search index of a local
+//! store. Parsing every card on each query is too slow for large
+//! addressbooks, so the parsed contacts are kept on the disk along
+//! with the modification date of their card. An entry is parsed again
+//! only when its card changed.
+
+use chrono::{DateTime, Local};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::PathBuf,
+};
+
+use crate::{
+    backend::Backend,
+    cache::write_atomically,
+    card::{Card, CardsMap},
+    card_parsers::date_parser,
+    contact::Contact,
+    error::*,
+    report::{Action, SideReport},
+    search::{searchable_text, Query},
+};
+
+/// Represents the current version of the index format. An index of
+/// another version is rebuilt.
+pub const INDEX_VERSION: u32 = 1;
+
+/// Represents the index as it is written to the disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexFile {
+    version: u32,
+    entries: HashMap<String, IndexEntry>,
+}
+
+/// Represents an indexed card.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Represents the modification date of the card when it was
+    /// indexed.
+    #[serde(with = "date_parser")]
+    pub date: DateTime<Local>,
+    pub contact: Contact,
+    /// Represents the normalized values of the searchable properties
+    /// of the card, matched by free text queries.
+    pub text: String,
+}
+
+impl From<&Card> for IndexEntry {
+    fn from(card: &Card) -> Self {
+        Self {
+            date: card.date,
+            contact: Contact::from(card),
+            text: searchable_text(&card.content),
+        }
+    }
+}
+
+/// Represents the search index of a local store. Entries are indexed
+/// by the location of their card.
+#[derive(Debug, Default)]
+pub struct Index {
+    path: PathBuf,
+    pub entries: HashMap<String, IndexEntry>,
+}
+
+impl Index {
+    /// Reads the index at the given path. A missing, unreadable or
+    /// outdated index is considered as empty, since it can always be
+    /// rebuilt from the cards.
+    pub fn new(path: PathBuf) -> Result<Self> {
+        let buff = match fs::read(&path) {
+            Ok(buff) => buff,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(CardamomError::ReadIndexError(path, e)),
+        };
+
+        let entries = match serde_json::from_slice::<IndexFile>(&buff) {
+            Ok(index) if index.version == INDEX_VERSION => index.entries,
+            Ok(index) => {
+                debug!("index version {} is outdated, rebuilding it", index.version);
+                HashMap::new()
+            }
+            Err(_) if buff.is_empty() => HashMap::new(),
+            Err(e) => {
+                warn!("cannot parse index at {:?}, rebuilding it: {}", path, e);
+                HashMap::new()
+            }
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    /// Saves the index atomically.
+    pub fn save(&self) -> Result<()> {
+        let index = IndexFile {
+            version: INDEX_VERSION,
+            entries: self.entries.clone(),
+        };
+        let buff = serde_json::to_vec(&index)
+            .map_err(|e| CardamomError::SerializeIndexError(self.path.clone(), e))?;
+        write_atomically(&self.path, &buff)
+            .map_err(|e| CardamomError::WriteIndexError(self.path.clone(), e))
+    }
+
+    /// Brings the index up to date with the given backend: entries of
+    /// removed cards are dropped, and cards modified since they were
+    /// indexed (including outside of cardamom) are indexed again.
+    /// Returns true if the index changed.
+    pub fn refresh<B: Backend + ?Sized>(&mut self, backend: &B) -> Result<bool> {
+        let dates = backend.list_dates()?;
+        let entries_count = self.entries.len();
+        self.entries.retain(|href, _| dates.contains_key(href));
+        let mut changed = self.entries.len() != entries_count;
+
+        let stale_hrefs: Vec<String> = dates
+            .iter()
+            .filter(|(href, date)| {
+                self.entries
+                    .get(*href)
+                    .map(|entry| &entry.date != *date)
+                    .unwrap_or(true)
+            })
+            .map(|(href, _)| href.to_owned())
+            .collect();
+        debug!("{} stale index entries", stale_hrefs.len());
+
+        // getting cards one by one is only worth it when few of them
+        // changed, otherwise listing them all at once is faster
+        if stale_hrefs.len() > dates.len() / 4 {
+            for card in backend.list()?.values() {
+                self.update(card);
+            }
+            changed = true;
+        } else {
+            for href in stale_hrefs {
+                let card = backend.get(&href)?;
+                self.update(&card);
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Indexes the given card, replacing its previous entry.
+    pub fn update(&mut self, card: &Card) {
+        if let Some(href) = card.href.as_ref() {
+            self.entries.insert(href.to_owned(), IndexEntry::from(card));
+        }
+    }
+
+    /// Applies the changes of one side of a sync to the index, the
+    /// given cards being the cards of this side after the sync.
+    pub fn apply_report(&mut self, report: &SideReport, cards: &CardsMap) {
+        let mut deleted_ids = HashSet::new();
+
+        for outcome in &report.cards {
+            match outcome.action {
+                Action::Del => {
+                    deleted_ids.insert(outcome.id.as_str());
+                }
+                Action::Add | Action::Set => match cards.get(&outcome.id) {
+                    Some(card) => self.update(card),
+                    None => warn!("cannot index card {:?}: card not found", outcome.id),
+                },
+            }
+        }
+
+        if !deleted_ids.is_empty() {
+            self.entries
+                .retain(|_, entry| !deleted_ids.contains(entry.contact.id.as_str()));
+        }
+    }
+
+    /// Returns the indexed contacts.
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.entries.values().map(|entry| &entry.contact)
+    }
+
+    /// Returns the indexed contacts matching the given query.
+    pub fn search<'a>(&'a self, query: &'a Query) -> impl Iterator<Item = &'a Contact> {
+        self.entries
+            .values()
+            .filter(|entry| query.matches_contact(&entry.contact, &entry.text))
+            .map(|entry| &entry.contact)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, thread, time::Duration};
+
+    use crate::{local::LocalCards, report::CardOutcome};
+
+    use super::*;
+
+    #[test]
+    fn refresh_and_search_index() {
+        let dir = env::temp_dir().join("cardamom-index");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("alice.vcf"),
+            "BEGIN:VCARD\r\nUID:alice\r\nFN:Alice\r\nEMAIL:alice@corp.com\r\nEND:VCARD\r\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("bob.vcf"),
+            "BEGIN:VCARD\r\nUID:bob\r\nFN:Bob\r\nNOTE:Café owner\r\nEND:VCARD\r\n",
+        )
+        .unwrap();
+        let backend = LocalCards::new(dir.clone());
+        let index_path = dir.join(".index");
+
+        let mut index = Index::new(index_path.clone()).unwrap();
+        assert!(index.refresh(&backend).unwrap());
+        index.save().unwrap();
+        let query: Query = "cafe".parse().unwrap();
+        let names: Vec<_> = index.search(&query).map(|c| c.name()).collect();
+        assert_eq!(vec![Some("Bob")], names);
+
+        // nothing changed since the last refresh
+        let mut index = Index::new(index_path).unwrap();
+        assert_eq!(2, index.contacts().count());
+        assert!(!index.refresh(&backend).unwrap());
+
+        // cards changed outside of cardamom are indexed again
+        thread::sleep(Duration::from_millis(10));
+        fs::write(
+            dir.join("alice.vcf"),
+            "BEGIN:VCARD\r\nUID:alice\r\nFN:Alice Smith\r\nEND:VCARD\r\n",
+        )
+        .unwrap();
+        fs::remove_file(dir.join("bob.vcf")).unwrap();
+        assert!(index.refresh(&backend).unwrap());
+        let names: Vec<_> = index.contacts().map(|c| c.name()).collect();
+        assert_eq!(vec![Some("Alice Smith")], names);
+
+        // sync changes are applied without reading the cards again
+        let mut carol = backend.get("alice.vcf").unwrap();
+        carol.id = "carol".into();
+        carol.href = Some("carol.vcf".into());
+        carol.content = "BEGIN:VCARD\r\nUID:carol\r\nFN:Carol\r\nEND:VCARD\r\n".into();
+        let report = SideReport {
+            cards: vec![
+                CardOutcome {
+                    id: "alice".into(),
+                    action: Action::Del,
+                },
+                CardOutcome {
+                    id: "carol".into(),
+                    action: Action::Add,
+                },
+            ],
+            ..SideReport::default()
+        };
+        index.apply_report(&report, &HashMap::from([("carol".into(), carol)]));
+        let names: Vec<_> = index.contacts().map(|c| c.name()).collect();
+        assert_eq!(vec![Some("Carol")], names);
+    }
+}
@@ -0,0 +1,215 @@
+//! jCard module
+//!
+//! This module contains the conversion of cards to jCards, the JSON
+//! representation of vCards defined in the [RFC7095]. Each property
+//! becomes an array made of its name, its parameters, its value type
+//! and its values.
+//!
+//! [RFC7095]: https://www.rfc-editor.org/rfc/rfc7095
+
+use serde_json::{json, Map, Value};
+
+use crate::vcard::{self, Property};
+
+/// Converts the given card content to a jCard.
+pub fn to_jcard(content: &str) -> Value {
+    let props: Vec<Value> = vcard::parse(content)
+        .iter()
+        .filter(|prop| prop.name != "BEGIN" && prop.name != "END")
+        .map(to_jcard_property)
+        .collect();
+    json!(["vcard", props])
+}
+
+fn to_jcard_property(prop: &Property) -> Value {
+    let mut params = Map::new();
+    if let Some(group) = prop.group.as_ref() {
+        params.insert(String::from("group"), json!(group.to_lowercase()));
+    }
+    for (name, _) in &prop.params {
+        let key = name.to_lowercase();
+        // the value type has its own place in the property
+        if name == "VALUE" || params.contains_key(&key) {
+            continue;
+        }
+        let values = prop.param(name);
+        let value = match values.as_slice() {
+            [value] => json!(value),
+            values => json!(values),
+        };
+        params.insert(key, value);
+    }
+
+    let value_type = prop
+        .param("VALUE")
+        .first()
+        .map(|value_type| value_type.to_lowercase())
+        .unwrap_or_else(|| value_type(&prop.name).to_owned());
+
+    let mut jprop = vec![
+        json!(prop.name.to_lowercase()),
+        Value::Object(params),
+        json!(value_type),
+    ];
+    jprop.extend(values(prop, &value_type));
+    Value::Array(jprop)
+}
+
+/// Returns the default value type of the given property.
+fn value_type(name: &str) -> &'static str {
+    match name {
+        "BDAY" | "ANNIVERSARY" => "date-and-or-time",
+        "REV" => "timestamp",
+        "URL" | "SOURCE" | "PHOTO" | "LOGO" | "SOUND" | "KEY" | "IMPP" | "GEO" | "MEMBER"
+        | "RELATED" | "FBURL" | "CALADRURI" | "CALURI" => "uri",
+        "LANG" => "language-tag",
+        name if name.starts_with("X-") => "unknown",
+        _ => "text",
+    }
+}
+
+/// Returns the values of the given property: structured values are
+/// written as an array of components, multiple values as several
+/// values. Text values are unescaped, dates and times are written in
+/// extended format.
+fn values(prop: &Property, value_type: &str) -> Vec<Value> {
+    match value_type {
+        "text" => (),
+        "date" | "time" | "date-time" | "date-and-or-time" | "timestamp" | "utc-offset" => {
+            return vec![json!(extended(prop.value.trim(), value_type))]
+        }
+        _ => return vec![json!(prop.value)],
+    }
+
+    match prop.name.as_str() {
+        "N" | "ADR" | "GENDER" => vec![json!(vcard::components(&prop.value))],
+        "ORG" => match vcard::components(&prop.value).as_slice() {
+            [org] => vec![json!(org)],
+            units => vec![json!(units)],
+        },
+        "CATEGORIES" | "NICKNAME" => vcard::list(&prop.value)
+            .into_iter()
+            .map(|value| json!(value))
+            .collect(),
+        _ => vec![json!(vcard::unescape(&prop.value))],
+    }
+}
+
+/// Converts the given date and/or time value from the basic format
+/// of vCard (`19850412T101500Z`) to the extended format required by
+/// jCard (`1985-04-12T10:15:00Z`). Values already in extended format
+/// are kept as they are.
+fn extended(value: &str, value_type: &str) -> String {
+    match value_type {
+        "date" => extended_date(value),
+        "time" => extended_time(value),
+        "utc-offset" => extended_offset(value),
+        _ => match value.split_once('T') {
+            Some((date, time)) => format!("{}T{}", extended_date(date), extended_time(time)),
+            None => extended_date(value),
+        },
+    }
+}
+
+/// Converts a date (`19850412`, `--0412`) to extended format.
+fn extended_date(date: &str) -> String {
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    match date.strip_prefix("--") {
+        Some(md) if md.len() == 4 && is_digits(md) => format!("--{}-{}", &md[..2], &md[2..]),
+        Some(_) => date.to_owned(),
+        None if date.len() == 8 && is_digits(date) => {
+            format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+        }
+        None => date.to_owned(),
+    }
+}
+
+/// Converts a time with an optional zone (`101500Z`, `1015+0200`,
+/// `-1500`) to extended format.
+fn extended_time(time: &str) -> String {
+    // a leading dash stands for a truncated hour, not for a zone
+    let zone_start = time
+        .char_indices()
+        .skip_while(|(_, c)| *c == '-')
+        .find(|(_, c)| matches!(c, 'Z' | 'z' | '+' | '-'))
+        .map_or(time.len(), |(i, _)| i);
+    let (time, zone) = time.split_at(zone_start);
+
+    let dashes = time.len() - time.trim_start_matches('-').len();
+    let digits = &time[dashes..];
+    let time = if digits.len() % 2 == 0 && digits.chars().all(|c| c.is_ascii_digit()) {
+        let pairs: Vec<&str> = (0..digits.len())
+            .step_by(2)
+            .map(|i| &digits[i..i + 2])
+            .collect();
+        format!("{}{}", &time[..dashes], pairs.join(":"))
+    } else {
+        time.to_owned()
+    };
+
+    format!("{}{}", time, extended_offset(zone))
+}
+
+/// Converts a UTC offset (`+0200`) to extended format.
+fn extended_offset(offset: &str) -> String {
+    let hm = offset.trim_start_matches(['+', '-']);
+    if offset.len() == hm.len() + 1 && hm.len() == 4 && hm.chars().all(|c| c.is_ascii_digit()) {
+        return format!("{}{}:{}", &offset[..1], &hm[..2], &hm[2..]);
+    }
+    offset.to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_to_jcard() {
+        let jcard = to_jcard(concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "FN:Doe\\, John\r\n",
+            "N:Doe;John;;;\r\n",
+            "item1.TEL;TYPE=work,voice;PREF=1:+33 6 00 00 00 00\r\n",
+            "CATEGORIES:friends,work\r\n",
+            "BDAY:19850412\r\n",
+            "ANNIVERSARY:--0412T1015+0200\r\n",
+            "REV:20261018T185918Z\r\n",
+            "X-FOO;VALUE=text:bar\r\n",
+            "END:VCARD\r\n",
+        ));
+
+        assert_eq!(
+            json!([
+                "vcard",
+                [
+                    ["version", {}, "text", "4.0"],
+                    ["fn", {}, "text", "Doe, John"],
+                    ["n", {}, "text", ["Doe", "John", "", "", ""]],
+                    [
+                        "tel",
+                        {"group": "item1", "type": ["work", "voice"], "pref": "1"},
+                        "text",
+                        "+33 6 00 00 00 00"
+                    ],
+                    ["categories", {}, "text", "friends", "work"],
+                    ["bday", {}, "date-and-or-time", "1985-04-12"],
+                    ["anniversary", {}, "date-and-or-time", "--04-12T10:15+02:00"],
+                    ["rev", {}, "timestamp", "2026-10-18T18:59:18Z"],
+                    ["x-foo", {}, "text", "bar"],
+                ]
+            ]),
+            jcard
+        );
+    }
+
+    #[test]
+    fn convert_to_extended_format() {
+        assert_eq!("1985-04-12", extended("1985-04-12", "date"));
+        assert_eq!("---12", extended("---12", "date"));
+        assert_eq!("-15:00", extended("-1500", "time"));
+        assert_eq!("10:15:00-05:00", extended("101500-0500", "time"));
+        assert_eq!("T10:15Z", extended("T1015z", "date-and-or-time"));
+        assert_eq!("+02:00", extended("+0200", "utc-offset"));
+    }
+}
@@ -0,0 +1,251 @@
+//! Journal module
+//!
+//! This module contains the sync journal. Before applying a patch,
+//! the whole patch is written down to the journal, then every hunk
+//! touching a card repository is recorded as soon as it is applied.
+//! A sync killed mid-way can then resume the very same patch and
+//! skip the hunks already applied, instead of computing a new patch
+//! from half-synchronized cards.
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    cache::write_atomically,
+    card::Card,
+    error::*,
+    sync::{Conflict, Hunk, HunkKind, Patch},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalHeader {
+    hunks: Vec<(HunkKind, Hunk)>,
+    #[serde(default)]
+    conflicts: Vec<Conflict>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    kind: HunkKind,
+    card: Card,
+}
+
+#[derive(Debug, Default)]
+pub struct Journal {
+    path: PathBuf,
+    hunks: Option<Vec<(HunkKind, Hunk)>>,
+    conflicts: Vec<Conflict>,
+    /// Represents the hunks already applied, associated to the card
+    /// as it was after being applied.
+    pub applied: HashMap<HunkKind, Card>,
+}
+
+impl Journal {
+    /// Reads the journal at the given path. A missing journal means
+    /// the previous sync (if any) terminated properly.
+    pub fn new(path: PathBuf) -> Result<Self> {
+        let mut journal = Self {
+            path,
+            ..Self::default()
+        };
+
+        if !journal.path.exists() {
+            return Ok(journal);
+        }
+
+        let file = fs::File::open(&journal.path)
+            .map_err(|e| CardamomError::ReadJournalError(journal.path.clone(), e))?;
+        let mut lines = BufReader::new(file).lines();
+
+        if let Some(line) = lines.next() {
+            let line =
+                line.map_err(|e| CardamomError::ReadJournalError(journal.path.clone(), e))?;
+            let header: JournalHeader = serde_json::from_str(&line)
+                .map_err(|e| CardamomError::ParseJournalError(journal.path.clone(), e))?;
+            journal.hunks = Some(header.hunks);
+            journal.conflicts = header.conflicts;
+        }
+
+        for line in lines {
+            let line =
+                line.map_err(|e| CardamomError::ReadJournalError(journal.path.clone(), e))?;
+            match serde_json::from_str::<JournalEntry>(&line) {
+                Ok(entry) => {
+                    journal.applied.insert(entry.kind, entry.card);
+                }
+                // the last entry can be truncated if the sync was
+                // killed while writing it, in which case the hunk is
+                // considered as not applied and the journal is
+                // rewritten without it
+                Err(err) => {
+                    warn!("skipping invalid journal entry: {}", err);
+                    journal.rewrite()?;
+                    break;
+                }
+            }
+        }
+
+        debug!(
+            "{} journaled hunk(s) already applied",
+            journal.applied.len()
+        );
+        Ok(journal)
+    }
+
+    /// Returns the patch of the interrupted sync, if any.
+    pub fn patch(&self) -> Option<Patch> {
+        self.hunks.as_ref().map(|hunks| {
+            let mut patch: Patch = hunks.iter().cloned().collect();
+            patch.conflicts = self.conflicts.clone();
+            patch
+        })
+    }
+
+    /// Writes down the patch about to be applied. If the journal
+    /// already holds this patch, the applied hunks are kept.
+    pub fn begin(&mut self, patch: &Patch) -> Result<()> {
+        if self.hunks.is_some() {
+            return Ok(());
+        }
+
+        self.hunks = Some(
+            patch
+                .iter()
+                .map(|(kind, hunk)| (kind.to_owned(), hunk.to_owned()))
+                .collect(),
+        );
+        self.conflicts = patch.conflicts.clone();
+        self.applied.clear();
+        self.rewrite()
+    }
+
+    /// Writes atomically the whole journal: the patch header followed
+    /// by the applied hunks.
+    fn rewrite(&self) -> Result<()> {
+        let header = JournalHeader {
+            hunks: self.hunks.clone().unwrap_or_default(),
+            conflicts: self.conflicts.clone(),
+        };
+        let mut buff =
+            serde_json::to_vec(&header).map_err(CardamomError::SerializeJournalEntryError)?;
+        buff.push(b'\n');
+
+        for (kind, card) in &self.applied {
+            let entry = JournalEntry {
+                kind: kind.to_owned(),
+                card: card.to_owned(),
+            };
+            serde_json::to_writer(&mut buff, &entry)
+                .map_err(CardamomError::SerializeJournalEntryError)?;
+            buff.push(b'\n');
+        }
+
+        write_atomically(&self.path, &buff)
+            .map_err(|e| CardamomError::WriteJournalError(self.path.clone(), e))
+    }
+
+    /// Records the given hunk as applied.
+    pub fn record(&mut self, kind: &HunkKind, card: &Card) -> Result<()> {
+        let entry = JournalEntry {
+            kind: kind.to_owned(),
+            card: card.to_owned(),
+        };
+        let mut entry_buff =
+            serde_json::to_vec(&entry).map_err(CardamomError::SerializeJournalEntryError)?;
+        entry_buff.push(b'\n');
+
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| CardamomError::WriteJournalError(self.path.clone(), e))?;
+        file.write_all(&entry_buff)
+            .and_then(|()| file.sync_data())
+            .map_err(|e| CardamomError::WriteJournalError(self.path.clone(), e))?;
+
+        self.applied.insert(entry.kind, entry.card);
+        Ok(())
+    }
+
+    /// Removes the journal once the patch is fully applied and the
+    /// caches are saved.
+    pub fn end(&mut self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)
+                .map_err(|e| CardamomError::WriteJournalError(self.path.clone(), e))?;
+        }
+
+        self.hunks = None;
+        self.conflicts.clear();
+        self.applied.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local};
+    use std::env;
+
+    use super::*;
+
+    fn card(id: &str) -> Card {
+        Card {
+            id: id.to_owned(),
+            date: DateTime::parse_from_rfc3339("2020-01-19T00:00:00+00:00")
+                .unwrap()
+                .with_timezone(&Local),
+            content: String::new(),
+            etag: None,
+            href: None,
+        }
+    }
+
+    #[test]
+    fn resume_interrupted_journal() {
+        let path = env::temp_dir().join("cardamom-journal-resume");
+        let _ = fs::remove_file(&path);
+
+        let mut patch = Patch::default();
+        patch.insert(HunkKind::NextLeft("a".into()), Hunk::Add(card("a")));
+        patch.insert(HunkKind::NextRight("b".into()), Hunk::Add(card("b")));
+
+        let mut journal = Journal::new(path.clone()).unwrap();
+        assert!(journal.patch().is_none());
+        journal.begin(&patch).unwrap();
+        journal
+            .record(&HunkKind::NextLeft("a".into()), &card("a"))
+            .unwrap();
+
+        // simulate a sync killed while writing an entry
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(br#"{"kind":{"NextRi"#)
+            .unwrap();
+
+        let mut journal = Journal::new(path.clone()).unwrap();
+        assert_eq!(2, journal.patch().unwrap().iter().count());
+        assert_eq!(1, journal.applied.len());
+        assert!(journal
+            .applied
+            .contains_key(&HunkKind::NextLeft("a".into())));
+
+        // the truncated entry must not swallow the next ones
+        journal
+            .record(&HunkKind::NextRight("b".into()), &card("b"))
+            .unwrap();
+        let mut journal = Journal::new(path.clone()).unwrap();
+        assert_eq!(2, journal.applied.len());
+
+        journal.end().unwrap();
+        assert!(!path.exists());
+        assert!(Journal::new(path).unwrap().patch().is_none());
+    }
+}
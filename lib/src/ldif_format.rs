@@ -0,0 +1,376 @@
+//! LDIF format module
+//!
+//! This module contains the reading and the writing of contacts from
+//! and to LDIF files, as defined in the [RFC2849], like the ones
+//! exported by Thunderbird or LDAP directories. Attributes of the
+//! `inetOrgPerson` and Mozilla address book schemas are mapped to
+//! contact fields.
+//!
+//! [RFC2849]: https://www.rfc-editor.org/rfc/rfc2849
+
+use log::{debug, warn};
+
+use crate::{
+    contact::Contact,
+    error::*,
+    import::{ContactBuilder, Field, Target},
+};
+
+/// Reads the contacts of the given LDIF content. Records are
+/// separated by empty lines.
+pub fn read_contacts(content: &str) -> Result<Vec<Contact>> {
+    let mut contacts = Vec::new();
+    let mut builder = None;
+
+    for (n, line) in unfold(content) {
+        if line.is_empty() {
+            contacts.extend(builder.take().and_then(ContactBuilder::build));
+            continue;
+        }
+
+        let (attr, value) = parse_line(n, &line)?;
+        if builder.is_none() && attr == "version" {
+            continue;
+        }
+        match target(&attr) {
+            Some(target) => builder
+                .get_or_insert_with(ContactBuilder::default)
+                .push(target, &value),
+            None => debug!("ignoring ldif attribute {:?}", attr),
+        }
+    }
+    contacts.extend(builder.and_then(ContactBuilder::build));
+
+    Ok(contacts)
+}
+
+/// Writes the given contacts as LDIF, one record per contact, with
+/// the attributes of the Mozilla address book schema.
+pub fn write_contacts(contacts: &[Contact]) -> String {
+    let mut ldif = String::new();
+
+    for contact in contacts {
+        let mut record = Record::default();
+        let name = contact.name().unwrap_or_default();
+        let dn = match contact.primary_email() {
+            Some(email) => format!("cn={},mail={}", escape_dn(name), escape_dn(&email.value)),
+            None => format!("cn={}", escape_dn(name)),
+        };
+        record.push("dn", &dn);
+        for class in ["top", "person", "organizationalPerson", "inetOrgPerson"] {
+            record.push("objectclass", class);
+        }
+        record.push("objectclass", "mozillaAbPersonAlpha");
+
+        record.push("cn", name);
+        if let Some(name) = contact.structured_name.as_ref() {
+            record.push("givenName", &name.given);
+            record.push("sn", &name.family);
+        }
+        for (n, email) in contact.emails.iter().take(2).enumerate() {
+            let attr = if n == 0 { "mail" } else { "mozillaSecondEmail" };
+            record.push(attr, &email.value);
+        }
+        for phone in &contact.phones {
+            let has_type = |t: &str| phone.types.iter().any(|t2| t2.eq_ignore_ascii_case(t));
+            let attr = if has_type("cell") {
+                "mobile"
+            } else if has_type("fax") {
+                "facsimileTelephoneNumber"
+            } else if has_type("pager") {
+                "pager"
+            } else if has_type("home") {
+                "homePhone"
+            } else {
+                "telephoneNumber"
+            };
+            record.push(attr, &phone.value);
+        }
+        if let Some(org) = contact.organization.as_ref() {
+            record.push("o", org);
+        }
+
+        let is_home = |types: &[String]| types.iter().any(|t| t.eq_ignore_ascii_case("home"));
+        for addr in &contact.addresses {
+            let attrs = if is_home(&addr.types) {
+                [
+                    "mozillaHomeStreet",
+                    "mozillaHomeStreet2",
+                    "mozillaHomeLocalityName",
+                    "mozillaHomeState",
+                    "mozillaHomePostalCode",
+                    "mozillaHomeCountryName",
+                ]
+            } else {
+                ["street", "mozillaWorkStreet2", "l", "st", "postalCode", "c"]
+            };
+            let values = [
+                &addr.street,
+                &addr.extended,
+                &addr.locality,
+                &addr.region,
+                &addr.postal_code,
+                &addr.country,
+            ];
+            for (attr, value) in attrs.iter().zip(values) {
+                record.push(attr, value);
+            }
+        }
+        for url in &contact.urls {
+            let attr = if is_home(&url.types) {
+                "mozillaHomeUrl"
+            } else {
+                "mozillaWorkUrl"
+            };
+            record.push(attr, &url.value);
+        }
+        record.push("description", &contact.notes.join("\n"));
+
+        // birthdays are written `YYYY-MM-DD`, `YYYYMMDD` or `--MMDD`
+        if let Some(bday) = contact.birthday.as_ref() {
+            let digits: String = bday.chars().take_while(|c| *c != 'T').collect();
+            let digits = digits.replace('-', "");
+            let (year, month_day) = match digits.len() {
+                8 => digits.split_at(4),
+                4 => ("", digits.as_str()),
+                _ => ("", ""),
+            };
+            if month_day.len() == 4 {
+                record.push("birthyear", year);
+                record.push("birthmonth", &month_day[..2]);
+                record.push("birthday", &month_day[2..]);
+            }
+        }
+
+        ldif.push_str(&record.lines);
+        ldif.push('\n');
+    }
+
+    ldif
+}
+
+/// Represents the attribute lines of a LDIF record being written.
+#[derive(Debug, Default)]
+struct Record {
+    lines: String,
+}
+
+impl Record {
+    /// Pushes an attribute line, unless the value is empty. Values
+    /// that are not safe strings are base64 encoded, and lines longer
+    /// than 76 characters are folded.
+    fn push(&mut self, attr: &str, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+        let line = if is_safe(value) {
+            format!("{}: {}", attr, value)
+        } else {
+            format!("{}:: {}", attr, base64::encode(value))
+        };
+
+        let mut len = 0;
+        for c in line.chars() {
+            if len >= 76 {
+                self.lines.push_str("\n ");
+                len = 1;
+            }
+            self.lines.push(c);
+            len += 1;
+        }
+        self.lines.push('\n');
+    }
+}
+
+/// Checks if the given value is a safe string, which can be written
+/// as it is (see the `SAFE-STRING` rule of the RFC).
+fn is_safe(value: &str) -> bool {
+    !value.starts_with([' ', ':', '<'])
+        && !value.ends_with(' ')
+        && value
+            .chars()
+            .all(|c| c.is_ascii() && c != '\0' && c != '\n' && c != '\r')
+}
+
+/// Escapes the special characters of the given distinguished name
+/// value.
+fn escape_dn(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Unfolds the lines of the given content: a line starting with a
+/// space continues the previous one. Comments are dropped. Lines are
+/// returned with their number, starting at 1.
+fn unfold(content: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut comment = false;
+
+    for (n, line) in content.lines().enumerate() {
+        match line.strip_prefix(' ') {
+            Some(rest) if !lines.is_empty() || comment => {
+                if !comment {
+                    lines.last_mut().unwrap().1.push_str(rest);
+                }
+            }
+            _ if line.starts_with('#') => comment = true,
+            _ => {
+                comment = false;
+                lines.push((n + 1, line.to_owned()));
+            }
+        }
+    }
+
+    lines
+}
+
+/// Parses an attribute line into its lowercased attribute name
+/// (without options) and its value. Values are either plain
+/// (`attr: value`) or base64 encoded (`attr:: dmFsdWU=`).
+fn parse_line(n: usize, line: &str) -> Result<(String, String)> {
+    let (attr, value) = line
+        .split_once(':')
+        .ok_or_else(|| CardamomError::ParseLdifError(n, "missing attribute value".into()))?;
+    // options (like `;lang-fr`) are not supported
+    let attr = attr.split(';').next().unwrap_or_default().to_lowercase();
+
+    let value = if let Some(value) = value.strip_prefix(':') {
+        let buff = base64::decode(value.trim())
+            .map_err(|e| CardamomError::ParseLdifError(n, e.to_string()))?;
+        String::from_utf8(buff).map_err(|e| CardamomError::ParseLdifError(n, e.to_string()))?
+    } else if value.starts_with('<') {
+        warn!("ignoring ldif attribute {:?} referring to an url", attr);
+        String::new()
+    } else {
+        value.trim_start().to_owned()
+    };
+
+    Ok((attr, value))
+}
+
+/// Returns the target of the given lowercased attribute.
+fn target(attr: &str) -> Option<Target> {
+    let (field, types): (Field, &[&str]) = match attr {
+        "cn" | "displayname" => (Field::Name, &[]),
+        "givenname" => (Field::GivenName, &[]),
+        "sn" | "surname" => (Field::FamilyName, &[]),
+        "mail" | "mozillasecondemail" => (Field::Email, &[]),
+        "telephonenumber" => (Field::Phone, &["work"]),
+        "homephone" => (Field::Phone, &["home"]),
+        "mobile" => (Field::Phone, &["cell"]),
+        "facsimiletelephonenumber" | "fax" => (Field::Phone, &["fax"]),
+        "pager" => (Field::Phone, &["pager"]),
+        "o" | "company" => (Field::Org, &[]),
+        "street" | "streetaddress" | "postaladdress" => (Field::Street, &["work"]),
+        "mozillaworkstreet2" => (Field::Extended, &["work"]),
+        "l" | "locality" => (Field::Locality, &["work"]),
+        "st" => (Field::Region, &["work"]),
+        "postalcode" => (Field::PostalCode, &["work"]),
+        "c" | "countryname" => (Field::Country, &["work"]),
+        "mozillahomestreet" | "homepostaladdress" => (Field::Street, &["home"]),
+        "mozillahomestreet2" => (Field::Extended, &["home"]),
+        "mozillahomelocalityname" => (Field::Locality, &["home"]),
+        "mozillahomestate" => (Field::Region, &["home"]),
+        "mozillahomepostalcode" => (Field::PostalCode, &["home"]),
+        "mozillahomecountryname" => (Field::Country, &["home"]),
+        "mozillaworkurl" | "workurl" => (Field::Url, &["work"]),
+        "mozillahomeurl" | "homeurl" => (Field::Url, &["home"]),
+        "labeleduri" => (Field::Url, &[]),
+        "description" | "note" => (Field::Note, &[]),
+        "birthyear" => (Field::BirthYear, &[]),
+        "birthmonth" => (Field::BirthMonth, &[]),
+        "birthday" => (Field::BirthDay, &[]),
+        _ => return None,
+    };
+    Some(Target::new(field, types))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::contact::{Address, TypedValue};
+
+    use super::*;
+
+    #[test]
+    fn read_ldif_contacts() {
+        let contacts = read_contacts(concat!(
+            "version: 1\n",
+            "\n",
+            "# exported by Thunderbird\n",
+            "dn: cn=Alice Smith,mail=alice@corp.com\n",
+            "objectclass: top\n",
+            "objectclass: person\n",
+            "cn: Alice Smith\n",
+            "mail: alice@corp.com\n",
+            "mobile: +33 6 00 00 00 00\n",
+            "description:: Q2Fmw6kgb3duZXI=\n",
+            "mozillaHomeStreet: 1 Main\n",
+            "  St\n",
+            "mozillaHomeLocalityName: Springfield\n",
+            "birthmonth: 04\n",
+            "birthday: 12\n",
+            "\n",
+            "dn: cn=Bob\n",
+            "cn: Bob\n",
+        ))
+        .unwrap();
+
+        assert_eq!(2, contacts.len());
+        let alice = &contacts[0];
+        assert_eq!(Some("Alice Smith"), alice.name());
+        assert_eq!("alice@corp.com", alice.emails[0].value);
+        assert_eq!(vec!["cell"], alice.phones[0].types);
+        assert_eq!(vec!["Café owner"], alice.notes);
+        assert_eq!("1 Main St", alice.addresses[0].street);
+        assert_eq!(vec!["home"], alice.addresses[0].types);
+        assert_eq!(Some("--0412".into()), alice.birthday);
+        assert_eq!(Some("Bob"), contacts[1].name());
+
+        assert!(read_contacts("cn Alice\n").is_err());
+    }
+
+    #[test]
+    fn write_and_read_ldif_contacts() {
+        let alice = Contact {
+            formatted_name: Some("Alice, Smith".into()),
+            emails: vec![TypedValue {
+                value: "alice@corp.com".into(),
+                ..TypedValue::default()
+            }],
+            phones: vec![TypedValue {
+                types: vec!["cell".into()],
+                value: "+33 6 00 00 00 00".into(),
+                ..TypedValue::default()
+            }],
+            addresses: vec![Address {
+                types: vec!["home".into()],
+                street: "1 Main St".into(),
+                locality: "Springfield".into(),
+                ..Address::default()
+            }],
+            notes: vec![format!("Café owner, {}", "a".repeat(80))],
+            birthday: Some("1985-04-12".into()),
+            ..Contact::default()
+        };
+
+        let ldif = write_contacts(std::slice::from_ref(&alice));
+        assert!(ldif.starts_with("dn: cn=Alice\\, Smith,mail=alice@corp.com\n"));
+        assert!(ldif.contains("\ndescription:: "));
+        assert!(ldif.lines().all(|line| line.len() <= 76));
+
+        let contacts = read_contacts(&ldif).unwrap();
+        assert_eq!(1, contacts.len());
+        assert_eq!(alice.formatted_name, contacts[0].formatted_name);
+        assert_eq!(alice.emails, contacts[0].emails);
+        assert_eq!(alice.phones, contacts[0].phones);
+        assert_eq!(alice.addresses, contacts[0].addresses);
+        assert_eq!(alice.notes, contacts[0].notes);
+        assert_eq!(Some("19850412".into()), contacts[0].birthday);
+    }
+}
@@ -1,8 +1,32 @@
+pub mod backend;
 pub mod cache;
 pub mod card;
 pub mod card_parsers;
+pub mod card_repository;
+#[cfg(feature = "async")]
 pub mod carddav;
+pub mod contact;
+pub mod csv_format;
+pub mod dedupe;
 pub mod error;
+pub mod export;
+pub mod import;
+pub mod index;
+pub mod jcard;
+pub mod journal;
+pub mod ldif_format;
+pub mod lint;
 pub mod local;
+pub mod lock;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod phone;
+#[cfg(feature = "async")]
 pub mod remote;
+pub mod report;
+pub mod search;
 pub mod sync;
+pub mod tombstone;
+pub mod vcard;
+pub mod vcard_version;
+pub mod vcf_file;
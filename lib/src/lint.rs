@@ -0,0 +1,588 @@
+//! Lint module
+//!
+//! This module contains the validation of cards: the problems which
+//! make CardDAV servers reject a card (or silently lose part of it)
+//! are detected before the card is uploaded. Some of them can be
+//! repaired automatically without changing the meaning of the card.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    card::Card,
+    contact::Contact,
+    vcard::{self, Property},
+};
+
+/// Represents the maximum size of an inline photo, in bytes. Bigger
+/// photos are rejected or downscaled by most servers.
+const MAX_PHOTO_SIZE: usize = 256 * 1024;
+
+/// Represents the parameters defined by the vCard 3.0 and 4.0
+/// versions. Extended parameters (`X-`) are always accepted.
+const KNOWN_PARAMS: [&str; 17] = [
+    "ALTID",
+    "CALSCALE",
+    "CC",
+    "CHARSET",
+    "ENCODING",
+    "GEO",
+    "INDEX",
+    "LABEL",
+    "LANGUAGE",
+    "LEVEL",
+    "MEDIATYPE",
+    "PID",
+    "PREF",
+    "SORT-AS",
+    "TYPE",
+    "TZ",
+    "VALUE",
+];
+
+/// Represents the severity of a problem. Cards with errors are not
+/// uploaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Represents a problem found in a card.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    /// Represents the line of the problem, starting at 1, when the
+    /// problem is located.
+    pub line: Option<usize>,
+    pub message: String,
+    /// Tells if the problem is repaired by [`fix`].
+    pub fixable: bool,
+}
+
+impl Problem {
+    fn error(line: Option<usize>, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            line,
+            message,
+            fixable: false,
+        }
+    }
+
+    fn warning(line: Option<usize>, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            line,
+            message,
+            fixable: false,
+        }
+    }
+
+    fn fixable(mut self) -> Self {
+        self.fixable = true;
+        self
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)?;
+        if self.fixable {
+            write!(f, " (fixable)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents the problems found in a set of cards, by card location
+/// (the file name for local cards). Cards without problem are left
+/// out.
+#[derive(Debug, Default, Serialize)]
+pub struct LintReport {
+    pub files: BTreeMap<String, Vec<Problem>>,
+    /// Represents the locations of the cards repaired by [`fix`].
+    pub fixed: Vec<String>,
+}
+
+impl LintReport {
+    /// Returns the number of problems of the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.files
+            .values()
+            .flatten()
+            .filter(|problem| problem.severity == severity)
+            .count()
+    }
+}
+
+/// Checks the given cards one by one, then checks that their UIDs are
+/// unique.
+pub fn lint_cards(cards: &[Card]) -> LintReport {
+    let location = |card: &Card| card.href.clone().unwrap_or_else(|| card.id.clone());
+    let mut report = LintReport::default();
+
+    let mut locations_by_uid: HashMap<String, Vec<String>> = HashMap::new();
+    for card in cards {
+        if let Some(uid) = card.uid() {
+            locations_by_uid
+                .entry(uid)
+                .or_default()
+                .push(location(card));
+        }
+    }
+
+    for card in cards {
+        let mut problems = lint(&card.content);
+        if let Some(uid) = card.uid() {
+            let others: Vec<String> = locations_by_uid[&uid]
+                .iter()
+                .filter(|other| **other != location(card))
+                .cloned()
+                .collect();
+            if !others.is_empty() {
+                let message = format!("UID {:?} also used by {}", uid, others.join(", "));
+                problems.push(Problem::error(None, message));
+            }
+        }
+        if !problems.is_empty() {
+            report.files.insert(location(card), problems);
+        }
+    }
+
+    report
+}
+
+/// Checks the given card content.
+pub fn lint(content: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    let content = match content.strip_prefix('\u{feff}') {
+        Some(content) => {
+            problems
+                .push(Problem::warning(Some(1), "byte order mark before card".into()).fixable());
+            content
+        }
+        None => content,
+    };
+    if content.matches('\n').count() != content.matches("\r\n").count() {
+        let message = String::from("lines not ending with CRLF");
+        problems.push(Problem::warning(None, message).fixable());
+    }
+
+    // content lines are unfolded, keeping the number of their first
+    // line
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (n, line) in content.split('\n').enumerate() {
+        let n = n + 1;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.len() > 75 {
+            let message = format!("line of {} octets longer than 75 octets", line.len());
+            problems.push(Problem::warning(Some(n), message).fixable());
+        }
+        match (line.strip_prefix(&[' ', '\t'][..]), lines.last_mut()) {
+            (Some(folded_line), Some((_, last_line))) => last_line.push_str(folded_line),
+            (Some(_), None) => {
+                let message = String::from("folded line without previous content line");
+                problems.push(Problem::error(Some(n), message));
+            }
+            _ if line.is_empty() => (),
+            _ => lines.push((n, line.to_owned())),
+        }
+    }
+
+    let mut props: Vec<(usize, Property)> = Vec::new();
+    for (n, line) in lines {
+        if line.chars().any(|c| c.is_control() && c != '\t') {
+            let message = String::from("control character in content line");
+            problems.push(Problem::error(Some(n), message).fixable());
+        }
+        if line.contains('\u{fffd}') {
+            let message = String::from("invalid UTF-8 sequence in content line");
+            problems.push(Problem::warning(Some(n), message));
+        }
+        match vcard::parse_line(&line) {
+            Some(prop) => props.push((n, prop)),
+            None => {
+                let message = String::from("content line without value, or badly folded");
+                problems.push(Problem::error(Some(n), message));
+            }
+        }
+    }
+
+    lint_props(&props, &mut problems);
+    problems
+}
+
+/// Checks the parsed properties of a card.
+fn lint_props(props: &[(usize, Property)], problems: &mut Vec<Problem>) {
+    let find = |name: &str| props.iter().find(|(_, prop)| prop.name == name);
+    let is_card_delimiter = |prop: Option<&(usize, Property)>, name: &str| {
+        prop.map(|(_, prop)| prop.name == name && prop.value.eq_ignore_ascii_case("VCARD"))
+            .unwrap_or(false)
+    };
+
+    if !is_card_delimiter(props.first(), "BEGIN") {
+        problems.push(Problem::error(
+            None,
+            "card not starting with BEGIN:VCARD".into(),
+        ));
+    }
+    if !is_card_delimiter(props.last(), "END") {
+        problems.push(Problem::error(
+            None,
+            "card not ending with END:VCARD".into(),
+        ));
+    }
+    if props
+        .iter()
+        .filter(|(_, prop)| prop.name == "BEGIN")
+        .count()
+        > 1
+    {
+        problems.push(Problem::error(
+            None,
+            "several cards in the same file".into(),
+        ));
+    }
+
+    let version = find("VERSION").map(|(_, prop)| prop.value.trim());
+    match version {
+        None => problems.push(Problem::error(None, "missing VERSION property".into())),
+        Some("3.0") | Some("4.0") => (),
+        Some(version) => {
+            let message = format!("vCard version {} not supported by CardDAV", version);
+            problems.push(Problem::error(find("VERSION").map(|(n, _)| *n), message));
+        }
+    }
+
+    // a missing name can be built from the other properties of the
+    // card, a missing UID from the file name
+    if find("FN").is_none() {
+        let problem = Problem::error(None, "missing FN property".into());
+        problems.push(match formatted_name(props) {
+            Some(_) => problem.fixable(),
+            None => problem,
+        });
+    }
+    if version == Some("3.0") && find("N").is_none() {
+        problems.push(
+            Problem::error(None, "missing N property, required by vCard 3.0".into()).fixable(),
+        );
+    }
+    if find("UID").is_none() {
+        problems.push(Problem::warning(None, "missing UID property".into()).fixable());
+    }
+
+    for (n, prop) in props {
+        let n = Some(*n);
+        lint_params(n, prop, version, problems);
+
+        let is_text = prop
+            .param("VALUE")
+            .iter()
+            .any(|v| v.eq_ignore_ascii_case("text"));
+        if matches!(
+            prop.name.as_str(),
+            "BDAY" | "ANNIVERSARY" | "DEATHDATE" | "REV"
+        ) && !is_text
+            && !is_valid_date(prop.value.trim())
+        {
+            let message = format!("invalid {} date {:?}", prop.name, prop.value);
+            problems.push(Problem::error(n, message));
+        }
+
+        if matches!(prop.name.as_str(), "PHOTO" | "LOGO") {
+            let data = match prop.value.strip_prefix("data:") {
+                Some(uri) => uri
+                    .split_once(',')
+                    .map(|(_, data)| data)
+                    .unwrap_or_default(),
+                None if !prop.param("ENCODING").is_empty() => &prop.value,
+                None => "",
+            };
+            let size = data.len() / 4 * 3;
+            if size > MAX_PHOTO_SIZE {
+                let message = format!(
+                    "{} of {} KiB bigger than {} KiB",
+                    prop.name.to_lowercase(),
+                    size / 1024,
+                    MAX_PHOTO_SIZE / 1024
+                );
+                problems.push(Problem::warning(n, message));
+            }
+        }
+    }
+}
+
+/// Checks the parameters of a property.
+fn lint_params(
+    n: Option<usize>,
+    prop: &Property,
+    version: Option<&str>,
+    problems: &mut Vec<Problem>,
+) {
+    for (name, _) in &prop.params {
+        if !name.starts_with("X-") && !KNOWN_PARAMS.contains(&name.as_str()) {
+            let message = format!("unknown parameter {} of {}", name, prop.name);
+            problems.push(Problem::warning(n, message));
+        }
+    }
+
+    if !prop.param("CHARSET").is_empty() {
+        let message = String::from("CHARSET parameter not supported by vCard 3.0 and 4.0");
+        problems.push(Problem::warning(n, message).fixable());
+    }
+    for encoding in prop.param("ENCODING") {
+        let message = match (encoding.to_lowercase().as_str(), version) {
+            ("b", Some("3.0")) => continue,
+            ("b" | "base64", Some("4.0")) => {
+                String::from("inline binary value instead of a data URI in vCard 4.0")
+            }
+            _ => format!("{} encoding not supported", encoding),
+        };
+        problems.push(Problem::error(n, message));
+    }
+}
+
+/// Builds the formatted name of a card missing it, from its
+/// structured name, its organization or its email address.
+fn formatted_name(props: &[(usize, Property)]) -> Option<String> {
+    let content: String = props.iter().map(|(_, prop)| prop.to_line()).collect();
+    let contact = Contact::parse("", &content);
+    contact
+        .structured_name
+        .as_ref()
+        .and_then(|name| name.display())
+        .or_else(|| contact.organization.clone())
+        .or_else(|| contact.primary_email().map(|email| email.value.clone()))
+        .filter(|name| !name.trim().is_empty())
+}
+
+/// Checks a date, a date and time, or a time (starting with `T`), as
+/// written by vCard 3.0 (`1985-04-12`) or 4.0 (`19850412`, `--0412`).
+fn is_valid_date(value: &str) -> bool {
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+
+    let is_valid_date = |y: i32, m: u32, d: u32| NaiveDate::from_ymd_opt(y, m, d).is_some();
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    let num = |s: &str| s.parse::<u32>().unwrap_or(0);
+    let date_is_valid = match date.len() {
+        0 => time.is_some(),
+        4 if digits(date) => true,
+        8 if digits(date) => {
+            is_valid_date(num(&date[..4]) as i32, num(&date[4..6]), num(&date[6..]))
+        }
+        10 if date.as_bytes()[4] == b'-' && date.as_bytes()[7] == b'-' => {
+            let parts: Vec<&str> = date.split('-').collect();
+            parts.iter().all(|part| digits(part))
+                && is_valid_date(num(parts[0]) as i32, num(parts[1]), num(parts[2]))
+        }
+        // dates without year, month or day
+        7 if date.as_bytes()[4] == b'-' && digits(&date[..4]) && digits(&date[5..]) => {
+            (1..=12).contains(&num(&date[5..]))
+        }
+        6 if date.starts_with("--") && digits(&date[2..]) => {
+            is_valid_date(2000, num(&date[2..4]), num(&date[4..]))
+        }
+        7 if date.starts_with("--") && date.as_bytes()[4] == b'-' => {
+            digits(&date[2..4])
+                && digits(&date[5..])
+                && is_valid_date(2000, num(&date[2..4]), num(&date[5..]))
+        }
+        5 if date.starts_with("---") && digits(&date[3..]) => (1..=31).contains(&num(&date[3..])),
+        4 if date.starts_with("--") && digits(&date[2..]) => (1..=12).contains(&num(&date[2..])),
+        _ => false,
+    };
+
+    date_is_valid && time.into_iter().all(is_valid_time)
+}
+
+/// Checks a time, with an optional UTC offset.
+fn is_valid_time(time: &str) -> bool {
+    let time = time.strip_suffix('Z').unwrap_or(time);
+    let time = match time.rfind(&['+', '-'][..]) {
+        Some(pos) if pos > 0 => &time[..pos],
+        _ => time,
+    };
+    let digits: String = time.chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) || !matches!(digits.len(), 2 | 4 | 6) {
+        return false;
+    }
+    let part = |i: usize| digits.get(i..i + 2).map(|p| p.parse::<u32>().unwrap_or(99));
+    matches!(part(0), Some(h) if h < 24)
+        && part(2).into_iter().all(|m| m < 60)
+        && part(4).into_iter().all(|s| s <= 60)
+}
+
+/// Repairs the fixable problems of the given card content (see
+/// [`lint`]). The card of the given id gets it as UID if it has none.
+/// Content lines are written again, folded and ending with CRLF.
+pub fn fix(content: &str, id: &str) -> String {
+    let content = content.trim_start_matches('\u{feff}');
+    let mut lines: Vec<String> = Vec::new();
+    let mut props: Vec<(usize, Property)> = Vec::new();
+
+    for line in vcard::unfold(content) {
+        let line: String = line
+            .chars()
+            .filter(|c| !c.is_control() || *c == '\t')
+            .collect();
+        match vcard::parse_line(&line) {
+            Some(mut prop) => {
+                prop.params
+                    .retain(|(name, _)| !name.eq_ignore_ascii_case("CHARSET"));
+                lines.push(prop.to_line());
+                props.push((lines.len(), prop));
+            }
+            None => lines.push(vcard::fold(&line)),
+        }
+    }
+
+    let has = |name: &str| props.iter().any(|(_, prop)| prop.name == name);
+    let mut missing_props = Vec::new();
+    if !has("FN") {
+        if let Some(name) = formatted_name(&props) {
+            missing_props.push(("FN", vcard::escape(&name)));
+        }
+    }
+    let is_v3 = props
+        .iter()
+        .any(|(_, prop)| prop.name == "VERSION" && prop.value.trim() == "3.0");
+    if is_v3 && !has("N") {
+        missing_props.push(("N", String::from(";;;;")));
+    }
+    if !has("UID") {
+        missing_props.push(("UID", vcard::escape(id)));
+    }
+
+    let end = lines
+        .iter()
+        .rposition(|line| line.to_uppercase().starts_with("END:VCARD"))
+        .unwrap_or(lines.len());
+    for (name, value) in missing_props.into_iter().rev() {
+        let prop = Property {
+            group: None,
+            name: name.to_owned(),
+            params: Vec::new(),
+            value,
+        };
+        lines.insert(end, prop.to_line());
+    }
+
+    lines.concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+
+    fn messages(problems: &[Problem]) -> Vec<String> {
+        problems.iter().map(Problem::to_string).collect()
+    }
+
+    #[test]
+    fn lint_valid_card() {
+        let problems = lint(concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "UID:alice\r\n",
+            "FN:Alice\r\n",
+            "TEL;TYPE=cell;PREF=1;X-CUSTOM=1:+33 6 00 00 00 00\r\n",
+            "BDAY:--0412\r\n",
+            "ANNIVERSARY:20100601T1200Z\r\n",
+            "REV:2022-03-01T10:00:00+01:00\r\n",
+            "END:VCARD\r\n",
+        ));
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn lint_invalid_card() {
+        let content = concat!(
+            "\u{feff}BEGIN:VCARD\n",
+            "VERSION:3.0\n",
+            "EMAIL;CHARSET=utf-8;FOO=bar:alice@corp.com\n",
+            "NOTE:a\u{7}b\n",
+            "BDAY:1985-02-30\n",
+            "PHOTO;ENCODING=QUOTED-PRINTABLE:xx\n",
+            "broken line\n",
+            "END:VCARD\n",
+        );
+        assert_eq!(
+            vec![
+                "line 1: warning: byte order mark before card (fixable)",
+                "warning: lines not ending with CRLF (fixable)",
+                "line 4: error: control character in content line (fixable)",
+                "line 7: error: content line without value, or badly folded",
+                "error: missing FN property (fixable)",
+                "error: missing N property, required by vCard 3.0 (fixable)",
+                "warning: missing UID property (fixable)",
+                "line 3: warning: unknown parameter FOO of EMAIL",
+                "line 3: warning: CHARSET parameter not supported by vCard 3.0 and 4.0 (fixable)",
+                "line 5: error: invalid BDAY date \"1985-02-30\"",
+                "line 6: error: QUOTED-PRINTABLE encoding not supported",
+            ],
+            messages(&lint(content))
+        );
+
+        let fixed = fix(content, "alice");
+        assert_eq!(
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "EMAIL;FOO=bar:alice@corp.com\r\n",
+                "NOTE:ab\r\n",
+                "BDAY:1985-02-30\r\n",
+                "PHOTO;ENCODING=QUOTED-PRINTABLE:xx\r\n",
+                "broken line\r\n",
+                "FN:alice@corp.com\r\n",
+                "N:;;;;\r\n",
+                "UID:alice\r\n",
+                "END:VCARD\r\n",
+            ),
+            fixed
+        );
+        assert!(lint(&fixed).iter().all(|problem| !problem.fixable));
+    }
+
+    #[test]
+    fn lint_duplicate_uids() {
+        let card = |href: &str| Card {
+            id: String::from("alice"),
+            date: Local::now(),
+            content: "BEGIN:VCARD\r\nVERSION:4.0\r\nUID:alice\r\nFN:Alice\r\nEND:VCARD\r\n".into(),
+            etag: None,
+            href: Some(href.to_owned()),
+        };
+
+        let report = lint_cards(&[card("a.vcf"), card("b.vcf")]);
+        assert_eq!(2, report.count(Severity::Error));
+        assert_eq!(
+            vec!["error: UID \"alice\" also used by b.vcf"],
+            messages(&report.files["a.vcf"])
+        );
+    }
+}
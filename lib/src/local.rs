@@ -1,57 +1,138 @@
-use std::{collections::HashMap, fs, ops::Deref, path::PathBuf};
+use chrono::{DateTime, Local};
+use log::warn;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    cache::CachedCards,
-    card::{Card, Cards, CardsMap},
+    backend::Backend,
+    card::{href_from_id, Card, CardsMap},
     error::*,
+    vcard,
 };
 
+/// Represents the local backend: a directory holding one vCard file
+/// per card.
 #[derive(Debug, Default)]
 pub struct LocalCards {
     sync_dir: PathBuf,
-    prev: CardsMap,
-    next: CardsMap,
 }
 
-impl Cards for LocalCards {
-    fn prev(&self) -> &CardsMap {
-        &self.prev
+impl LocalCards {
+    pub fn new(sync_dir: PathBuf) -> Self {
+        Self { sync_dir }
+    }
+
+    /// Returns the path of the file holding the given card.
+    fn vcf_path(&self, card: &Card) -> PathBuf {
+        let file_name = card
+            .href
+            .to_owned()
+            .unwrap_or_else(|| href_from_id(&card.id));
+        self.sync_dir.join(file_name)
     }
 
-    fn next(&self) -> &CardsMap {
-        &self.next
+    /// Returns the paths of all the vCard files of the directory.
+    fn vcf_paths(&self) -> Result<impl Iterator<Item = PathBuf>> {
+        Ok(fs::read_dir(&self.sync_dir)
+            .map_err(|e| CardamomError::ReadLocalCardsDirError(self.sync_dir.clone(), e))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map(|ext| ext == "vcf").unwrap_or(false)))
+    }
+
+    fn read_vcf(&self, path: PathBuf) -> Result<Card> {
+        let href = href(&path);
+        let date = date(&path)?;
+        let content =
+            fs::read_to_string(&path).map_err(|e| CardamomError::ReadVcfError(path.clone(), e))?;
+        let id = vcard::uid(&content).unwrap_or_else(|| {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        });
+        Ok(Card {
+            id,
+            date,
+            content,
+            etag: None,
+            href,
+        })
     }
 }
 
-impl LocalCards {
-    pub fn new(sync_dir: PathBuf) -> Result<Self> {
-        let prev = CachedCards::new(sync_dir.join(".local"))?.cards;
-        let mut next = HashMap::default();
+impl Backend for LocalCards {
+    fn list(&self) -> Result<CardsMap> {
+        let mut cards = HashMap::default();
 
-        let vcf_entries = fs::read_dir(&sync_dir)
-            .map_err(|e| CardamomError::ReadLocalCardsDirError(sync_dir.clone(), e))?
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| entry.path().ends_with(".vcf"));
-        for vcf in vcf_entries {
-            if let Some(vcf_file_name) = PathBuf::from(vcf.path()).file_stem() {
-                let card = Card {
-                    id: vcf_file_name.to_string_lossy().to_string(),
-                    date: vcf
-                        .metadata()
-                        .map_err(|e| CardamomError::GetVcfMetadataError(vcf.path().to_owned(), e))?
-                        .modified()
-                        .map_err(|e| CardamomError::GetVcfModifiedError(vcf.path().to_owned(), e))?
-                        .into(),
-                    content: String::default(),
-                };
-                next.insert(card.id.clone(), card);
+        for vcf_path in self.vcf_paths()? {
+            let card = self.read_vcf(vcf_path)?;
+            if let Some(dup) = cards.insert(card.id.clone(), card) {
+                warn!("duplicate local card {:?} at {:?}", dup.id, dup.href);
             }
         }
 
-        Ok(Self {
-            sync_dir,
-            prev,
-            next,
-        })
+        Ok(cards)
+    }
+
+    fn list_all(&self) -> Result<Vec<Card>> {
+        self.vcf_paths()?
+            .map(|vcf_path| self.read_vcf(vcf_path))
+            .collect()
+    }
+
+    fn list_dates(&self) -> Result<HashMap<String, DateTime<Local>>> {
+        let mut dates = HashMap::default();
+
+        for vcf_path in self.vcf_paths()? {
+            if let Some(href) = href(&vcf_path) {
+                dates.insert(href, date(&vcf_path)?);
+            }
+        }
+
+        Ok(dates)
+    }
+
+    fn get(&self, href: &str) -> Result<Card> {
+        self.read_vcf(self.sync_dir.join(href))
+    }
+
+    fn put(&self, card: &mut Card) -> Result<()> {
+        let path = self.vcf_path(card);
+        fs::write(&path, &card.content)
+            .map_err(|e| CardamomError::WriteVcfError(path.clone(), e))?;
+        // the card now matches the written file, otherwise the next
+        // sync would consider the file as modified
+        let written_card = self.read_vcf(path)?;
+        card.date = written_card.date;
+        card.etag = written_card.etag;
+        card.href = written_card.href;
+        Ok(())
     }
+
+    fn delete(&self, card: &Card) -> Result<()> {
+        let path = self.vcf_path(card);
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| CardamomError::DeleteVcfError(path, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the location of the card at the given path: its file name.
+fn href(path: &Path) -> Option<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+}
+
+/// Returns the modification date of the file at the given path.
+fn date(path: &Path) -> Result<DateTime<Local>> {
+    Ok(fs::metadata(path)
+        .map_err(|e| CardamomError::GetVcfMetadataError(path.to_owned(), e))?
+        .modified()
+        .map_err(|e| CardamomError::GetVcfModifiedError(path.to_owned(), e))?
+        .into())
 }
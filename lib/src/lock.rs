@@ -0,0 +1,156 @@
+//! Lock module
+//!
+//! This module contains the sync lock, an advisory lock taken on a
+//! file of the sync directory so that two syncs of the same account
+//! cannot run at the same time. The lock file holds the id of the
+//! process owning the lock, which helps to report who holds it and
+//! to detect locks left by processes that died before releasing it.
+
+use fs2::FileExt;
+use log::{debug, warn};
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    process,
+};
+
+use crate::error::*;
+
+#[derive(Debug)]
+pub struct SyncLock {
+    path: PathBuf,
+    file: File,
+    /// Represents the id of the dead process which left the lock
+    /// file behind, if any.
+    pub stale_pid: Option<u32>,
+}
+
+impl SyncLock {
+    /// Takes the lock at the given path. If the lock is already taken,
+    /// either waits for it to be released or fails straight away.
+    pub fn acquire(path: PathBuf, wait: bool) -> Result<Self> {
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| CardamomError::OpenSyncLockError(path.clone(), e))?;
+
+        match file.try_lock_exclusive() {
+            Ok(()) => (),
+            Err(err) if err.kind() == fs2::lock_contended_error().kind() => {
+                let pid = read_pid(&mut file);
+                if !wait {
+                    return Err(CardamomError::SyncLockedError(path, pid));
+                }
+                debug!("waiting for sync lock held by process {:?}", pid);
+                file.lock_exclusive()
+                    .map_err(|e| CardamomError::LockSyncError(path.clone(), e))?;
+            }
+            Err(err) => return Err(CardamomError::LockSyncError(path, err)),
+        }
+
+        // the lock is released by the system when its owner dies, but
+        // the id written in the file stays: if that process is dead,
+        // the previous sync did not terminate properly
+        let stale_pid = read_pid(&mut file).filter(|pid| !is_alive(*pid));
+        if let Some(pid) = stale_pid {
+            debug!(
+                "found stale sync lock at {:?} left by process {}",
+                path, pid
+            );
+        }
+
+        let pid = process::id().to_string();
+        file.set_len(0)
+            .and_then(|()| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(pid.as_bytes()))
+            .and_then(|()| file.sync_data())
+            .map_err(|e| CardamomError::LockSyncError(path.clone(), e))?;
+
+        Ok(Self {
+            path,
+            file,
+            stale_pid,
+        })
+    }
+}
+
+impl Drop for SyncLock {
+    fn drop(&mut self) {
+        // the file is emptied rather than removed: removing it would
+        // let another process lock a new file while a third one is
+        // still waiting on the old one
+        if let Err(err) = self.file.set_len(0).and_then(|()| self.file.unlock()) {
+            warn!("cannot release sync lock at {:?}: {}", self.path, err);
+        }
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut pid = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
+
+/// Returns true if the process matching the given id is running. On
+/// systems where it cannot be checked, processes are considered as
+/// running.
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+    // the signal 0 only checks whether the process can be signaled
+    let signaled = unsafe { libc::kill(pid, 0) } == 0;
+    signaled || std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+#[cfg(not(unix))]
+fn is_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn lock_is_exclusive() {
+        let path = env::temp_dir().join("cardamom-sync-lock-exclusive");
+        let _ = fs::remove_file(&path);
+
+        let lock = SyncLock::acquire(path.clone(), false).unwrap();
+        assert_eq!(None, lock.stale_pid);
+        assert!(matches!(
+            SyncLock::acquire(path.clone(), false).unwrap_err(),
+            CardamomError::SyncLockedError(_, Some(pid)) if pid == process::id()
+        ));
+
+        drop(lock);
+        assert!(SyncLock::acquire(path, false).is_ok());
+    }
+
+    #[test]
+    fn lock_detects_stale_pid() {
+        let path = env::temp_dir().join("cardamom-sync-lock-stale");
+        // no system has that many processes
+        let dead_pid = i32::MAX as u32;
+        fs::write(&path, dead_pid.to_string()).unwrap();
+
+        let lock = SyncLock::acquire(path.clone(), false).unwrap();
+        assert_eq!(Some(dead_pid), lock.stale_pid);
+        drop(lock);
+
+        // a lock file left by a running process is not stale
+        fs::write(&path, process::id().to_string()).unwrap();
+        let lock = SyncLock::acquire(path, false).unwrap();
+        assert_eq!(None, lock.stale_pid);
+    }
+}
@@ -0,0 +1,614 @@
+//! Non-blocking module
+//!
+//! This module contains the asynchronous variants of the CardDAV
+//! client, the backend trait and the CardDAV backend. They hold the
+//! actual implementation, the blocking variants being thin wrappers
+//! running them on a runtime of their own. This module and the
+//! blocking variants are only available with the `async` feature,
+//! which is enabled by default.
+
+use chrono::{DateTime, Local, Utc};
+use log::{debug, trace, warn};
+use quick_xml::de as xml;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use std::{collections::HashMap, future::Future, path::PathBuf, time::Duration};
+use tokio::time;
+use url::Url;
+
+use crate::{
+    cache::CachedCards,
+    card::{href_from_id, Card, CardsMap},
+    carddav::*,
+    error::*,
+    lint::{self, Severity},
+    vcard,
+    vcard_version::{self, VcardVersion},
+};
+
+/// Represents the asynchronous variant of the
+/// [`Backend`](crate::backend::Backend).
+pub trait AsyncBackend {
+    fn list(&self) -> impl Future<Output = Result<CardsMap>> + Send;
+    fn get(&self, href: &str) -> impl Future<Output = Result<Card>> + Send;
+    fn put(&self, card: &mut Card) -> impl Future<Output = Result<()>> + Send;
+    fn delete(&self, card: &Card) -> impl Future<Output = Result<()>> + Send;
+
+    /// Returns the maximum number of cards which can be written to
+    /// the backend at the same time.
+    fn concurrency(&self) -> usize {
+        1
+    }
+
+    /// Prepares the cache of the backend before a sync.
+    fn prepare_cache(&self, _cache: &mut CachedCards) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+}
+
+#[derive(Debug)]
+pub struct AsyncCardDavClient {
+    client: Client,
+    retry: RetryPolicy,
+    root_url: Url,
+    current_user_principal_url: Url,
+    addressbook_home_set_url: Url,
+    addressbook_url: Url,
+    login: String,
+    passwd: String,
+}
+
+impl AsyncCardDavClient {
+    pub async fn new(
+        host: String,
+        port: u16,
+        login: String,
+        passwd: String,
+        retry: RetryPolicy,
+    ) -> Result<Self> {
+        let root_url = format!("https://{}:{}", host, port);
+        let root_url =
+            Url::parse(&root_url).map_err(|e| CardamomError::ParseCardDavUrlError(root_url, e))?;
+
+        let mut client = Self {
+            client: Client::new(),
+            retry,
+            current_user_principal_url: root_url.clone(),
+            addressbook_home_set_url: root_url.clone(),
+            addressbook_url: root_url.clone(),
+            root_url,
+            login,
+            passwd,
+        };
+
+        client.update_current_user_principal_url().await?;
+        client.update_addressbook_home_set_url().await?;
+        client.update_addressbook_url().await?;
+
+        Ok(client)
+    }
+
+    async fn update_current_user_principal_url(&mut self) -> Result<()> {
+        let res = self
+            .send(propfind()?, self.root_url.clone(), true, |req| {
+                req.header("Content-Type", "application/xml; charset=utf-8")
+                    .header("Depth", "0")
+                    .body(
+                        r#"
+                        <propfind xmlns="DAV:">
+                            <prop>
+                                <current-user-principal />
+                            </prop>
+                        </propfind>
+                        "#,
+                    )
+            })
+            .await
+            .map_err(CardamomError::FetchCurrentUserPrincipalUrlError)?;
+        let res = res
+            .text()
+            .await
+            .map_err(CardamomError::FetchCurrentUserPrincipalUrlError)?;
+        trace!("current user principal url response: {}", res);
+        let res: Multistatus<CurrentUserPrincipalProp> =
+            xml::from_str(&res).map_err(CardamomError::ParseCurrentUserPrincipalUrlError)?;
+        let path = res
+            .responses
+            .first()
+            .and_then(|res| res.propstat.first())
+            .map(|propstat| propstat.prop.current_user_principal.href.to_owned())
+            .unwrap_or_else(|| self.root_url.path().to_owned());
+        self.current_user_principal_url.set_path(&path);
+        self.addressbook_home_set_url.set_path(&path);
+        self.addressbook_url.set_path(&path);
+        Ok(())
+    }
+
+    async fn update_addressbook_home_set_url(&mut self) -> Result<()> {
+        let res = self
+            .send(
+                propfind()?,
+                self.current_user_principal_url.clone(),
+                true,
+                |req| {
+                    req.header("Content-Type", "application/xml; charset=utf-8")
+                        .header("Depth", "0")
+                        .body(
+                            r#"
+                        <propfind xmlns="DAV:" xmlns:c="urn:ietf:params:xml:ns:carddav">
+                            <prop>
+                                <c:addressbook-home-set />
+                            </prop>
+                        </propfind>
+                        "#,
+                        )
+                },
+            )
+            .await
+            .map_err(CardamomError::FetchAddressbookHomeSetUrlError)?;
+        let res = res
+            .text()
+            .await
+            .map_err(CardamomError::FetchAddressbookHomeSetUrlError)?;
+        trace!("addressbook home set url response: {}", res);
+        let res: Multistatus<AddressbookHomeSetProp> =
+            xml::from_str(&res).map_err(CardamomError::ParseAddressbookHomeSetUrlError)?;
+        let path = res
+            .responses
+            .first()
+            .and_then(|res| res.propstat.first())
+            .map(|propstat| propstat.prop.addressbook_home_set.href.to_owned())
+            .unwrap_or_else(|| self.current_user_principal_url.path().to_owned());
+        self.addressbook_home_set_url.set_path(&path);
+        self.addressbook_url.set_path(&path);
+        Ok(())
+    }
+
+    async fn update_addressbook_url(&mut self) -> Result<()> {
+        let res = self
+            .send(
+                propfind()?,
+                self.addressbook_home_set_url.clone(),
+                true,
+                |req| {
+                    req.header("Content-Type", "application/xml; charset=utf-8")
+                        .header("Depth", "1")
+                        .body(
+                            r#"
+                        <propfind xmlns="DAV:">
+                            <prop>
+                                <resourcetype />
+                            </prop>
+                        </propfind>
+                        "#,
+                        )
+                },
+            )
+            .await
+            .map_err(CardamomError::FetchAddressbookUrlError)?;
+        let res = res
+            .text()
+            .await
+            .map_err(CardamomError::FetchAddressbookUrlError)?;
+        trace!("addressbook url response: {}", res);
+        let res: Multistatus<AddressbookProp> =
+            xml::from_str(&res).map_err(CardamomError::ParseAddressbookUrlError)?;
+        let path = res
+            .responses
+            .iter()
+            .find_map(|res| {
+                res.propstat
+                    .iter()
+                    .find(|propstat| {
+                        let valid_status = propstat
+                            .status
+                            .as_ref()
+                            .map(|s| s.ends_with("200 OK"))
+                            .unwrap_or(false);
+                        let has_addressbook =
+                            propstat.prop.resourcetype.addressbook.as_ref().is_some();
+                        valid_status && has_addressbook
+                    })
+                    .map(|_| res.href.to_owned())
+            })
+            .unwrap_or_else(|| self.addressbook_home_set_url.path().to_owned());
+        self.addressbook_url.set_path(&path);
+        Ok(())
+    }
+
+    pub async fn fetch_address_data(&self) -> Result<Multistatus<AddressDataProp>> {
+        let res = self
+            .send(report()?, self.addressbook_url.clone(), true, |req| {
+                req.header("Content-Type", "application/xml; charset=utf-8")
+                    .header("Depth", "1")
+                    .body(
+                        r#"
+                        <c:addressbook-query xmlns="DAV:" xmlns:c="urn:ietf:params:xml:ns:carddav">
+                            <prop>
+                                <getetag />
+                                <getlastmodified />
+                                <c:address-data />
+                            </prop>
+                        </c:addressbook-query>
+                        "#,
+                    )
+            })
+            .await
+            .map_err(CardamomError::FetchAddressDataError)?;
+        let res = res
+            .text()
+            .await
+            .map_err(CardamomError::FetchAddressDataError)?;
+        trace!("address data response: {}", res);
+        xml::from_str(&res).map_err(CardamomError::ParseAddressDataError)
+    }
+
+    /// Returns the URL of the addressbook.
+    pub fn addressbook_url(&self) -> &Url {
+        &self.addressbook_url
+    }
+
+    /// Builds the URL of a card from its href, which is either an
+    /// absolute path or a path relative to the addressbook.
+    fn card_url(&self, href: &str) -> Result<Url> {
+        let mut addressbook_url = self.addressbook_url.clone();
+        if !addressbook_url.path().ends_with('/') {
+            addressbook_url.set_path(&format!("{}/", addressbook_url.path()));
+        }
+        addressbook_url
+            .join(href)
+            .map_err(|e| CardamomError::ParseCardUrlError(href.to_owned(), e))
+    }
+
+    /// Fetches the vCard content of the card at the given href.
+    pub async fn fetch_card(&self, href: &str) -> Result<CardResponse> {
+        let url = self.card_url(href)?;
+        let res = self
+            .send(Method::GET, url.clone(), true, |req| req)
+            .await
+            .map_err(|e| CardamomError::FetchCardError(href.to_owned(), e))?;
+        let status = res.status();
+        let etag = etag(&res);
+        let last_modified = last_modified(&res);
+        let content = res
+            .text()
+            .await
+            .map_err(|e| CardamomError::FetchCardError(href.to_owned(), e))?;
+
+        if !status.is_success() {
+            return Err(CardamomError::ReadCardError(
+                href.to_owned(),
+                status.to_string(),
+            ));
+        }
+
+        Ok(CardResponse {
+            href: url.path().to_owned(),
+            etag,
+            last_modified,
+            content,
+        })
+    }
+
+    /// Creates or replaces the card at the given href. The upload is
+    /// retried only if it is conditional.
+    pub async fn put_card(
+        &self,
+        href: &str,
+        content: &str,
+        precondition: Option<&Precondition>,
+    ) -> Result<CardResponse> {
+        let url = self.card_url(href)?;
+        let res = self
+            .send(Method::PUT, url.clone(), precondition.is_some(), |req| {
+                let req = req
+                    .header("Content-Type", "text/vcard; charset=utf-8")
+                    .body(content.to_owned());
+                match precondition {
+                    Some(Precondition::IfMatch(etag)) => req.header("If-Match", etag),
+                    Some(Precondition::IfNoneMatch) => req.header("If-None-Match", "*"),
+                    None => req,
+                }
+            })
+            .await
+            .map_err(|e| CardamomError::PutCardError(href.to_owned(), e))?;
+        let status = res.status();
+
+        if status == StatusCode::PRECONDITION_FAILED && precondition.is_some() {
+            return Err(CardamomError::CardChangedError(href.to_owned()));
+        }
+        if !status.is_success() {
+            let reason = res.text().await.unwrap_or_else(|_| status.to_string());
+            return Err(CardamomError::WriteCardError(href.to_owned(), reason));
+        }
+
+        Ok(CardResponse {
+            href: url.path().to_owned(),
+            etag: etag(&res),
+            last_modified: last_modified(&res),
+            content: content.to_owned(),
+        })
+    }
+
+    /// Deletes the card at the given href, only if its entity tag
+    /// matches the given one (if any). A card already gone is not
+    /// considered as an error.
+    pub async fn delete_card(&self, href: &str, etag: Option<&str>) -> Result<()> {
+        let res = self
+            .send(
+                Method::DELETE,
+                self.card_url(href)?,
+                true,
+                |req| match etag {
+                    Some(etag) => req.header("If-Match", etag),
+                    None => req,
+                },
+            )
+            .await
+            .map_err(|e| CardamomError::DeleteRemoteCardError(href.to_owned(), e))?;
+        let status = res.status();
+
+        if status == StatusCode::PRECONDITION_FAILED && etag.is_some() {
+            return Err(CardamomError::CardChangedError(href.to_owned()));
+        }
+
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            let reason = res.text().await.unwrap_or_else(|_| status.to_string());
+            return Err(CardamomError::DeleteCardError(href.to_owned(), reason));
+        }
+
+        Ok(())
+    }
+
+    /// Sends a request built by the given function. Requests marked as
+    /// retryable are sent again when the server is temporarily
+    /// unavailable or when the connection fails, following the retry
+    /// policy.
+    async fn send<F>(
+        &self,
+        method: Method,
+        url: Url,
+        retryable: bool,
+        build: F,
+    ) -> reqwest::Result<Response>
+    where
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let max_attempts = if retryable {
+            self.retry.max_attempts.max(1)
+        } else {
+            1
+        };
+        let mut attempt = 1;
+
+        loop {
+            debug!("{} {} (attempt {}/{})", method, url, attempt, max_attempts);
+            let req = self
+                .client
+                .request(method.clone(), url.clone())
+                .basic_auth(&self.login, Some(&self.passwd));
+            let res = build(req).send().await;
+
+            let retry_after = match &res {
+                Ok(res) if is_transient(res.status()) => {
+                    debug!("{} {} failed: {}", method, url, res.status());
+                    retry_after(res)
+                }
+                Err(err) if err.is_timeout() || err.is_connect() => {
+                    debug!("{} {} failed: {}", method, url, err);
+                    None
+                }
+                _ => return res,
+            };
+
+            if attempt >= max_attempts {
+                return res;
+            }
+
+            let delay = self.retry.delay(attempt, retry_after);
+            debug!("retrying {} {} in {:?}", method, url, delay);
+            time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Represents the CardDAV backend.
+#[derive(Debug)]
+pub struct AsyncRemoteCards {
+    client: AsyncCardDavClient,
+    concurrency: usize,
+    vcard_version: Option<VcardVersion>,
+}
+
+impl AsyncRemoteCards {
+    pub async fn new(
+        host: String,
+        port: u16,
+        login: String,
+        passwd: String,
+        retry: RetryPolicy,
+        concurrency: usize,
+    ) -> Result<Self> {
+        Ok(Self {
+            client: AsyncCardDavClient::new(host, port, login, passwd, retry).await?,
+            concurrency,
+            vcard_version: None,
+        })
+    }
+
+    /// Converts the cards to the given vCard version before uploading
+    /// them, for servers accepting only one version.
+    pub fn with_vcard_version(mut self, version: Option<VcardVersion>) -> Self {
+        self.vcard_version = version;
+        self
+    }
+}
+
+/// Checks the given card before uploading it: servers reject invalid
+/// cards with errors which do not tell what is wrong with them.
+fn validate(card: &Card) -> Result<()> {
+    let (errors, warnings): (Vec<_>, Vec<_>) = lint::lint(&card.content)
+        .into_iter()
+        .partition(|problem| problem.severity == Severity::Error);
+    for warning in warnings {
+        warn!("card {:?}: {}", card.id, warning);
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    Err(CardamomError::InvalidCardError(
+        card.id.clone(),
+        errors.join(", "),
+    ))
+}
+
+impl AsyncBackend for AsyncRemoteCards {
+    async fn list(&self) -> Result<CardsMap> {
+        let mut cards = HashMap::default();
+        let address_data = self.client.fetch_address_data().await?;
+
+        for res in address_data.responses {
+            let prop = res.propstat.first().map(|propstat| &propstat.prop);
+            let content = prop
+                .and_then(|prop| prop.address_data.to_owned())
+                .unwrap_or_default();
+            let card = Card {
+                id: card_id(&content, &res.href)?,
+                date: prop
+                    .and_then(|prop| prop.getlastmodified.as_ref())
+                    .and_then(|getlastmodified| {
+                        DateTime::parse_from_rfc2822(getlastmodified)
+                            .map(|d| d.into())
+                            .ok()
+                    })
+                    .ok_or(CardamomError::ParseAddressDataLastModifiedError)?,
+                content,
+                etag: prop.and_then(|prop| prop.getetag.to_owned()),
+                href: Some(res.href.to_owned()),
+            };
+            if let Some(dup) = cards.insert(card.id.to_owned(), card) {
+                warn!("duplicate remote card {:?} at {:?}", dup.id, dup.href);
+            }
+        }
+
+        Ok(cards)
+    }
+
+    async fn get(&self, href: &str) -> Result<Card> {
+        let res = self.client.fetch_card(href).await?;
+        Ok(Card {
+            id: card_id(&res.content, &res.href)?,
+            date: res
+                .last_modified
+                .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+                .map(|date| date.into())
+                .ok_or(CardamomError::ParseAddressDataLastModifiedError)?,
+            content: res.content,
+            etag: res.etag,
+            href: Some(res.href),
+        })
+    }
+
+    async fn put(&self, card: &mut Card) -> Result<()> {
+        // a known card is replaced only if it did not change on the
+        // server since it was fetched, a new card never overwrites
+        // an existing one
+        let precondition = match (&card.etag, &card.href) {
+            (Some(etag), _) => Some(Precondition::IfMatch(etag.to_owned())),
+            (None, None) => Some(Precondition::IfNoneMatch),
+            (None, Some(_)) => None,
+        };
+        let href = card
+            .href
+            .to_owned()
+            .unwrap_or_else(|| href_from_id(&card.id));
+        // the converted card is the one stored by the server, the sync
+        // writes it back to the local side
+        if let Some(version) = self.vcard_version {
+            card.content = vcard_version::convert(&card.content, version);
+        }
+        validate(card)?;
+        let res = self
+            .client
+            .put_card(&href, &card.content, precondition.as_ref())
+            .await?;
+        card.etag = res.etag;
+        card.href = Some(res.href);
+        card.date = res
+            .last_modified
+            .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+            .map(|date| date.into())
+            .unwrap_or_else(Local::now);
+        Ok(())
+    }
+
+    async fn delete(&self, card: &Card) -> Result<()> {
+        // a known card is deleted only if it did not change on the
+        // server since it was fetched
+        let href = card
+            .href
+            .to_owned()
+            .unwrap_or_else(|| href_from_id(&card.id));
+        self.client.delete_card(&href, card.etag.as_deref()).await
+    }
+
+    fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    async fn prepare_cache(&self, cache: &mut CachedCards) -> Result<()> {
+        // cached cards of another addressbook cannot be compared
+        // with the current ones
+        let addressbook_url = self.client.addressbook_url().to_string();
+        match cache.addressbook_url.as_ref() {
+            Some(url) if url != &addressbook_url => {
+                warn!(
+                    "addressbook url changed from {:?} to {:?}, resetting remote cache",
+                    url, addressbook_url
+                );
+                cache.cards.clear();
+            }
+            _ => (),
+        }
+        cache.addressbook_url = Some(addressbook_url);
+        Ok(())
+    }
+}
+
+/// Returns the id of a remote card: its UID, or the file stem of its
+/// href if it has none.
+fn card_id(content: &str, href: &str) -> Result<String> {
+    match vcard::uid(content) {
+        Some(uid) => Ok(uid),
+        None => Ok(PathBuf::from(href)
+            .file_stem()
+            .ok_or_else(|| CardamomError::ParseAddressDataHrefError(href.to_owned()))?
+            .to_string_lossy()
+            .to_string()),
+    }
+}
+
+/// Returns the delay requested by the server before retrying, which
+/// is either a number of seconds or an HTTP date.
+fn retry_after(res: &Response) -> Option<Duration> {
+    res.headers()
+        .get("retry-after")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| parse_retry_after(h, Utc::now()))
+}
+
+fn etag(res: &Response) -> Option<String> {
+    res.headers()
+        .get("etag")
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.to_owned())
+}
+
+fn last_modified(res: &Response) -> Option<String> {
+    res.headers()
+        .get("last-modified")
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.to_owned())
+}
@@ -0,0 +1,137 @@
+//! Phone module
+//!
+//! This module contains the normalization of phone numbers to the
+//! E.164 format (`+33612345678`). National numbers (`06 12 34 56 78`)
+//! are read in a default region, given as an ISO 3166 country code
+//! (`FR`). Numbers which cannot be read are left as they are.
+
+use chrono::{DateTime, Utc};
+use phonenumber::{country, Mode};
+
+use crate::{contact::rev_prop, error::*, search::phone_digits, vcard};
+
+/// Represents the region national phone numbers belong to.
+pub type Region = country::Id;
+
+/// Parses a region from its ISO 3166 country code, like `FR`.
+pub fn region(code: &str) -> Result<Region> {
+    code.trim()
+        .to_uppercase()
+        .parse()
+        .map_err(|_| CardamomError::UnknownRegionError(code.to_owned()))
+}
+
+/// Returns the given phone number (or `tel` URI) in the E.164 format,
+/// if it is a valid number. Numbers with an extension are not
+/// normalized, since E.164 cannot hold it.
+pub fn to_e164(phone: &str, region: Option<Region>) -> Option<String> {
+    let phone = phone.trim().trim_start_matches("tel:");
+    let number = phonenumber::parse(region, phone).ok()?;
+    if number.extension().is_some() || !phonenumber::is_valid(&number) {
+        return None;
+    }
+    Some(number.format().mode(Mode::E164).to_string())
+}
+
+/// Returns the key phone numbers are compared with: the E.164 number
+/// when it is valid, otherwise the number without its separators.
+pub fn phone_key(phone: &str, region: Option<Region>) -> String {
+    to_e164(phone, region).unwrap_or_else(|| phone_digits(phone.trim().trim_start_matches("tel:")))
+}
+
+/// Rewrites the phone numbers of the given card in the E.164 format,
+/// revised at the given date. Returns none if no number changed.
+pub fn normalize_phones(
+    content: &str,
+    region: Option<Region>,
+    rev: DateTime<Utc>,
+) -> Option<String> {
+    let mut props = vcard::parse(content);
+    let mut changed = false;
+    for prop in props.iter_mut().filter(|prop| prop.name == "TEL") {
+        let number = match to_e164(&prop.value, region) {
+            Some(number) => number,
+            None => continue,
+        };
+        // numbers written as URI stay URI
+        let value = if prop.value.trim().starts_with("tel:") {
+            format!("tel:{}", number)
+        } else {
+            number
+        };
+        if value != prop.value {
+            prop.value = value;
+            changed = true;
+        }
+    }
+    if !changed {
+        return None;
+    }
+
+    let mut normalized_content = String::new();
+    for prop in props.iter().filter(|prop| prop.name != "REV") {
+        if prop.name == "END" {
+            normalized_content.push_str(&rev_prop(rev).to_line());
+        }
+        normalized_content.push_str(&prop.to_line());
+    }
+    Some(normalized_content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phone_to_e164() {
+        let fr = Some(region("fr").unwrap());
+
+        assert_eq!(Some("+33612345678".into()), to_e164("06 12 34 56 78", fr));
+        assert_eq!(
+            Some("+33612345678".into()),
+            to_e164("+33 (0)6.12.34.56.78", None)
+        );
+        assert_eq!(
+            Some("+14155552671".into()),
+            to_e164("tel:+1-415-555-2671", fr)
+        );
+        assert_eq!(None, to_e164("06 12 34 56 78", None));
+        assert_eq!(None, to_e164("+33 6 12 34 56 78 ext. 12", fr));
+        assert_eq!(None, to_e164("1234", fr));
+        assert!(region("zz").is_err());
+
+        assert_eq!("+33612345678", phone_key("06-12-34-56-78", fr));
+        assert_eq!("0612345678", phone_key("06-12-34-56-78", None));
+    }
+
+    #[test]
+    fn normalize_card_phones() {
+        let fr = Some(region("FR").unwrap());
+        let rev = "2022-03-01T10:00:00Z".parse().unwrap();
+        let content = concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "FN:Alice\r\n",
+            "TEL;TYPE=cell:06 12 34 56 78\r\n",
+            "TEL;VALUE=uri:tel:01-23-45-67-89\r\n",
+            "TEL:1234\r\n",
+            "REV:20200101T000000Z\r\n",
+            "END:VCARD\r\n",
+        );
+
+        assert_eq!(
+            Some(String::from(concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:4.0\r\n",
+                "FN:Alice\r\n",
+                "TEL;TYPE=cell:+33612345678\r\n",
+                "TEL;VALUE=uri:tel:+33123456789\r\n",
+                "TEL:1234\r\n",
+                "REV:20220301T100000Z\r\n",
+                "END:VCARD\r\n",
+            ))),
+            normalize_phones(content, fr, rev)
+        );
+        assert_eq!(None, normalize_phones(content, None, rev));
+    }
+}
@@ -1,71 +1,74 @@
-use chrono::DateTime;
-use std::{collections::HashMap, path::PathBuf};
+use tokio::runtime::Runtime;
 
 use crate::{
+    backend::Backend,
     cache::CachedCards,
-    card::{Card, Cards, CardsMap},
-    carddav::CardDavClient,
+    card::{Card, CardsMap},
+    carddav::{runtime, RetryPolicy},
     error::*,
+    nonblocking::{AsyncBackend, AsyncRemoteCards},
+    vcard_version::VcardVersion,
 };
 
+/// Represents the blocking CardDAV backend. It runs the
+/// [`AsyncRemoteCards`] on a runtime of its own.
 #[derive(Debug)]
 pub struct RemoteCards {
-    sync_dir: PathBuf,
-    client: CardDavClient,
-    pub prev: CardsMap,
-    next: CardsMap,
-}
-
-impl Cards for RemoteCards {
-    fn prev(&self) -> &CardsMap {
-        &self.prev
-    }
-
-    fn next(&self) -> &CardsMap {
-        &self.next
-    }
+    runtime: Runtime,
+    remote: AsyncRemoteCards,
 }
 
 impl RemoteCards {
     pub fn new(
-        sync_dir: PathBuf,
         host: String,
         port: u16,
         login: String,
         passwd: String,
+        retry: RetryPolicy,
+        concurrency: usize,
     ) -> Result<Self> {
-        let prev = CachedCards::new(sync_dir.join(".remote"))?.cards;
-        let mut next = HashMap::default();
-        let client = CardDavClient::new(host, port, login, passwd)?;
-        let address_data = client.fetch_address_data()?;
+        let runtime = runtime()?;
+        let remote = runtime.block_on(AsyncRemoteCards::new(
+            host,
+            port,
+            login,
+            passwd,
+            retry,
+            concurrency,
+        ))?;
+        Ok(Self { runtime, remote })
+    }
+
+    /// Converts the cards to the given vCard version before uploading
+    /// them (see [`AsyncRemoteCards::with_vcard_version`]).
+    pub fn with_vcard_version(mut self, version: Option<VcardVersion>) -> Self {
+        self.remote = self.remote.with_vcard_version(version);
+        self
+    }
+}
 
-        for res in address_data.responses {
-            let card = Card {
-                id: PathBuf::from(&res.href)
-                    .file_stem()
-                    .ok_or_else(|| CardamomError::ParseAddressDataHrefError(res.href.clone()))?
-                    .to_string_lossy()
-                    .to_string(),
-                date: res
-                    .propstat
-                    .first()
-                    .and_then(|propstat| propstat.prop.getlastmodified.as_ref())
-                    .and_then(|getlastmodified| {
-                        DateTime::parse_from_rfc2822(&getlastmodified)
-                            .map(|d| d.into())
-                            .ok()
-                    })
-                    .ok_or_else(|| CardamomError::ParseAddressDataLastModifiedError)?,
-                content: String::default(),
-            };
-            next.insert(card.id.to_owned(), card);
-        }
+impl Backend for RemoteCards {
+    fn list(&self) -> Result<CardsMap> {
+        self.runtime.block_on(self.remote.list())
+    }
+
+    fn get(&self, href: &str) -> Result<Card> {
+        self.runtime.block_on(self.remote.get(href))
+    }
+
+    fn put(&self, card: &mut Card) -> Result<()> {
+        self.runtime.block_on(self.remote.put(card))
+    }
+
+    fn delete(&self, card: &Card) -> Result<()> {
+        self.runtime.block_on(self.remote.delete(card))
+    }
+
+    fn concurrency(&self) -> usize {
+        self.remote.concurrency()
+    }
 
-        Ok(Self {
-            sync_dir,
-            client,
-            prev,
-            next,
-        })
+    fn prepare_cache(&self, cache: &mut CachedCards) -> Result<()> {
+        self.runtime.block_on(self.remote.prepare_cache(cache))
     }
 }
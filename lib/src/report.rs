@@ -0,0 +1,71 @@
+//! Report module
+//!
+//! This module contains the report of a sync: what changed on each
+//! side, the conflicts and how they were resolved, the failures and
+//! the time it took.
+
+use serde::{Serialize, Serializer};
+use std::time::Duration;
+
+use crate::sync::Conflict;
+
+/// Represents the action applied to a card.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Add,
+    Set,
+    Del,
+}
+
+/// Represents the outcome of a single card.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CardOutcome {
+    pub id: String,
+    pub action: Action,
+}
+
+/// Represents the changes applied to one side of the sync.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct SideReport {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub cards: Vec<CardOutcome>,
+}
+
+impl SideReport {
+    pub fn push(&mut self, id: &str, action: Action) {
+        match action {
+            Action::Add => self.added += 1,
+            Action::Set => self.updated += 1,
+            Action::Del => self.deleted += 1,
+        }
+        self.cards.push(CardOutcome {
+            id: id.to_owned(),
+            action,
+        });
+    }
+}
+
+/// Represents a card which could not be synchronized.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Failure {
+    pub id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SyncReport {
+    pub left: SideReport,
+    pub right: SideReport,
+    pub conflicts: Vec<Conflict>,
+    pub failures: Vec<Failure>,
+    /// Represents the duration of the sync, serialized in seconds.
+    #[serde(serialize_with = "serialize_secs")]
+    pub duration: Duration,
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
@@ -0,0 +1,300 @@
+//! Search module
+//!
+//! This module contains the contact search. A query is a list of
+//! terms which must all match: a term is either scoped to a field
+//! (`email:@corp.com`, `org:"ACME Inc"`) or free text matched against
+//! all the properties of the card. Matching is case- and
+//! accent-insensitive.
+
+use std::{result, str::FromStr};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::{
+    card::Card,
+    contact::Contact,
+    error::*,
+    phone::{to_e164, Region},
+    vcard,
+};
+
+/// Represents the properties never matched by free text, since they
+/// hold technical or binary data.
+const UNSEARCHABLE_PROPS: [&str; 9] = [
+    "BEGIN", "END", "VERSION", "PRODID", "REV", "PHOTO", "LOGO", "SOUND", "KEY",
+];
+
+/// Represents the contact fields a term can be scoped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Email,
+    Phone,
+    Org,
+    Address,
+    Note,
+    Category,
+    Url,
+}
+
+impl FromStr for Field {
+    type Err = ();
+
+    fn from_str(field: &str) -> result::Result<Self, Self::Err> {
+        match field.to_lowercase().as_str() {
+            "name" => Ok(Self::Name),
+            "email" => Ok(Self::Email),
+            "phone" | "tel" => Ok(Self::Phone),
+            "org" => Ok(Self::Org),
+            "address" | "adr" => Ok(Self::Address),
+            "note" => Ok(Self::Note),
+            "category" | "categories" => Ok(Self::Category),
+            "url" => Ok(Self::Url),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Represents a search term. Its value is normalized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub field: Option<Field>,
+    pub value: String,
+}
+
+impl Term {
+    fn new(field: Option<Field>, value: &str) -> Self {
+        Self {
+            field,
+            value: normalize(value),
+        }
+    }
+
+    fn matches(&self, contact: &Contact, text: &str, region: Option<Region>) -> bool {
+        let values: Vec<String> = match self.field {
+            None => return text.contains(&self.value),
+            Some(Field::Phone) => {
+                // phone numbers are compared without their separators,
+                // and in E.164 when a full number is searched, a term
+                // made of separators only matches nothing
+                let value = phone_digits(&self.value);
+                let number = to_e164(&self.value, region);
+                return contact.phones.iter().any(|phone| {
+                    !value.is_empty() && phone_digits(&phone.value).contains(&value)
+                        || number.is_some() && to_e164(&phone.value, region) == number
+                });
+            }
+            Some(Field::Name) => {
+                let mut names: Vec<String> = contact.formatted_name.iter().cloned().collect();
+                if let Some(name) = contact.structured_name.as_ref() {
+                    names.extend(name.display());
+                }
+                names
+            }
+            Some(Field::Email) => contact.emails.iter().map(|e| e.value.clone()).collect(),
+            Some(Field::Org) => contact.organization.iter().cloned().collect(),
+            Some(Field::Address) => contact
+                .addresses
+                .iter()
+                .map(|a| a.lines().join(" "))
+                .collect(),
+            Some(Field::Note) => contact.notes.clone(),
+            Some(Field::Category) => contact.categories.clone(),
+            Some(Field::Url) => contact.urls.iter().map(|u| u.value.clone()).collect(),
+        };
+
+        values
+            .iter()
+            .any(|value| normalize(value).contains(&self.value))
+    }
+}
+
+/// Represents a search query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+    /// Represents the region national phone numbers are read in.
+    pub region: Option<Region>,
+}
+
+impl Query {
+    /// Reads national phone numbers in the given region.
+    pub fn with_region(mut self, region: Option<Region>) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// Returns true if the given card matches all the terms of the
+    /// query. An empty query matches all the cards.
+    pub fn matches(&self, card: &Card) -> bool {
+        self.matches_contact(&Contact::from(card), &searchable_text(&card.content))
+    }
+
+    /// Returns true if the given contact matches all the terms of the
+    /// query, free text being matched against the given searchable
+    /// text of its card.
+    pub fn matches_contact(&self, contact: &Contact, text: &str) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.matches(contact, text, self.region))
+    }
+}
+
+impl FromStr for Query {
+    type Err = CardamomError;
+
+    fn from_str(query: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+        let mut chars = query.chars().peekable();
+
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+
+            // a field is a word followed by a colon, other words are
+            // free text (like urls)
+            let mut word = String::new();
+            let mut field = None;
+            let mut quoted = false;
+            for c in chars.by_ref() {
+                match c {
+                    '"' => quoted = !quoted,
+                    ':' if !quoted && field.is_none() && word.parse::<Field>().is_ok() => {
+                        field = word.parse().ok();
+                        word.clear();
+                    }
+                    c if c.is_whitespace() && !quoted => break,
+                    c => word.push(c),
+                }
+            }
+
+            if quoted {
+                return Err(CardamomError::ParseQueryError(
+                    query.to_owned(),
+                    "unterminated quote".into(),
+                ));
+            }
+            if word.is_empty() {
+                if field.is_some() {
+                    return Err(CardamomError::ParseQueryError(
+                        query.to_owned(),
+                        "missing field value".into(),
+                    ));
+                }
+                continue;
+            }
+            terms.push(Term::new(field, &word));
+        }
+
+        Ok(Self {
+            terms,
+            region: None,
+        })
+    }
+}
+
+/// Returns the normalized values of all the searchable properties of
+/// the given card content, one per line.
+pub fn searchable_text(content: &str) -> String {
+    vcard::parse(content)
+        .iter()
+        .filter(|prop| !UNSEARCHABLE_PROPS.contains(&prop.name.as_str()))
+        .map(|prop| normalize(&vcard::unescape(&prop.value)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Normalizes the given text for comparison: accents are removed and
+/// letters are lowercased.
+pub fn normalize(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns the given phone number without its separators.
+pub(crate) fn phone_digits(phone: &str) -> String {
+    phone
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')' | '/'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+
+    fn card(content: &str) -> Card {
+        Card {
+            id: "id".into(),
+            date: Local::now(),
+            content: content.into(),
+            etag: None,
+            href: None,
+        }
+    }
+
+    #[test]
+    fn parse_query() {
+        let query: Query = r#"name:alice email:@corp.com org:"ACME Inc" Zoë"#.parse().unwrap();
+        assert_eq!(
+            vec![
+                Term::new(Some(Field::Name), "alice"),
+                Term::new(Some(Field::Email), "@corp.com"),
+                Term::new(Some(Field::Org), "acme inc"),
+                Term::new(None, "zoe"),
+            ],
+            query.terms
+        );
+
+        let query: Query = "https://example.org".parse().unwrap();
+        assert_eq!(vec![Term::new(None, "https://example.org")], query.terms);
+
+        assert!("org:\"ACME".parse::<Query>().is_err());
+        assert!("name:".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn match_query() {
+        let card = card(concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "FN:Alice Lefèvre\r\n",
+            "EMAIL:alice@corp.com\r\n",
+            "TEL:+33 6 12 34 56 78\r\n",
+            "ORG:ACME Inc\r\n",
+            "NICKNAME:Lili\r\n",
+            "END:VCARD\r\n",
+        ));
+        let matches = |query: &str| query.parse::<Query>().unwrap().matches(&card);
+
+        assert!(matches(""));
+        assert!(matches("name:lefevre email:@CORP.com"));
+        assert!(matches(r#"org:"acme inc""#));
+        assert!(!matches("phone:0612345678"));
+        assert!(matches("phone:612-345-678"));
+        assert!(!matches("phone:-"));
+        assert!(!matches("phone:()"));
+        assert!(matches("lili"));
+        assert!(!matches("name:lili"));
+        assert!(!matches("vcard"));
+        assert!(!matches("name:alice org:globex"));
+
+        let region = Some(crate::phone::region("FR").unwrap());
+        let matches_in_fr = |query: &str| {
+            query
+                .parse::<Query>()
+                .unwrap()
+                .with_region(region)
+                .matches(&card)
+        };
+
+        assert!(matches_in_fr("phone:0612345678"));
+        assert!(matches_in_fr("phone:06.12.34.56.78"));
+        assert!(!matches_in_fr("phone:0712345678"));
+    }
+}
@@ -1,8 +1,26 @@
-use std::collections::{HashMap, HashSet};
-
-use crate::card::*;
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+use log::{debug, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    iter::FromIterator,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    cache::CachedCards,
+    card::*,
+    card_repository::CardRepository,
+    error::*,
+    journal::Journal,
+    report::{Action, Failure, SyncReport},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum HunkKind {
     PrevLeft(String),
     NextLeft(String),
@@ -10,7 +28,7 @@ pub enum HunkKind {
     NextRight(String),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Hunk {
     Add(Card),
     Set(Card),
@@ -27,9 +45,29 @@ impl Hunk {
     }
 }
 
+/// Represents the way a conflict has been resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictResolution {
+    /// The left version of the card has been kept.
+    KeepLeft,
+    /// The right version of the card has been kept.
+    KeepRight,
+    /// The card has been deleted on both sides.
+    Delete,
+}
+
+/// Represents a card changed on both sides since the last sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conflict {
+    pub id: String,
+    pub resolution: ConflictResolution,
+}
+
 #[derive(Debug, Default)]
 pub struct Patch {
     hunks: HashMap<HunkKind, Hunk>,
+    pub conflicts: Vec<Conflict>,
 }
 
 impl Patch {
@@ -42,10 +80,31 @@ impl Patch {
             self.hunks.insert(kind, next_hunk);
         }
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&HunkKind, &Hunk)> {
+        self.hunks.iter()
+    }
+
+    fn conflict(&mut self, id: &str, resolution: ConflictResolution) {
+        self.conflicts.push(Conflict {
+            id: id.to_owned(),
+            resolution,
+        });
+    }
+}
+
+impl FromIterator<(HunkKind, Hunk)> for Patch {
+    fn from_iter<T: IntoIterator<Item = (HunkKind, Hunk)>>(iter: T) -> Self {
+        let mut patch = Patch::default();
+        for (kind, hunk) in iter {
+            patch.insert(kind, hunk);
+        }
+        patch
+    }
 }
 
 impl Patch {
-    pub fn new(left: impl Cards, right: impl Cards) -> Self {
+    pub fn new(left: &impl Cards, right: &impl Cards) -> Self {
         let mut ids = HashSet::new();
         let mut patch = Patch::default();
 
@@ -100,7 +159,8 @@ impl Patch {
 
             // 3 (0011): id in right cards, which means the previous
             // synchro failed and left is not in phase with right
-            // anymore
+            // anymore (interrupted synchros are normally resumed from
+            // the journal, this only covers caches from older runs)
             if !lp && !ln && rp && rn {
                 let prev_card = right.prev().get(id).unwrap();
                 let next_card = right.next().get(id).unwrap();
@@ -173,6 +233,9 @@ impl Patch {
                         HunkKind::PrevRight(id.to_owned()),
                         Hunk::Add(right_card.to_owned()),
                     );
+                    if right_card.content != left_card.content {
+                        patch.conflict(id, ConflictResolution::KeepRight);
+                    }
                 } else {
                     patch.insert(
                         HunkKind::PrevLeft(id.to_owned()),
@@ -186,6 +249,9 @@ impl Patch {
                         HunkKind::NextRight(id.to_owned()),
                         Hunk::Set(left_card.to_owned()),
                     );
+                    if right_card.content != left_card.content {
+                        patch.conflict(id, ConflictResolution::KeepLeft);
+                    }
                 }
             }
 
@@ -204,6 +270,7 @@ impl Patch {
                         HunkKind::PrevRight(id.to_owned()),
                         Hunk::Del(right_card.to_owned()),
                     );
+                    patch.conflict(id, ConflictResolution::Delete);
                 } else {
                     patch.insert(
                         HunkKind::PrevLeft(id.to_owned()),
@@ -217,6 +284,7 @@ impl Patch {
                         HunkKind::NextRight(id.to_owned()),
                         Hunk::Add(left_card.to_owned()),
                     );
+                    patch.conflict(id, ConflictResolution::KeepLeft);
                 }
             }
 
@@ -227,6 +295,17 @@ impl Patch {
                 let prev_right_card = right.prev().get(id).unwrap();
                 let next_right_card = right.next().get(id).unwrap();
 
+                if left_card.content != next_right_card.content {
+                    patch.conflict(
+                        id,
+                        if next_right_card.date >= left_card.date {
+                            ConflictResolution::KeepRight
+                        } else {
+                            ConflictResolution::KeepLeft
+                        },
+                    );
+                }
+
                 if next_right_card.date >= left_card.date {
                     if next_right_card.date >= prev_right_card.date {
                         patch.insert(
@@ -290,6 +369,15 @@ impl Patch {
                 let left_card = left.prev().get(id).unwrap();
                 let right_card = right.next().get(id).unwrap();
 
+                patch.conflict(
+                    id,
+                    if right_card.date >= left_card.date {
+                        ConflictResolution::KeepRight
+                    } else {
+                        ConflictResolution::Delete
+                    },
+                );
+
                 if right_card.date >= left_card.date {
                     patch.insert(
                         HunkKind::PrevLeft(id.to_owned()),
@@ -338,6 +426,10 @@ impl Patch {
                 let prev_right_card = right.prev().get(id).unwrap();
                 let next_right_card = right.next().get(id).unwrap();
 
+                if next_right_card.content != prev_right_card.content {
+                    patch.conflict(id, ConflictResolution::Delete);
+                }
+
                 patch.insert(
                     HunkKind::PrevLeft(id.to_owned()),
                     Hunk::Del(prev_left_card.to_owned()),
@@ -354,7 +446,8 @@ impl Patch {
 
             // 12 (1100): id in left cards, which means the previous
             // synchro failed and left is not in phase with right
-            // anymore
+            // anymore (interrupted synchros are normally resumed from
+            // the journal, this only covers caches from older runs)
             if lp && ln && !rp && !rn {
                 let prev_card = left.prev().get(id).unwrap();
                 let next_card = left.next().get(id).unwrap();
@@ -395,6 +488,17 @@ impl Patch {
                 let next_left_card = left.next().get(id).unwrap();
                 let right_card = right.next().get(id).unwrap();
 
+                if next_left_card.content != right_card.content {
+                    patch.conflict(
+                        id,
+                        if next_left_card.date >= right_card.date {
+                            ConflictResolution::KeepLeft
+                        } else {
+                            ConflictResolution::KeepRight
+                        },
+                    );
+                }
+
                 if next_left_card.date >= right_card.date {
                     if next_left_card.date >= prev_left_card.date {
                         if next_left_card.date != prev_left_card.date {
@@ -448,6 +552,10 @@ impl Patch {
                 let next_left_card = left.next().get(id).unwrap();
                 let prev_right_card = right.prev().get(id).unwrap();
 
+                if next_left_card.content != prev_left_card.content {
+                    patch.conflict(id, ConflictResolution::Delete);
+                }
+
                 patch.insert(
                     HunkKind::PrevLeft(id.to_owned()),
                     Hunk::Del(prev_left_card.to_owned()),
@@ -476,25 +584,41 @@ impl Patch {
                 ];
                 cards.sort_by(|a, b| b.date.partial_cmp(&a.date).unwrap());
                 let card = *cards.first().unwrap();
-                if card != prev_left_card {
+
+                if next_left_card.content != prev_left_card.content
+                    && next_right_card.content != prev_right_card.content
+                    && next_left_card.content != next_right_card.content
+                {
+                    patch.conflict(
+                        id,
+                        if card == next_left_card || card == prev_left_card {
+                            ConflictResolution::KeepLeft
+                        } else {
+                            ConflictResolution::KeepRight
+                        },
+                    );
+                }
+                // cards are compared by content only: the same card
+                // has a different location and date on each side
+                if card.content != prev_left_card.content {
                     patch.insert(
                         HunkKind::PrevLeft(id.to_owned()),
                         Hunk::Set(card.to_owned()),
                     );
                 }
-                if card != next_left_card {
+                if card.content != next_left_card.content {
                     patch.insert(
                         HunkKind::NextLeft(id.to_owned()),
                         Hunk::Set(card.to_owned()),
                     );
                 }
-                if card != prev_right_card {
+                if card.content != prev_right_card.content {
                     patch.insert(
                         HunkKind::PrevRight(id.to_owned()),
                         Hunk::Set(card.to_owned()),
                     );
                 }
-                if card != next_right_card {
+                if card.content != next_right_card.content {
                     patch.insert(
                         HunkKind::NextRight(id.to_owned()),
                         Hunk::Set(card.to_owned()),
@@ -505,12 +629,245 @@ impl Patch {
 
         patch
     }
+
+    /// Applies the patch to the given repositories and their caches.
+    ///
+    /// Hunks touching repositories are applied first and recorded one
+    /// by one in the journal, then caches are updated and saved. If
+    /// the journal comes from an interrupted sync, the hunks it
+    /// already recorded are not applied twice. A card failing to be
+    /// applied does not stop the sync, it is reported as a failure.
+    ///
+    /// Hunks of a same repository can be applied concurrently, see
+    /// [`CardRepository::concurrency`].
+    pub fn apply<L, R>(
+        &self,
+        left: &mut L,
+        right: &mut R,
+        journal: &mut Journal,
+    ) -> Result<SyncReport>
+    where
+        L: CardRepository + AsMut<CachedCards> + Sync,
+        R: CardRepository + AsMut<CachedCards> + Sync,
+    {
+        info!(">> apply patch");
+        let start = Instant::now();
+        let mut report = SyncReport {
+            conflicts: self.conflicts.clone(),
+            ..SyncReport::default()
+        };
+        journal.begin(self)?;
+
+        // cards as they are after being applied to the repositories,
+        // the caches need them to stay in phase with the repositories
+        let mut applied_cards = HashMap::new();
+        // cards which could not be applied to a repository, their
+        // cache entries are left untouched so they are retried during
+        // the next sync
+        let mut failed_ids = HashSet::new();
+
+        // hunks touching the repositories and not applied yet
+        let mut left_hunks = Vec::new();
+        let mut right_hunks = Vec::new();
+
+        for (kind, hunk) in self.iter() {
+            let id = match kind {
+                HunkKind::NextLeft(id) | HunkKind::NextRight(id) => id,
+                _ => continue,
+            };
+            match (journal.applied.get(kind), kind) {
+                (Some(card), _) => {
+                    debug!("skipping hunk {:?} already applied", kind);
+                    report_hunk(&mut report, kind, id, hunk);
+                    applied_cards.insert(kind.to_owned(), card.to_owned());
+                }
+                (None, HunkKind::NextLeft(_)) => left_hunks.push((kind, hunk)),
+                (None, _) => right_hunks.push((kind, hunk)),
+            }
+        }
+
+        let mut on_applied = |kind: &HunkKind, hunk: &Hunk, card: Result<Card>| {
+            let id = match kind {
+                HunkKind::NextLeft(id) | HunkKind::NextRight(id) => id,
+                _ => return Ok(()),
+            };
+            match card {
+                Ok(card) => {
+                    journal.record(kind, &card)?;
+                    report_hunk(&mut report, kind, id, hunk);
+                    applied_cards.insert(kind.to_owned(), card);
+                }
+                Err(err) => {
+                    warn!("cannot apply hunk {:?}: {}", kind, err);
+                    report.failures.push(Failure {
+                        id: id.to_owned(),
+                        error: err.to_string(),
+                    });
+                    failed_ids.insert(id.to_owned());
+                }
+            }
+            Ok(())
+        };
+        apply_hunks(left, left_hunks, &mut on_applied)?;
+        apply_hunks(right, right_hunks, &mut on_applied)?;
+
+        // the right repository may rewrite the cards it stores (like
+        // converting them to another vCard version): rewritten cards
+        // are written back to the left side, so that both sides and
+        // their caches agree on the next sync
+        let rewritten_hunks: Vec<(HunkKind, Hunk)> = self
+            .iter()
+            .filter_map(|(kind, hunk)| match (kind, hunk) {
+                (HunkKind::NextRight(id), Hunk::Add(card) | Hunk::Set(card)) => {
+                    let applied_card = applied_cards.get(kind)?;
+                    (applied_card.content != card.content).then(|| {
+                        (
+                            HunkKind::NextLeft(id.to_owned()),
+                            Hunk::Set(applied_card.to_owned()),
+                        )
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        let mut rewritten_cards = Vec::new();
+        apply_hunks(
+            left,
+            rewritten_hunks
+                .iter()
+                .map(|(kind, hunk)| (kind, hunk))
+                .collect(),
+            |kind, _, card| {
+                match card {
+                    Ok(card) => {
+                        journal.record(kind, &card)?;
+                        rewritten_cards.push(card);
+                    }
+                    Err(err) => warn!("cannot write back rewritten hunk {:?}: {}", kind, err),
+                }
+                Ok(())
+            },
+        )?;
+
+        for (kind, hunk) in self.iter() {
+            let (cache, id, next_kind) = match kind {
+                HunkKind::PrevLeft(id) => (left.as_mut(), id, HunkKind::NextLeft(id.to_owned())),
+                HunkKind::PrevRight(id) => (right.as_mut(), id, HunkKind::NextRight(id.to_owned())),
+                _ => continue,
+            };
+            if failed_ids.contains(id) {
+                debug!("skipping hunk {:?} of failed card", kind);
+                continue;
+            }
+            trace!("applying hunk {:?}: {:?}", kind, hunk);
+            match hunk {
+                Hunk::Add(card) | Hunk::Set(card) => {
+                    let card = applied_cards.get(&next_kind).unwrap_or(card);
+                    cache.cards.insert(card.id.to_owned(), card.to_owned());
+                }
+                Hunk::Del(card) => {
+                    cache.cards.remove(&card.id);
+                }
+            }
+        }
+
+        for card in rewritten_cards {
+            left.as_mut().cards.insert(card.id.to_owned(), card);
+        }
+
+        left.as_mut().save()?;
+        right.as_mut().save()?;
+        journal.end()?;
+
+        report.duration = start.elapsed();
+        info!("<< apply patch");
+        Ok(report)
+    }
+}
+
+/// Applies the given hunks to the repository, using up to as many
+/// threads as the repository allows. The outcome of each hunk is
+/// handed to the given function from the calling thread, as soon as
+/// it is known. If the function fails, pending hunks are dropped.
+fn apply_hunks<'a, R, F>(repository: &R, hunks: Vec<(&'a HunkKind, &'a Hunk)>, f: F) -> Result<()>
+where
+    R: CardRepository + Sync,
+    F: FnMut(&'a HunkKind, &'a Hunk, Result<Card>) -> Result<()>,
+{
+    let mut f = f;
+    let workers = repository.concurrency().clamp(1, hunks.len().max(1));
+
+    if workers == 1 {
+        for (kind, hunk) in hunks {
+            trace!("applying hunk {:?}: {:?}", kind, hunk);
+            f(kind, hunk, apply_hunk(repository, hunk))?;
+        }
+        return Ok(());
+    }
+
+    debug!("applying {} hunk(s) with {} threads", hunks.len(), workers);
+    let queue = Mutex::new(hunks.into_iter());
+    let stop = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let (queue, stop) = (&queue, &stop);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let (kind, hunk) = match queue.lock().ok().and_then(|mut q| q.next()) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    trace!("applying hunk {:?}: {:?}", kind, hunk);
+                    let card = apply_hunk(repository, hunk);
+                    if tx.send((kind, hunk, card)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        for (kind, hunk, card) in rx {
+            if let Err(err) = f(kind, hunk, card) {
+                stop.store(true, Ordering::Relaxed);
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn report_hunk(report: &mut SyncReport, kind: &HunkKind, id: &str, hunk: &Hunk) {
+    let action = match hunk {
+        Hunk::Add(_) => Action::Add,
+        Hunk::Set(_) => Action::Set,
+        Hunk::Del(_) => Action::Del,
+    };
+    match kind {
+        HunkKind::NextLeft(_) => report.left.push(id, action),
+        _ => report.right.push(id, action),
+    }
+}
+
+fn apply_hunk(repository: &impl CardRepository, hunk: &Hunk) -> Result<Card> {
+    let mut card = hunk.card().to_owned();
+    match hunk {
+        Hunk::Add(_) => repository.insert(&mut card)?,
+        Hunk::Set(_) => repository.update(&mut card)?,
+        Hunk::Del(_) => repository.delete(&card)?,
+    }
+    Ok(card)
 }
 
 #[cfg(test)]
 mod tests {
     use chrono::{DateTime, Local};
-    use std::{collections::HashMap, iter::FromIterator};
+    use std::{collections::HashMap, env, fs, iter::FromIterator, sync::Mutex};
 
     use super::*;
 
@@ -544,14 +901,103 @@ mod tests {
         }
     }
 
+    struct TestRepository {
+        cache: CachedCards,
+        cards: Mutex<CardsMap>,
+        failing_id: &'static str,
+        concurrency: usize,
+        /// Represents whether the repository rewrites the cards it
+        /// stores, like a server converting them.
+        rewrite: bool,
+    }
+
+    impl TestRepository {
+        pub fn new(name: &str, failing_id: &'static str, concurrency: usize) -> Self {
+            let path = env::temp_dir().join(format!("cardamom-sync-apply-{}", name));
+            let _ = fs::remove_file(&path);
+            Self {
+                cache: CachedCards::new(path).unwrap(),
+                cards: Mutex::new(HashMap::new()),
+                failing_id,
+                concurrency,
+                rewrite: false,
+            }
+        }
+
+        pub fn rewriting(mut self) -> Self {
+            self.rewrite = true;
+            self
+        }
+
+        fn check(&self, card: &Card) -> Result<()> {
+            if card.id == self.failing_id {
+                Err(CardamomError::WriteCardError(card.id.clone(), "412".into()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl CardRepository for TestRepository {
+        fn insert(&self, card: &mut Card) -> Result<()> {
+            self.check(card)?;
+            if self.rewrite {
+                card.content = card.content.to_uppercase();
+            }
+            self.cards
+                .lock()
+                .unwrap()
+                .insert(card.id.clone(), card.clone());
+            Ok(())
+        }
+
+        fn select(&self, id: &str) -> Result<Card> {
+            self.cards
+                .lock()
+                .unwrap()
+                .get(id)
+                .cloned()
+                .ok_or_else(|| CardamomError::ReadCardError(id.into(), "404".into()))
+        }
+
+        fn select_all(&self) -> Result<CardsMap> {
+            Ok(self.cards.lock().unwrap().clone())
+        }
+
+        fn update(&self, card: &mut Card) -> Result<()> {
+            self.insert(card)
+        }
+
+        fn delete(&self, card: &Card) -> Result<()> {
+            self.check(card)?;
+            self.cards.lock().unwrap().remove(&card.id);
+            Ok(())
+        }
+
+        fn concurrency(&self) -> usize {
+            self.concurrency
+        }
+    }
+
+    impl AsMut<CachedCards> for TestRepository {
+        fn as_mut(&mut self) -> &mut CachedCards {
+            &mut self.cache
+        }
+    }
+
     macro_rules! card {
         ($id: literal, $date: literal) => {
+            card!($id, $date, "")
+        };
+        ($id: literal, $date: literal, $content: literal) => {
             Card {
                 id: format!("{}", $id),
                 date: DateTime::parse_from_rfc3339(&format!("{}T00:00:00+00:00", $date))
                     .unwrap()
                     .with_timezone(&Local),
-                content: String::new(),
+                content: String::from($content),
+                etag: None,
+                href: None,
             }
         };
     }
@@ -560,7 +1006,7 @@ mod tests {
     fn test_patch_0000() {
         let left = TestCards::new(vec![], vec![]);
         let right = TestCards::new(vec![], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert!(patch.hunks.is_empty());
     }
@@ -569,7 +1015,7 @@ mod tests {
     fn test_patch_0001() {
         let left = TestCards::new(vec![], vec![]);
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-19")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -590,7 +1036,7 @@ mod tests {
     fn test_patch_0010() {
         let left = TestCards::new(vec![], vec![]);
         let right = TestCards::new(vec![card!("id", "2020-01-19")], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(1, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -607,7 +1053,7 @@ mod tests {
             vec![card!("id", "2020-01-18")],
             vec![card!("id", "2020-01-19")],
         );
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -629,7 +1075,7 @@ mod tests {
             vec![card!("id", "2020-01-20")],
             vec![card!("id", "2020-01-19")],
         );
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -650,7 +1096,7 @@ mod tests {
     fn test_patch_0100() {
         let left = TestCards::new(vec![], vec![card!("id", "2020-01-19")]);
         let right = TestCards::new(vec![], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -672,7 +1118,7 @@ mod tests {
         // when left date is before right date
         let left = TestCards::new(vec![], vec![card!("id", "2020-01-18")]);
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-19")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -691,7 +1137,7 @@ mod tests {
         // when left date is after right date
         let left = TestCards::new(vec![], vec![card!("id", "2020-01-20")]);
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-19")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -712,7 +1158,7 @@ mod tests {
     fn test_patch_0110() {
         let left = TestCards::new(vec![], vec![card!("id", "2020-01-18")]);
         let right = TestCards::new(vec![card!("id", "2020-01-19")], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(2, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -723,6 +1169,13 @@ mod tests {
             Some(&Hunk::Del(card!("id", "2020-01-19"))),
             patch.hunks.get(&HunkKind::PrevRight("id".into())),
         );
+        assert_eq!(
+            vec![Conflict {
+                id: "id".into(),
+                resolution: ConflictResolution::Delete
+            }],
+            patch.conflicts
+        );
     }
 
     #[test]
@@ -734,7 +1187,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
             vec![card!("id", "2020-01-20")],
         );
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -757,7 +1210,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
             vec![card!("id", "2020-01-19")],
         );
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(2, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -776,7 +1229,7 @@ mod tests {
             vec![card!("id", "2020-01-20")],
             vec![card!("id", "2020-01-19")],
         );
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -798,7 +1251,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
             vec![card!("id", "2020-01-19")],
         );
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -819,7 +1272,7 @@ mod tests {
     fn test_patch_1000() {
         let left = TestCards::new(vec![card!("id", "2020-01-19")], vec![]);
         let right = TestCards::new(vec![], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(1, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -833,7 +1286,7 @@ mod tests {
         // when left date is before right date
         let left = TestCards::new(vec![card!("id", "2020-01-18")], vec![]);
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-19")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -852,7 +1305,7 @@ mod tests {
         // when left date is equal to right date
         let left = TestCards::new(vec![card!("id", "2020-01-19")], vec![]);
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-19")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -871,7 +1324,7 @@ mod tests {
         // when left date is after to right date
         let left = TestCards::new(vec![card!("id", "2020-01-20")], vec![]);
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-19")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(2, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -888,7 +1341,7 @@ mod tests {
     fn test_patch_1010() {
         let left = TestCards::new(vec![card!("id", "2020-01-18")], vec![]);
         let right = TestCards::new(vec![card!("id", "2020-01-19")], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(2, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -908,7 +1361,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
             vec![card!("id", "2020-01-20")],
         );
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -933,7 +1386,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
         );
         let right = TestCards::new(vec![], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -955,7 +1408,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
         );
         let right = TestCards::new(vec![], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -981,7 +1434,7 @@ mod tests {
             vec![card!("id", "2020-01-20")],
         );
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-18")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -1004,7 +1457,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
         );
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-18")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(2, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -1023,7 +1476,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
         );
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-18")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -1045,7 +1498,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
         );
         let right = TestCards::new(vec![], vec![card!("id", "2020-01-20")]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -1069,7 +1522,7 @@ mod tests {
             vec![card!("id", "2020-01-19")],
         );
         let right = TestCards::new(vec![card!("id", "2020-01-20")], vec![]);
-        let patch = Patch::new(left, right);
+        let patch = Patch::new(&left, &right);
 
         assert_eq!(3, patch.hunks.len(), "{:?}", patch);
         assert_eq!(
@@ -1088,6 +1541,158 @@ mod tests {
 
     #[test]
     fn test_patch_1111() {
-        // TODO
+        // when cards only differ by their date
+        let left = TestCards::new(
+            vec![card!("id", "2020-01-18", "a")],
+            vec![card!("id", "2020-01-19", "a")],
+        );
+        let right = TestCards::new(
+            vec![card!("id", "2020-01-18", "a")],
+            vec![card!("id", "2020-01-20", "a")],
+        );
+        let patch = Patch::new(&left, &right);
+
+        assert!(patch.hunks.is_empty(), "{:?}", patch);
+
+        // when next right card is the most recent
+        let left = TestCards::new(
+            vec![card!("id", "2020-01-18", "a")],
+            vec![card!("id", "2020-01-18", "a")],
+        );
+        let right = TestCards::new(
+            vec![card!("id", "2020-01-18", "a")],
+            vec![card!("id", "2020-01-19", "b")],
+        );
+        let patch = Patch::new(&left, &right);
+
+        assert!(patch.conflicts.is_empty(), "{:?}", patch);
+        assert_eq!(3, patch.hunks.len(), "{:?}", patch);
+        assert_eq!(
+            Some(&Hunk::Set(card!("id", "2020-01-19", "b"))),
+            patch.hunks.get(&HunkKind::PrevLeft("id".into())),
+        );
+        assert_eq!(
+            Some(&Hunk::Set(card!("id", "2020-01-19", "b"))),
+            patch.hunks.get(&HunkKind::NextLeft("id".into())),
+        );
+        assert_eq!(
+            Some(&Hunk::Set(card!("id", "2020-01-19", "b"))),
+            patch.hunks.get(&HunkKind::PrevRight("id".into())),
+        );
+
+        // when both sides changed
+        let left = TestCards::new(
+            vec![card!("id", "2020-01-18", "a")],
+            vec![card!("id", "2020-01-20", "b")],
+        );
+        let right = TestCards::new(
+            vec![card!("id", "2020-01-18", "a")],
+            vec![card!("id", "2020-01-19", "c")],
+        );
+        let patch = Patch::new(&left, &right);
+
+        assert_eq!(
+            vec![Conflict {
+                id: "id".into(),
+                resolution: ConflictResolution::KeepLeft
+            }],
+            patch.conflicts
+        );
+    }
+
+    #[test]
+    fn test_apply_partial_failure() {
+        let right = TestCards::new(
+            vec![],
+            vec![card!("a", "2020-01-19"), card!("b", "2020-01-19")],
+        );
+        let patch = Patch::new(&TestCards::new(vec![], vec![]), &right);
+
+        let mut left = TestRepository::new("left", "b", 1);
+        let mut right = TestRepository::new("right", "", 1);
+        let journal_path = env::temp_dir().join("cardamom-sync-apply-journal");
+        let _ = fs::remove_file(&journal_path);
+        let mut journal = Journal::new(journal_path).unwrap();
+        let report = patch.apply(&mut left, &mut right, &mut journal).unwrap();
+
+        assert_eq!(1, report.left.added);
+        assert_eq!(
+            vec![Failure {
+                id: "b".into(),
+                error: "cannot write card b: 412".into()
+            }],
+            report.failures
+        );
+        assert!(left.cards.lock().unwrap().contains_key("a"));
+        assert!(!left.cards.lock().unwrap().contains_key("b"));
+
+        // the failed card stays out of the caches
+        assert!(left.cache.cards.contains_key("a"));
+        assert!(!left.cache.cards.contains_key("b"));
+        assert!(right.cache.cards.contains_key("a"));
+        assert!(!right.cache.cards.contains_key("b"));
+    }
+
+    #[test]
+    fn test_apply_concurrently() {
+        let left = TestCards::new(
+            vec![],
+            vec![
+                card!("a", "2020-01-19"),
+                card!("b", "2020-01-19"),
+                card!("c", "2020-01-19"),
+                card!("d", "2020-01-19"),
+                card!("e", "2020-01-19"),
+                card!("f", "2020-01-19"),
+                card!("g", "2020-01-19"),
+                card!("h", "2020-01-19"),
+            ],
+        );
+        let patch = Patch::new(&left, &TestCards::new(vec![], vec![]));
+
+        let mut left = TestRepository::new("concurrent-left", "", 1);
+        let mut right = TestRepository::new("concurrent-right", "c", 3);
+        let journal_path = env::temp_dir().join("cardamom-sync-apply-concurrent-journal");
+        let _ = fs::remove_file(&journal_path);
+        let mut journal = Journal::new(journal_path).unwrap();
+        let report = patch.apply(&mut left, &mut right, &mut journal).unwrap();
+
+        assert_eq!(7, report.right.added);
+        assert_eq!(1, report.failures.len());
+        assert_eq!("c", report.failures[0].id);
+        assert_eq!(7, right.cards.lock().unwrap().len());
+        assert_eq!(7, right.cache.cards.len());
+        assert_eq!(7, left.cache.cards.len());
+        assert!(!right.cache.cards.contains_key("c"));
+    }
+
+    #[test]
+    fn test_apply_rewritten_cards() {
+        let left = TestCards::new(
+            vec![card!("a", "2020-01-18", "a")],
+            vec![card!("a", "2020-01-19", "b"), card!("c", "2020-01-19", "c")],
+        );
+        let right = TestCards::new(
+            vec![card!("a", "2020-01-18", "a")],
+            vec![card!("a", "2020-01-18", "a")],
+        );
+        let patch = Patch::new(&left, &right);
+
+        let mut left = TestRepository::new("rewritten-left", "", 1);
+        let mut right = TestRepository::new("rewritten-right", "", 1).rewriting();
+        let journal_path = env::temp_dir().join("cardamom-sync-apply-rewritten-journal");
+        let _ = fs::remove_file(&journal_path);
+        let mut journal = Journal::new(journal_path).unwrap();
+        patch.apply(&mut left, &mut right, &mut journal).unwrap();
+
+        // both sides and caches hold the cards as rewritten by the
+        // right repository
+        for id in ["a", "c"] {
+            let content = id.replace('a', "b").to_uppercase();
+            assert_eq!(content, left.cards.lock().unwrap()[id].content);
+            assert_eq!(content, left.cache.cards[id].content);
+            assert_eq!(content, right.cards.lock().unwrap()[id].content);
+            assert_eq!(content, right.cache.cards[id].content);
+        }
     }
 }
@@ -0,0 +1,157 @@
+//! Tombstone module
+//!
+//! This module contains the tombstones of the local store: the cards
+//! deleted on purpose since the last sync. A deleted card is normally
+//! detected by the sync from the local cache, but a card missing from
+//! the cache (never synced yet, or cache reset) would then be seen as
+//! a card added on the server and brought back. Tombstones make sure
+//! such a deletion is applied to the server instead.
+
+use log::debug;
+use std::{collections::HashSet, fs, io, path::PathBuf};
+
+use crate::{
+    cache::{write_atomically, CachedCards},
+    card::{Card, Cards, CardsMap},
+    error::*,
+    report::SyncReport,
+};
+
+/// Represents the tombstones of a local store, indexed by card id.
+#[derive(Debug, Default)]
+pub struct Tombstones {
+    path: PathBuf,
+    pub cards: CardsMap,
+}
+
+impl Tombstones {
+    /// Reads the tombstones at the given path. Missing tombstones mean
+    /// that no card was deleted since the last sync.
+    pub fn new(path: PathBuf) -> Result<Self> {
+        let buff = match fs::read(&path) {
+            Ok(buff) => buff,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(CardamomError::ReadTombstonesError(path, e)),
+        };
+
+        let cards = if buff.is_empty() {
+            CardsMap::new()
+        } else {
+            serde_json::from_slice(&buff)
+                .map_err(|e| CardamomError::ParseTombstonesError(path.clone(), e))?
+        };
+
+        Ok(Self { path, cards })
+    }
+
+    /// Saves the tombstones atomically. The file is removed when there
+    /// is no tombstone left.
+    pub fn save(&self) -> Result<()> {
+        if self.cards.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    Err(CardamomError::WriteTombstonesError(self.path.clone(), e))
+                }
+                _ => Ok(()),
+            };
+        }
+
+        let buff = serde_json::to_vec(&self.cards)
+            .map_err(|e| CardamomError::ParseTombstonesError(self.path.clone(), e))?;
+        write_atomically(&self.path, &buff)
+            .map_err(|e| CardamomError::WriteTombstonesError(self.path.clone(), e))
+    }
+
+    /// Records the deletion of the given card.
+    pub fn insert(&mut self, card: Card) {
+        self.cards.insert(card.id.clone(), card);
+    }
+
+    /// Makes the tombstoned cards look deleted left since the last
+    /// sync (case 1011 of the patch), so the patch deletes them right
+    /// instead of adding them back left. Tombstones of cards present
+    /// left again (restored or imported since) are dropped.
+    pub fn bury<L, R>(&mut self, left: &mut L, right: &mut R)
+    where
+        L: Cards + AsMut<CachedCards>,
+        R: Cards + AsMut<CachedCards>,
+    {
+        self.cards.retain(|id, _| !left.next().contains_key(id));
+
+        for (id, card) in &self.cards {
+            debug!("burying card {:?}", id);
+            if let Some(right_card) = right.next().get(id).cloned() {
+                right
+                    .as_mut()
+                    .cards
+                    .entry(id.to_owned())
+                    .or_insert(right_card);
+            }
+            left.as_mut()
+                .cards
+                .entry(id.to_owned())
+                .or_insert_with(|| card.clone());
+        }
+    }
+
+    /// Drops the tombstones of the cards synchronized by the given
+    /// sync. Tombstones of failed cards are kept for the next sync.
+    pub fn clear(&mut self, report: &SyncReport) {
+        let failed_ids: HashSet<&str> = report
+            .failures
+            .iter()
+            .map(|failure| failure.id.as_str())
+            .collect();
+        self.cards.retain(|id, _| failed_ids.contains(id.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::{backend::Replica, journal::Journal, local::LocalCards, sync::Patch};
+
+    use super::*;
+
+    #[test]
+    fn bury_deleted_cards() {
+        let dir = env::temp_dir().join("cardamom-tombstone");
+        let _ = fs::remove_dir_all(&dir);
+        let (left_dir, right_dir) = (dir.join("left"), dir.join("right"));
+        fs::create_dir_all(&left_dir).unwrap();
+        fs::create_dir_all(&right_dir).unwrap();
+        fs::write(
+            right_dir.join("alice.vcf"),
+            "BEGIN:VCARD\r\nUID:alice\r\nFN:Alice\r\nEND:VCARD\r\n",
+        )
+        .unwrap();
+        let replica = |dir: &PathBuf| {
+            Replica::new(LocalCards::new(dir.to_owned()), dir.join(".cache")).unwrap()
+        };
+
+        // without tombstone, the card missing from the left cache is
+        // considered as added right
+        let mut left = replica(&left_dir);
+        let mut right = replica(&right_dir);
+        assert_eq!(3, Patch::new(&left, &right).iter().count());
+
+        let mut tombstones = Tombstones::new(dir.join(".tombstones")).unwrap();
+        tombstones.insert(right.next().get("alice").unwrap().clone());
+        tombstones.save().unwrap();
+        let mut tombstones = Tombstones::new(dir.join(".tombstones")).unwrap();
+        tombstones.bury(&mut left, &mut right);
+
+        let mut journal = Journal::new(dir.join(".journal")).unwrap();
+        let report = Patch::new(&left, &right)
+            .apply(&mut left, &mut right, &mut journal)
+            .unwrap();
+        assert_eq!(0, report.left.added);
+        assert_eq!(1, report.right.deleted);
+        assert!(!right_dir.join("alice.vcf").exists());
+
+        tombstones.clear(&report);
+        tombstones.save().unwrap();
+        assert!(!dir.join(".tombstones").exists());
+    }
+}
@@ -0,0 +1,387 @@
+//! vCard module
+//!
+//! This module contains a minimal vCard parser, as defined in the
+//! [RFC6350]. It only splits a card into properties, values are kept
+//! as they are written in the card until they are explicitly
+//! unescaped.
+//!
+//! [RFC6350]: https://www.rfc-editor.org/rfc/rfc6350
+
+/// Represents a content line of a vCard.
+///
+/// ```text
+/// item1.TEL;TYPE=work,voice;PREF=1:+33 6 00 00 00 00
+/// ^^^^^ ^^^ ^^^^^^^^^^^^^^^^^^^^^^^ ^^^^^^^^^^^^^^^^
+/// group name        params               value
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub group: Option<String>,
+    /// Represents the property name, in uppercase.
+    pub name: String,
+    /// Represents the property parameters. Names are in uppercase,
+    /// parameters without name (vCard 2.1) are considered as types.
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl Property {
+    /// Returns the values of the given parameter.
+    pub fn param(&self, name: &str) -> Vec<&str> {
+        self.params
+            .iter()
+            .filter(|(param_name, _)| param_name.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(','))
+            .map(|value| value.trim_matches('"'))
+            .collect()
+    }
+
+    /// Writes the property as a folded content line, ending with a
+    /// line break. The value is written as it is, it must already be
+    /// escaped.
+    pub fn to_line(&self) -> String {
+        let mut line = String::new();
+
+        if let Some(group) = self.group.as_ref() {
+            line.push_str(group);
+            line.push('.');
+        }
+        line.push_str(&self.name);
+        for (name, value) in &self.params {
+            line.push(';');
+            line.push_str(name);
+            line.push('=');
+            line.push_str(value);
+        }
+        line.push(':');
+        line.push_str(&self.value);
+
+        fold(&line)
+    }
+}
+
+/// Folds the given content line so that no line is longer than 75
+/// octets, as recommended by the RFC, then ends it with a line break.
+pub fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut line_len = 0;
+
+    for c in line.chars() {
+        // continuation lines start with a space, which counts
+        if line_len + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            line_len = 1;
+        }
+        folded.push(c);
+        line_len += c.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Unfolds the content lines of a card: a line starting with a space
+/// or a tab continues the previous one.
+pub fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in content.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix(&[' ', '\t'][..]), lines.last_mut()) {
+            (Some(folded_line), Some(last_line)) => last_line.push_str(folded_line),
+            _ if line.is_empty() => (),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    lines
+}
+
+/// Parses a single unfolded content line.
+pub fn parse_line(line: &str) -> Option<Property> {
+    let (head, value) = split_once_unquoted(line, ':')?;
+    let mut head = split_unquoted(head, ';').into_iter();
+    let name = head.next()?;
+    let (group, name) = match name.split_once('.') {
+        Some((group, name)) => (Some(group.to_owned()), name),
+        None => (None, name),
+    };
+    let params = head
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) => (name.to_uppercase(), value.to_owned()),
+            None => (String::from("TYPE"), param.to_owned()),
+        })
+        .collect();
+
+    Some(Property {
+        group,
+        name: name.to_uppercase(),
+        params,
+        value: value.to_owned(),
+    })
+}
+
+/// Parses all the properties of a card.
+pub fn parse(content: &str) -> Vec<Property> {
+    unfold(content)
+        .iter()
+        .filter_map(|line| parse_line(line))
+        .collect()
+}
+
+/// Splits a content holding several cards into the content of each
+/// card, as it is written. Lines outside of a card are dropped.
+pub fn split(content: &str) -> Vec<String> {
+    let mut cards = Vec::new();
+    let mut card = String::new();
+    let mut depth = 0;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed_line = line.trim_end_matches(&['\r', '\n'][..]);
+        if trimmed_line.eq_ignore_ascii_case("BEGIN:VCARD") {
+            depth += 1;
+        }
+        if depth > 0 {
+            card.push_str(line);
+        }
+        // vCard 2.1 agents are cards nested in a card
+        if trimmed_line.eq_ignore_ascii_case("END:VCARD") && depth > 0 {
+            depth -= 1;
+            if depth == 0 {
+                cards.push(std::mem::take(&mut card));
+            }
+        }
+    }
+
+    cards
+}
+
+/// Unescapes a text value: `\\n` becomes a line break, and escaped
+/// commas, semicolons and backslashes lose their backslash.
+pub fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some(c @ (',' | ';' | '\\'))) => {
+                unescaped.push(c);
+                chars.next();
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// Escapes a text value, the opposite of [`unescape`].
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => (),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Splits a structured value (like `N` or `ADR`) into its components,
+/// then unescapes them.
+pub fn components(value: &str) -> Vec<String> {
+    split_escaped(value, ';')
+}
+
+/// Splits a text list value (like `CATEGORIES`) into its items, then
+/// unescapes them.
+pub fn list(value: &str) -> Vec<String> {
+    split_escaped(value, ',')
+}
+
+/// Returns the unique identifier of a card, if any.
+pub fn uid(content: &str) -> Option<String> {
+    parse(content)
+        .into_iter()
+        .find(|prop| prop.name == "UID")
+        .map(|prop| prop.value.trim().to_owned())
+        .filter(|uid| !uid.is_empty())
+}
+
+/// Sets the unique identifier of a card, right after its beginning,
+/// in place of the previous one if any. The card is written again as
+/// folded content lines.
+pub fn set_uid(content: &str, uid: &str) -> String {
+    let uid_prop = Property {
+        group: None,
+        name: String::from("UID"),
+        params: Vec::new(),
+        value: escape(uid),
+    };
+    let mut card = String::new();
+    let mut uid_written = false;
+
+    for line in unfold(content) {
+        let name = parse_line(&line).map(|prop| prop.name);
+        if name.as_deref() == Some("UID") {
+            continue;
+        }
+        card.push_str(&fold(&line));
+        if !uid_written && name.as_deref() == Some("BEGIN") {
+            card.push_str(&uid_prop.to_line());
+            uid_written = true;
+        }
+    }
+
+    card
+}
+
+/// Splits the given value on the given separator, unless escaped,
+/// then unescapes the parts.
+fn split_escaped(value: &str, sep: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            c if c == sep && !escaped => {
+                parts.push(unescape(&value[start..i]));
+                start = i + c.len_utf8();
+            }
+            _ => escaped = false,
+        }
+    }
+
+    parts.push(unescape(&value[start..]));
+    parts
+}
+
+/// Splits the given string by the given separator, except when the
+/// separator is between double quotes.
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == sep && !quoted {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+
+    parts.push(&s[start..]);
+    parts
+}
+
+fn split_once_unquoted(s: &str, sep: char) -> Option<(&str, &str)> {
+    let mut quoted = false;
+
+    for (i, c) in s.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == sep && !quoted {
+            return Some((&s[..i], &s[i + c.len_utf8()..]));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_folded_card() {
+        let props = parse(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nUID:urn:uuid:22d3a5d9\r\nNOTE:John Doe has \r\n a long history\r\nitem1.EMAIL;type=INTERNET;TYPE=\"work,pref\":john@example.org\r\nTEL;CELL:+1 781 555 1212\r\nEND:VCARD\r\n",
+        );
+
+        assert_eq!(7, props.len());
+        assert_eq!("urn:uuid:22d3a5d9", props[2].value);
+        assert_eq!("John Doe has a long history", props[3].value);
+        assert_eq!(Some("item1".into()), props[4].group);
+        assert_eq!("EMAIL", props[4].name);
+        assert_eq!(vec!["INTERNET", "work", "pref"], props[4].param("type"));
+        assert_eq!(vec!["CELL"], props[5].param("TYPE"));
+    }
+
+    #[test]
+    fn find_uid() {
+        assert_eq!(
+            Some("urn:uuid:22d3a5d9".into()),
+            uid("BEGIN:VCARD\nuid:urn:uuid:22d3a5d9 \nEND:VCARD")
+        );
+        assert_eq!(None, uid("BEGIN:VCARD\nUID:\nEND:VCARD"));
+        assert_eq!(None, uid("BEGIN:VCARD\nEND:VCARD"));
+    }
+
+    #[test]
+    fn replace_uid() {
+        assert_eq!(
+            "BEGIN:VCARD\r\nUID:new\r\nVERSION:4.0\r\nFN:Alice\r\nEND:VCARD\r\n",
+            set_uid(
+                "BEGIN:VCARD\nVERSION:4.0\nUID:old\nFN:Alice\nEND:VCARD\n",
+                "new"
+            )
+        );
+        assert_eq!(
+            "BEGIN:VCARD\r\nUID:a\\,b\r\nEND:VCARD\r\n",
+            set_uid("BEGIN:VCARD\r\nEND:VCARD", "a,b")
+        );
+    }
+
+    #[test]
+    fn split_cards() {
+        let cards = split(
+            "BEGIN:VCARD\r\nUID:a\r\nEND:VCARD\r\n\r\nbegin:vcard\nUID:b\nAGENT:BEGIN:VCARD\nBEGIN:VCARD\nFN:c\nEND:VCARD\nend:vcard",
+        );
+
+        assert_eq!(2, cards.len());
+        assert_eq!("BEGIN:VCARD\r\nUID:a\r\nEND:VCARD\r\n", cards[0]);
+        assert_eq!(Some("b".into()), uid(&cards[1]));
+        assert!(cards[1].ends_with("END:VCARD\nend:vcard"));
+    }
+
+    #[test]
+    fn escape_and_unescape_values() {
+        assert_eq!("a, b; c\\d\ne", unescape("a\\, b\\; c\\\\d\\ne"));
+        assert_eq!(
+            vec!["Doe", "John", "", "Dr.;", ""],
+            components("Doe;John;;Dr.\\;;")
+        );
+        assert_eq!(vec!["friends", "a,b"], list("friends,a\\,b"));
+        assert_eq!("a\\, b\\; c\\\\d\\ne", escape("a, b; c\\d\r\ne"));
+    }
+
+    #[test]
+    fn write_folded_line() {
+        let prop = Property {
+            group: Some("item1".into()),
+            name: "NOTE".into(),
+            params: vec![("LANGUAGE".into(), "fr".into())],
+            value: format!("{}é", "a".repeat(100)),
+        };
+        let line = prop.to_line();
+
+        assert!(line.lines().all(|line| line.len() <= 75));
+        assert!(line.starts_with("item1.NOTE;LANGUAGE=fr:aaa"));
+        assert_eq!(vec![prop], parse(&line));
+    }
+}
@@ -0,0 +1,338 @@
+//! vCard version module
+//!
+//! This module contains the conversion of cards between the vCard 3.0
+//! ([RFC2426]) and 4.0 ([RFC6350]) versions. Only the differences
+//! which matter in practice are converted: preferred and obsolete
+//! types, inline binary values, the kind of the card (and its members)
+//! and the anniversary. Other properties are kept as they are.
+//!
+//! [RFC2426]: https://www.rfc-editor.org/rfc/rfc2426
+//! [RFC6350]: https://www.rfc-editor.org/rfc/rfc6350
+
+use std::{fmt, result, str::FromStr};
+
+use crate::{
+    error::*,
+    vcard::{self, Property},
+};
+
+/// Represents a vCard version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcardVersion {
+    V3,
+    V4,
+}
+
+impl VcardVersion {
+    /// Returns the version of the given card, if it is a known one.
+    pub fn of(content: &str) -> Option<Self> {
+        vcard::parse(content)
+            .into_iter()
+            .find(|prop| prop.name == "VERSION")
+            .and_then(|prop| prop.value.trim().parse().ok())
+    }
+}
+
+impl FromStr for VcardVersion {
+    type Err = CardamomError;
+
+    fn from_str(version: &str) -> result::Result<Self, Self::Err> {
+        match version {
+            "3" | "3.0" => Ok(Self::V3),
+            "4" | "4.0" => Ok(Self::V4),
+            _ => Err(CardamomError::UnknownVcardVersionError(version.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for VcardVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V3 => write!(f, "3.0"),
+            Self::V4 => write!(f, "4.0"),
+        }
+    }
+}
+
+/// Represents the properties holding binary values, along with the
+/// media type of their inline values when the vCard 3.0 type is not
+/// a full media type.
+const BINARY_PROPS: [(&str, &str); 4] = [
+    ("PHOTO", "image"),
+    ("LOGO", "image"),
+    ("SOUND", "audio"),
+    ("KEY", "application"),
+];
+
+/// Converts the given card to the given version. A card already at
+/// this version is returned as it is, other cards are written again
+/// property by property.
+pub fn convert(content: &str, version: VcardVersion) -> String {
+    if VcardVersion::of(content) == Some(version) {
+        return content.to_owned();
+    }
+
+    let mut props: Vec<Property> = vcard::parse(content)
+        .into_iter()
+        .map(|prop| match version {
+            VcardVersion::V3 => to_v3(prop),
+            VcardVersion::V4 => to_v4(prop),
+        })
+        .collect();
+
+    // the version is the first property after BEGIN, and vCard 3.0
+    // requires a structured name
+    if !props.iter().any(|prop| prop.name == "VERSION") {
+        props.insert(1.min(props.len()), property("VERSION", ""));
+    }
+    if version == VcardVersion::V3 && !props.iter().any(|prop| prop.name == "N") {
+        let pos = props.iter().position(|prop| prop.name == "END");
+        props.insert(pos.unwrap_or(props.len()), property("N", ";;;;"));
+    }
+    for prop in props.iter_mut().filter(|prop| prop.name == "VERSION") {
+        prop.value = version.to_string();
+    }
+
+    props.iter().map(Property::to_line).collect()
+}
+
+/// Converts a vCard 3.0 property to vCard 4.0.
+fn to_v4(mut prop: Property) -> Property {
+    prop.name = match prop.name.as_str() {
+        "X-ADDRESSBOOKSERVER-KIND" => {
+            prop.value = prop.value.to_lowercase();
+            String::from("KIND")
+        }
+        "X-ADDRESSBOOKSERVER-MEMBER" => String::from("MEMBER"),
+        "X-ANNIVERSARY" | "X-EVOLUTION-ANNIVERSARY" => String::from("ANNIVERSARY"),
+        _ => prop.name,
+    };
+
+    // inline binary values become data URIs, and URIs no longer need
+    // a value type
+    if let Some((_, media)) = BINARY_PROPS.iter().find(|(name, _)| *name == prop.name) {
+        let encoding = prop.param("ENCODING").first().map(|e| e.to_lowercase());
+        if let Some("b" | "base64") = encoding.as_deref() {
+            let media_type = match prop.param("TYPE").first() {
+                Some(t) if t.contains('/') => t.to_lowercase(),
+                Some(t) => format!("{}/{}", media, t.to_lowercase()),
+                None => String::from("application/octet-stream"),
+            };
+            prop.value = format!("data:{};base64,{}", media_type, prop.value);
+            remove_params(&mut prop, &["ENCODING", "TYPE"]);
+        } else if prop
+            .param("VALUE")
+            .iter()
+            .any(|v| v.eq_ignore_ascii_case("uri"))
+        {
+            remove_params(&mut prop, &["VALUE"]);
+        }
+        return prop;
+    }
+
+    let mut pref = false;
+    let types = types(&prop)
+        .into_iter()
+        .filter_map(|t| match t.to_lowercase().as_str() {
+            "pref" => {
+                pref = true;
+                None
+            }
+            // the Internet email type is the only one left, and the
+            // address types were dropped
+            "internet" | "dom" | "intl" | "postal" | "parcel" => None,
+            "msg" if prop.name == "TEL" => Some(String::from("text")),
+            _ => Some(t),
+        })
+        .collect();
+    set_types(&mut prop, types);
+    if pref && prop.param("PREF").is_empty() {
+        prop.params.push((String::from("PREF"), String::from("1")));
+    }
+
+    prop
+}
+
+/// Converts a vCard 4.0 property to vCard 3.0.
+fn to_v3(mut prop: Property) -> Property {
+    prop.name = match prop.name.as_str() {
+        "KIND" => String::from("X-ADDRESSBOOKSERVER-KIND"),
+        "MEMBER" => String::from("X-ADDRESSBOOKSERVER-MEMBER"),
+        "ANNIVERSARY" => String::from("X-ANNIVERSARY"),
+        _ => prop.name,
+    };
+
+    // data URIs become inline binary values, other URIs need a value
+    // type
+    if BINARY_PROPS.iter().any(|(name, _)| *name == prop.name) {
+        let data_uri = parse_data_uri(&prop.value)
+            .map(|(media_type, data)| (media_type.to_owned(), data.to_owned()));
+        match data_uri {
+            Some((media_type, data)) => {
+                let subtype = media_type.rsplit('/').next().unwrap_or_default();
+                prop.value = data;
+                remove_params(&mut prop, &["MEDIATYPE", "VALUE"]);
+                prop.params
+                    .push((String::from("ENCODING"), String::from("b")));
+                if !subtype.is_empty() {
+                    prop.params
+                        .push((String::from("TYPE"), subtype.to_uppercase()));
+                }
+            }
+            None if prop.param("VALUE").is_empty() => prop
+                .params
+                .push((String::from("VALUE"), String::from("uri"))),
+            None => (),
+        }
+        return prop;
+    }
+
+    // phone numbers written as URIs become text
+    if prop.name == "TEL"
+        && prop
+            .param("VALUE")
+            .iter()
+            .any(|v| v.eq_ignore_ascii_case("uri"))
+    {
+        remove_params(&mut prop, &["VALUE"]);
+        if let Some(number) = prop.value.strip_prefix("tel:") {
+            prop.value = number.to_owned();
+        }
+    }
+
+    let pref = prop.param("PREF").first() == Some(&"1");
+    remove_params(&mut prop, &["PREF"]);
+    let mut types: Vec<String> = types(&prop)
+        .into_iter()
+        .map(|t| match t.to_lowercase().as_str() {
+            "text" if prop.name == "TEL" => String::from("msg"),
+            _ => t,
+        })
+        .collect();
+    if pref {
+        types.push(String::from("pref"));
+    }
+    set_types(&mut prop, types);
+
+    prop
+}
+
+/// Builds a property without group nor parameter.
+fn property(name: &str, value: &str) -> Property {
+    Property {
+        group: None,
+        name: name.to_owned(),
+        params: Vec::new(),
+        value: value.to_owned(),
+    }
+}
+
+/// Returns the types of the given property.
+fn types(prop: &Property) -> Vec<String> {
+    prop.param("TYPE")
+        .into_iter()
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Replaces the types of the given property by the given ones.
+fn set_types(prop: &mut Property, types: Vec<String>) {
+    remove_params(prop, &["TYPE"]);
+    if !types.is_empty() {
+        prop.params.push((String::from("TYPE"), types.join(",")));
+    }
+}
+
+fn remove_params(prop: &mut Property, names: &[&str]) {
+    prop.params
+        .retain(|(name, _)| !names.iter().any(|n| name.eq_ignore_ascii_case(n)));
+}
+
+/// Parses a data URI (`data:image/jpeg;base64,...`) into its media
+/// type and its base64 data. URIs of data which is not base64
+/// encoded are not supported.
+fn parse_data_uri(uri: &str) -> Option<(&str, &str)> {
+    let (head, data) = uri.strip_prefix("data:")?.split_once(',')?;
+    let media_type = head.strip_suffix(";base64")?;
+    Some((media_type, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V3: &str = concat!(
+        "BEGIN:VCARD\r\n",
+        "VERSION:3.0\r\n",
+        "FN:Alice\r\n",
+        "N:;Alice;;;\r\n",
+        "EMAIL;TYPE=INTERNET,WORK,pref:alice@corp.com\r\n",
+        "TEL;TYPE=cell,msg:+33 6 00 00 00 00\r\n",
+        "ADR;TYPE=home,postal:;;1 Main St;Springfield;;;\r\n",
+        "PHOTO;ENCODING=b;TYPE=JPEG:/9j/4AAQ\r\n",
+        "LOGO;VALUE=uri:https://corp.com/logo.png\r\n",
+        "X-ADDRESSBOOKSERVER-KIND:group\r\n",
+        "X-ADDRESSBOOKSERVER-MEMBER:urn:uuid:bob\r\n",
+        "X-ANNIVERSARY:2010-06-01\r\n",
+        "END:VCARD\r\n",
+    );
+
+    const V4: &str = concat!(
+        "BEGIN:VCARD\r\n",
+        "VERSION:4.0\r\n",
+        "FN:Alice\r\n",
+        "N:;Alice;;;\r\n",
+        "EMAIL;TYPE=WORK;PREF=1:alice@corp.com\r\n",
+        "TEL;TYPE=cell,text:+33 6 00 00 00 00\r\n",
+        "ADR;TYPE=home:;;1 Main St;Springfield;;;\r\n",
+        "PHOTO:data:image/jpeg;base64,/9j/4AAQ\r\n",
+        "LOGO:https://corp.com/logo.png\r\n",
+        "KIND:group\r\n",
+        "MEMBER:urn:uuid:bob\r\n",
+        "ANNIVERSARY:2010-06-01\r\n",
+        "END:VCARD\r\n",
+    );
+
+    #[test]
+    fn convert_v3_to_v4() {
+        assert_eq!(Some(VcardVersion::V3), VcardVersion::of(V3));
+        assert_eq!(V4, convert(V3, VcardVersion::V4));
+        assert_eq!(V4, convert(V4, VcardVersion::V4));
+    }
+
+    #[test]
+    fn convert_v4_to_v3() {
+        let v3 = convert(V4, VcardVersion::V3);
+        assert_eq!(
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "FN:Alice\r\n",
+                "N:;Alice;;;\r\n",
+                "EMAIL;TYPE=WORK,pref:alice@corp.com\r\n",
+                "TEL;TYPE=cell,msg:+33 6 00 00 00 00\r\n",
+                "ADR;TYPE=home:;;1 Main St;Springfield;;;\r\n",
+                "PHOTO;ENCODING=b;TYPE=JPEG:/9j/4AAQ\r\n",
+                "LOGO;VALUE=uri:https://corp.com/logo.png\r\n",
+                "X-ADDRESSBOOKSERVER-KIND:group\r\n",
+                "X-ADDRESSBOOKSERVER-MEMBER:urn:uuid:bob\r\n",
+                "X-ANNIVERSARY:2010-06-01\r\n",
+                "END:VCARD\r\n",
+            ),
+            v3
+        );
+        assert_eq!(V4, convert(&v3, VcardVersion::V4));
+
+        // vCard 3.0 requires a structured name and phone numbers as
+        // text
+        let v3 = convert(
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Bob\r\nTEL;VALUE=uri:tel:+1-555\r\nEND:VCARD\r\n",
+            VcardVersion::V3,
+        );
+        assert_eq!(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:Bob\r\nTEL:+1-555\r\nN:;;;;\r\nEND:VCARD\r\n",
+            v3
+        );
+    }
+}
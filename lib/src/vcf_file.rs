@@ -0,0 +1,227 @@
+use chrono::{DateTime, Local};
+use log::warn;
+use std::{collections::HashMap, fs, path::PathBuf, sync::Mutex};
+
+use crate::{
+    backend::Backend,
+    cache::{write_atomically, CachedCards},
+    card::{Card, CardsMap},
+    error::*,
+    vcard,
+};
+
+/// Represents the single file backend: one vCard file holding all the
+/// cards, as exported by most contact managers and phones.
+///
+/// Cards are identified by their UID, or by the hash of their content
+/// when they have none. Their version is the hash of their content,
+/// and the whole file is rewritten atomically on every change.
+#[derive(Debug, Default)]
+pub struct VcfFile {
+    path: PathBuf,
+    // prevents concurrent read-modify-write cycles from losing cards
+    write_lock: Mutex<()>,
+}
+
+impl VcfFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            write_lock: Mutex::default(),
+        }
+    }
+
+    /// Reads the cards of the file, in the order they are written. A
+    /// missing file is considered as empty.
+    fn read(&self) -> Result<Vec<Card>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let date = self.date()?;
+        let content = fs::read_to_string(&self.path)
+            .map_err(|e| CardamomError::ReadVcfError(self.path.clone(), e))?;
+        let cards = vcard::split(&content)
+            .into_iter()
+            .map(|mut content| {
+                ensure_line_break(&mut content);
+                let mut card = Card {
+                    id: String::new(),
+                    date,
+                    content,
+                    etag: None,
+                    href: None,
+                };
+                card.id = card.uid().unwrap_or_else(|| card.hash());
+                card.etag = Some(card.hash());
+                card.href = Some(card.id.clone());
+                card
+            })
+            .collect();
+
+        Ok(cards)
+    }
+
+    fn write(&self, cards: &[Card]) -> Result<()> {
+        let content: String = cards.iter().map(|card| card.content.as_str()).collect();
+        write_atomically(&self.path, content.as_bytes())
+            .map_err(|e| CardamomError::WriteVcfError(self.path.clone(), e))
+    }
+
+    fn date(&self) -> Result<DateTime<Local>> {
+        Ok(fs::metadata(&self.path)
+            .map_err(|e| CardamomError::GetVcfMetadataError(self.path.clone(), e))?
+            .modified()
+            .map_err(|e| CardamomError::GetVcfModifiedError(self.path.clone(), e))?
+            .into())
+    }
+
+    /// Returns the position of the card at the given location.
+    fn position(cards: &[Card], href: &str) -> Option<usize> {
+        cards
+            .iter()
+            .position(|card| card.href.as_deref() == Some(href))
+    }
+}
+
+impl Backend for VcfFile {
+    fn list(&self) -> Result<CardsMap> {
+        let mut cards = HashMap::default();
+
+        for card in self.read()? {
+            if let Some(dup) = cards.insert(card.id.clone(), card) {
+                warn!("duplicate card {:?} in {:?}", dup.id, self.path);
+            }
+        }
+
+        Ok(cards)
+    }
+
+    fn list_all(&self) -> Result<Vec<Card>> {
+        self.read()
+    }
+
+    fn get(&self, href: &str) -> Result<Card> {
+        self.read()?
+            .into_iter()
+            .find(|card| card.href.as_deref() == Some(href))
+            .ok_or_else(|| CardamomError::ReadCardError(href.to_owned(), "not found".into()))
+    }
+
+    fn put(&self, card: &mut Card) -> Result<()> {
+        let _guard = self.write_lock.lock();
+        let mut cards = self.read()?;
+        let href = card.href.to_owned().unwrap_or_else(|| card.id.clone());
+        let pos = Self::position(&cards, &href);
+
+        if let (Some(pos), Some(etag)) = (pos, &card.etag) {
+            if cards[pos].etag.as_ref() != Some(etag) {
+                return Err(CardamomError::CardChangedError(card.id.clone()));
+            }
+        }
+
+        ensure_line_break(&mut card.content);
+        card.etag = Some(card.hash());
+        card.href = Some(href);
+
+        match pos {
+            Some(pos) => cards[pos] = card.clone(),
+            None => cards.push(card.clone()),
+        }
+        self.write(&cards)?;
+        card.date = self.date()?;
+        Ok(())
+    }
+
+    fn delete(&self, card: &Card) -> Result<()> {
+        let _guard = self.write_lock.lock();
+        let mut cards = self.read()?;
+        let href = card.href.to_owned().unwrap_or_else(|| card.id.clone());
+
+        if let Some(pos) = Self::position(&cards, &href) {
+            cards.remove(pos);
+            self.write(&cards)?;
+        }
+
+        Ok(())
+    }
+
+    fn prepare_cache(&self, cache: &mut CachedCards) -> Result<()> {
+        // cached cards of another file cannot be compared with the
+        // current ones
+        let path = self.path.to_string_lossy().to_string();
+        match cache.addressbook_url.as_ref() {
+            Some(cached_path) if cached_path != &path => {
+                warn!(
+                    "vcf file changed from {:?} to {:?}, resetting cache",
+                    cached_path, path
+                );
+                cache.cards.clear();
+            }
+            _ => (),
+        }
+        cache.addressbook_url = Some(path);
+        Ok(())
+    }
+}
+
+/// Adds a line break at the end of the given card content if it has
+/// none, since cards are concatenated in the file.
+fn ensure_line_break(content: &mut String) {
+    if !content.ends_with('\n') {
+        content.push_str("\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn put_and_delete_cards() {
+        let path = env::temp_dir().join("cardamom-vcf-file.vcf");
+        fs::write(
+            &path,
+            "BEGIN:VCARD\r\nUID:a\r\nFN:A\r\nEND:VCARD\r\nBEGIN:VCARD\r\nFN:No UID\r\nEND:VCARD",
+        )
+        .unwrap();
+        let file = VcfFile::new(path.clone());
+
+        let cards = file.list().unwrap();
+        assert_eq!(2, cards.len());
+        let a = cards.get("a").unwrap().to_owned();
+        assert_eq!(Some("a".into()), a.href);
+
+        // a card listed before being changed cannot be replaced
+        let mut stale_a = a.clone();
+        stale_a.etag = Some("stale".into());
+        assert!(file.put(&mut stale_a).is_err());
+
+        let mut new_a = a.clone();
+        new_a.content = "BEGIN:VCARD\r\nUID:a\r\nFN:A2\r\nEND:VCARD".into();
+        file.put(&mut new_a).unwrap();
+        assert_ne!(a.etag, new_a.etag);
+
+        let mut b = Card {
+            id: "b".into(),
+            content: "BEGIN:VCARD\nUID:b\nEND:VCARD\n".into(),
+            ..a
+        };
+        b.etag = None;
+        b.href = None;
+        file.put(&mut b).unwrap();
+
+        let cards = file.list().unwrap();
+        assert_eq!(3, cards.len());
+        assert_eq!(new_a.content, cards.get("a").unwrap().content);
+        assert_eq!(new_a.etag, cards.get("a").unwrap().etag);
+        assert_eq!(b.etag, cards.get("b").unwrap().etag);
+
+        file.delete(&new_a).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("BEGIN:VCARD\r\nFN:No UID\r\nEND:VCARD\r\n"));
+        assert!(content.ends_with("BEGIN:VCARD\nUID:b\nEND:VCARD\n"));
+    }
+}